@@ -0,0 +1,60 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use crate::util::now_secs;
+
+#[derive(Debug, Serialize)]
+struct AuditEntry<'a> {
+    at: u64,
+    operator_name: &'a str,
+    action: &'a str,
+    detail: &'a str,
+}
+
+/// Append-only `audit.log` of operator actions that move money or paper
+/// outside a normal sale: drawer openings, reprints. One JSON object per line.
+pub struct AuditLog {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl AuditLog {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Failing to audit must not stop the booth, so errors are only logged.
+    pub fn record(&self, operator_name: &str, action: &str, detail: &str) {
+        println!("[Rust] Audit: {} by {} ({})", action, operator_name, detail);
+
+        let entry = AuditEntry {
+            at: now_secs(),
+            operator_name,
+            action,
+            detail,
+        };
+
+        let _guard = self.lock.lock().unwrap();
+        let written = serde_json::to_string(&entry)
+            .map_err(|e| e.to_string())
+            .and_then(|line| {
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)
+                    .and_then(|mut f| writeln!(f, "{}", line))
+                    .map_err(|e| e.to_string())
+            });
+
+        if let Err(e) = written {
+            println!("[Rust] Failed to write audit log: {}", e);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::fs;
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use rsa::pkcs8::DecodePublicKey;
+use rsa::{Pkcs1v15Sign, RsaPrivateKey, RsaPublicKey};
+use sha1::{Digest, Sha1};
+
+use super::BillingConfig;
+use crate::fiscal::vfd::{escape_xml, sign, xml_field};
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// GePG transaction status codes.
+pub const SUCCESS: &str = "7101";
+pub const FAILURE: &str = "7201";
+
+/// Everything in a `<gepgBillSubReq>` for one parking bill.
+#[derive(Debug, Clone)]
+pub struct BillPayload {
+    pub bill_id: String,
+    pub amount: f64,
+    pub description: String,
+    pub payer_name: String,
+    pub payer_phone: String,
+    pub generated_at: String,
+    pub expires_at: String,
+    pub generated_by: String,
+}
+
+pub fn bill_request_xml(config: &BillingConfig, b: &BillPayload) -> String {
+    format!(
+        "<gepgBillSubReq><BillHdr><SpCode>{}</SpCode><RtrRespFlg>true</RtrRespFlg></BillHdr>\
+         <BillTrxInf><BillId>{}</BillId><SubSpCode>{}</SubSpCode><SpSysId>{}</SpSysId>\
+         <BillAmt>{:.2}</BillAmt><MiscAmt>0</MiscAmt><BillExprDt>{}</BillExprDt>\
+         <PyrId>{}</PyrId><PyrName>{}</PyrName><BillDesc>{}</BillDesc><BillGenDt>{}</BillGenDt>\
+         <BillGenBy>{}</BillGenBy><BillApprBy>{}</BillApprBy><PyrCellNum>{}</PyrCellNum><PyrEmail></PyrEmail>\
+         <Ccy>TZS</Ccy><BillEqvAmt>{:.2}</BillEqvAmt><RemFlag>true</RemFlag><BillPayOpt>{}</BillPayOpt>\
+         <BillItems><BillItem><BillItemRef>{}</BillItemRef><UseItemRefOnPay>N</UseItemRefOnPay>\
+         <BillItemAmt>{:.2}</BillItemAmt><BillItemEqvAmt>{:.2}</BillItemEqvAmt><BillItemMiscAmt>0</BillItemMiscAmt>\
+         <GfsCode>{}</GfsCode></BillItem></BillItems></BillTrxInf></gepgBillSubReq>",
+        escape_xml(&config.sp_code),
+        escape_xml(&b.bill_id),
+        escape_xml(&config.sub_sp_code),
+        escape_xml(&config.sp_sys_id),
+        b.amount,
+        b.expires_at,
+        escape_xml(&b.bill_id),
+        escape_xml(&b.payer_name),
+        escape_xml(&b.description),
+        b.generated_at,
+        escape_xml(&b.generated_by),
+        escape_xml(&config.approved_by),
+        escape_xml(&b.payer_phone),
+        b.amount,
+        config.pay_option,
+        escape_xml(&b.bill_id),
+        b.amount,
+        b.amount,
+        escape_xml(&config.gfs_code),
+    )
+}
+
+/// A signed `<Gepg>` envelope around one message element.
+pub fn envelope(content: &str, signature: &str) -> String {
+    format!("<Gepg>{}<gepgSignature>{}</gepgSignature></Gepg>", content, signature)
+}
+
+pub fn signed_envelope(key: &RsaPrivateKey, content: &str) -> Result<String, String> {
+    Ok(envelope(content, &sign(key, content)?))
+}
+
+/// The acknowledgement GePG expects back for a message it sent us.
+pub fn ack_xml(element: &str, code: &str) -> String {
+    format!("<{0}Ack><TrxStsCode>{1}</TrxStsCode></{0}Ack>", element, code)
+}
+
+/// Name of the message element inside a `<Gepg>` envelope.
+pub fn message_element(xml: &str) -> Option<&str> {
+    let start = xml.find("<Gepg>")? + "<Gepg>".len();
+    let rest = xml[start..].trim_start().strip_prefix('<')?;
+    let end = rest.find(|c: char| c == '>' || c.is_whitespace())?;
+    Some(&rest[..end])
+}
+
+/// The signed part of an envelope: the message element, tags included.
+pub fn signed_content<'a>(xml: &'a str, element: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", element))?;
+    let close = format!("</{}>", element);
+    let end = xml[start..].find(&close)? + start + close.len();
+    Some(&xml[start..end])
+}
+
+pub fn load_public_key(path: &str) -> Result<RsaPublicKey, String> {
+    let pem = fs::read_to_string(path).map_err(|e| format!("Failed to read GePG public key {}: {}", path, e))?;
+    RsaPublicKey::from_public_key_pem(&pem).map_err(|e| format!("Invalid GePG public key {}: {}", path, e))
+}
+
+/// Checks GePG's SHA1withRSA signature on a message it sent us.
+pub fn verify(key: &RsaPublicKey, xml: &str) -> Result<(), String> {
+    let element = message_element(xml).ok_or("GePG message has no content")?;
+    let content = signed_content(xml, element).ok_or("GePG message is malformed")?;
+    let signature = xml_field(xml, "gepgSignature")
+        .and_then(|s| BASE64.decode(s).ok())
+        .ok_or("GePG message is not signed")?;
+
+    key.verify(Pkcs1v15Sign::new::<Sha1>(), &Sha1::digest(content.as_bytes()), &signature)
+        .map_err(|_| format!("GePG signature on {} does not match", element))
+}
+
+/// Talks to the GePG bill submission API at one base URL.
+pub struct GepgClient {
+    base_url: String,
+    agent: ureq::Agent,
+}
+
+impl GepgClient {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            agent: ureq::AgentBuilder::new().timeout(HTTP_TIMEOUT).build(),
+        }
+    }
+
+    /// Posts a signed bill. GePG only acknowledges receipt here; the control
+    /// number follows on the callback. Returns the `TrxStsCode`.
+    pub fn submit_bill(&self, config: &BillingConfig, document: &str) -> Result<String, String> {
+        let response = self
+            .agent
+            .post(&format!("{}/api/bill/sigqrequest", self.base_url))
+            .set("Content-Type", "application/xml")
+            .set("Gepg-Com", "default.sp.in")
+            .set("Gepg-Code", &config.sp_code)
+            .send_string(document);
+
+        let body = match response {
+            Ok(r) => r.into_string(),
+            Err(ureq::Error::Status(_, r)) => r.into_string(),
+            Err(e) => return Err(format!("GePG bill submission failed: {}", e)),
+        }
+        .map_err(|e| format!("Failed to read GePG response: {}", e))?;
+
+        xml_field(&body, "TrxStsCode")
+            .ok_or_else(|| format!("Unexpected GePG response: {}", body.chars().take(200).collect::<String>()))
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
+
+use chrono::Local;
+use tiny_http::{Header, Response, Server};
+
+use super::gepg::{self, envelope};
+use crate::fiscal::vfd::xml_field;
+
+/// Stand-in for GePG on localhost, for training and testing without an SP
+/// registration. Acknowledges every bill, then issues a control number on
+/// the callback the way GePG does. `POST /mock/pay` with a `PayCntrNum` and
+/// `PaidAmt` sends a payment notification for that control number.
+///
+/// Returns the base URL to point the client at.
+pub fn spawn_mock_gepg(addr: &str, callback_url: &str) -> Result<String, String> {
+    let server = Server::http(addr).map_err(|e| format!("Failed to start mock GePG on {}: {}", addr, e))?;
+    let url = format!("http://{}", server.server_addr());
+    let callback_url = callback_url.to_string();
+    println!("[Rust] Mock GePG listening on {}", url);
+
+    thread::spawn(move || {
+        let mut issued = 0u64;
+        // Control number -> bill id
+        let mut bills: HashMap<String, String> = HashMap::new();
+
+        for mut request in server.incoming_requests() {
+            let mut body = String::new();
+            let _ = request.as_reader().read_to_string(&mut body);
+
+            let response = match request.url() {
+                "/api/bill/sigqrequest" => {
+                    let code = match xml_field(&body, "BillId") {
+                        Some(bill_id) if xml_field(&body, "gepgSignature").is_some_and(|s| !s.is_empty()) => {
+                            issued += 1;
+                            let control_number = format!("99{:010}", 1_900_000_000 + issued);
+                            bills.insert(control_number.clone(), bill_id.clone());
+                            callback(
+                                &callback_url,
+                                format!(
+                                    "<gepgBillSubResp><BillTrxInf><BillId>{}</BillId><TrxSts>GS</TrxSts>\
+                                     <PayCntrNum>{}</PayCntrNum><TrxStsCode>{}</TrxStsCode></BillTrxInf></gepgBillSubResp>",
+                                    bill_id,
+                                    control_number,
+                                    gepg::SUCCESS
+                                ),
+                            );
+                            gepg::SUCCESS
+                        }
+                        _ => gepg::FAILURE,
+                    };
+                    xml_response(&gepg::ack_xml("gepgBillSubReq", code))
+                }
+                "/mock/pay" => {
+                    let control_number = xml_field(&body, "PayCntrNum").unwrap_or_default();
+                    match bills.get(&control_number) {
+                        Some(bill_id) => {
+                            issued += 1;
+                            callback(
+                                &callback_url,
+                                format!(
+                                    "<gepgPmtSpInfo><PymtTrxInf><TrxId>MOCK{}</TrxId><SpCode>SP000</SpCode>\
+                                     <PayRefId>MOCKREF{}</PayRefId><BillId>{}</BillId><PayCtrNum>{}</PayCtrNum>\
+                                     <PaidAmt>{}</PaidAmt><CCy>TZS</CCy><TrxDtTm>{}</TrxDtTm>\
+                                     <UsdPayChnl>MOBILE</UsdPayChnl><PspName>Mock Wallet</PspName>\
+                                     <PspReceiptNumber>MW{}</PspReceiptNumber></PymtTrxInf></gepgPmtSpInfo>",
+                                    issued,
+                                    issued,
+                                    bill_id,
+                                    control_number,
+                                    xml_field(&body, "PaidAmt").unwrap_or_default(),
+                                    Local::now().format("%Y-%m-%dT%H:%M:%S"),
+                                    issued
+                                ),
+                            );
+                            xml_response(&gepg::ack_xml("mockPay", gepg::SUCCESS))
+                        }
+                        None => xml_response(&gepg::ack_xml("mockPay", gepg::FAILURE)),
+                    }
+                }
+                _ => Response::from_string("Not found").with_status_code(404),
+            };
+
+            let _ = request.respond(response);
+        }
+    });
+
+    Ok(url)
+}
+
+fn xml_response(content: &str) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_string(envelope(content, "MOCK"))
+        .with_header(Header::from_bytes("Content-Type", "application/xml").unwrap())
+}
+
+/// Posts to the booth after answering, as GePG does, so the booth never
+/// waits on itself.
+fn callback(url: &str, content: String) {
+    let url = url.to_string();
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        if let Err(e) = ureq::post(&url)
+            .set("Content-Type", "application/xml")
+            .send_string(&envelope(&content, "MOCK"))
+        {
+            println!("[Rust] Mock GePG callback failed: {}", e);
+        }
+    });
+}
+
+/// Asks the mock gateway to pay a control number.
+pub fn pay(mock_url: &str, control_number: &str, amount: f64) -> Result<(), String> {
+    let body = format!("<PayCntrNum>{}</PayCntrNum><PaidAmt>{:.2}</PaidAmt>", control_number, amount);
+    let response = ureq::post(&format!("{}/mock/pay", mock_url))
+        .send_string(&body)
+        .map_err(|e| format!("Mock GePG payment failed: {}", e))?
+        .into_string()
+        .map_err(|e| format!("Failed to read mock GePG response: {}", e))?;
+
+    match xml_field(&response, "TrxStsCode").as_deref() {
+        Some(gepg::SUCCESS) => Ok(()),
+        _ => Err(format!("Mock GePG has no bill with control number {}", control_number)),
+    }
+}
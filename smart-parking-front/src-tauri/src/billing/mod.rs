@@ -0,0 +1,710 @@
+pub mod gepg;
+pub mod mock;
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use chrono::{Duration, Local, TimeZone};
+use rsa::{RsaPrivateKey, RsaPublicKey};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::callback_server;
+use crate::fiscal::vfd::{self, xml_field};
+use crate::sync_queue;
+use crate::util::{now_secs, read_json, write_json};
+use gepg::{BillPayload, GepgClient};
+
+/// Event emitted with a `Bill` whenever GePG tells us something about it.
+pub const BILL_UPDATED_EVENT: &str = "gepg-bill-updated";
+
+/// Settled and failed bills kept after they stop changing.
+const KEEP_FINISHED: usize = 1000;
+
+/// Service provider registration with GePG, kept in `gepg_config.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BillingConfig {
+    pub enabled: bool,
+    /// Base URL of the GePG API, as given at onboarding.
+    pub endpoint: String,
+    /// Send bills to the built-in mock gateway instead of `endpoint`.
+    pub mock: bool,
+    pub sp_code: String,
+    pub sub_sp_code: String,
+    pub sp_sys_id: String,
+    /// Revenue (GFS) code the parking fee is collected under.
+    pub gfs_code: String,
+    /// Collection short code in payment QR codes.
+    pub short_code: String,
+    /// Council name shown by the payer's bank or wallet.
+    pub sp_name: String,
+    pub approved_by: String,
+    /// 1 = exact amount, 2 = partial payments allowed, 3 = exact or more.
+    pub pay_option: u8,
+    pub expiry_days: u32,
+    /// Where GePG posts control numbers and payment notifications. Registered
+    /// with GePG at onboarding; changes take effect when the app restarts.
+    /// Only this PC can reach the default; set a LAN address when GePG's
+    /// posts are forwarded in from the council's gateway.
+    pub callback_addr: String,
+    /// PEM (PKCS#8 or PKCS#1) key registered with GePG for signing bills.
+    pub private_key_path: String,
+    /// PEM public key from the GePG certificate. Without it every callback is
+    /// refused, except from the mock gateway.
+    pub gepg_public_key_path: String,
+}
+
+impl Default for BillingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: String::new(),
+            mock: false,
+            sp_code: String::new(),
+            sub_sp_code: String::new(),
+            sp_sys_id: String::new(),
+            gfs_code: String::new(),
+            short_code: "001001".to_string(),
+            sp_name: String::new(),
+            approved_by: String::new(),
+            pay_option: 1,
+            expiry_days: 7,
+            callback_addr: "127.0.0.1:8788".to_string(),
+            private_key_path: String::new(),
+            gepg_public_key_path: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BillState {
+    /// Not yet acknowledged by GePG; sent again on the next request.
+    Submitting,
+    AwaitingControlNumber,
+    Issued,
+    PartiallyPaid,
+    Paid,
+    Failed,
+    Expired,
+}
+
+impl BillState {
+    fn is_open(self) -> bool {
+        matches!(self, BillState::Issued | BillState::PartiallyPaid)
+    }
+}
+
+/// A payment GePG reported against a bill.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Payment {
+    pub trx_id: String,
+    pub amount: f64,
+    pub channel: String,
+    pub psp_name: String,
+    pub psp_receipt_number: String,
+    pub payer_phone: String,
+    pub paid_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bill {
+    /// Receipt number or session id the bill was raised for.
+    pub bill_id: String,
+    pub amount: f64,
+    pub description: String,
+    pub payer_name: String,
+    pub payer_phone: String,
+    pub control_number: Option<String>,
+    pub state: BillState,
+    /// Last `TrxStsCode` from GePG.
+    pub status_code: Option<String>,
+    pub payments: Vec<Payment>,
+    pub paid_amount: f64,
+    pub document: String,
+    pub last_error: Option<String>,
+    pub created_at: u64,
+    pub expires_at: u64,
+    pub updated_at: u64,
+}
+
+/// What the booth asks GePG to bill.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BillRequest {
+    pub bill_id: String,
+    pub amount: f64,
+    pub description: Option<String>,
+    pub payer_name: Option<String>,
+    pub payer_phone: Option<String>,
+    pub operator_name: Option<String>,
+}
+
+/// GePG format date, local time.
+fn gepg_time(secs: u64) -> String {
+    Local
+        .timestamp_opt(secs as i64, 0)
+        .single()
+        .map(|t| t.format("%Y-%m-%dT%H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+/// The GePG "Lipa kwa QR" payload banks and wallets scan to pay a bill.
+pub fn payment_qr(config: &BillingConfig, bill: &Bill) -> Option<String> {
+    let control_number = bill.control_number.as_ref()?;
+    let qr = serde_json::json!({
+        "opType": "2",
+        "shortCode": config.short_code,
+        "billReference": control_number,
+        "amount": format!("{}", bill.amount),
+        "billCcy": "TZS",
+        "billExprDt": gepg_time(bill.expires_at).get(..10).unwrap_or_default(),
+        "billPayOpt": config.pay_option.to_string(),
+        "billRsv01": format!("{}|{}", config.sp_name, bill.payer_name),
+    });
+    Some(qr.to_string())
+}
+
+pub struct Billing {
+    dir: PathBuf,
+    config: Mutex<BillingConfig>,
+    bills: Mutex<Vec<Bill>>,
+    key: Mutex<Option<RsaPrivateKey>>,
+    gepg_key: Mutex<Option<RsaPublicKey>>,
+    callback_url: Mutex<Option<String>>,
+    mock_url: Mutex<Option<String>>,
+}
+
+impl Billing {
+    pub fn load(dir: PathBuf) -> Result<Self, String> {
+        let config: BillingConfig = read_json(&dir.join("gepg_config.json"))?;
+        let bills: Vec<Bill> = read_json(&dir.join("gepg_bills.json"))?;
+
+        let open = bills.iter().filter(|b| b.state.is_open()).count();
+        println!("[Rust] GePG bills loaded: {} waiting for payment", open);
+
+        Ok(Self {
+            dir,
+            config: Mutex::new(config),
+            bills: Mutex::new(bills),
+            key: Mutex::new(None),
+            gepg_key: Mutex::new(None),
+            callback_url: Mutex::new(None),
+            mock_url: Mutex::new(None),
+        })
+    }
+
+    pub fn config(&self) -> BillingConfig {
+        self.config.lock().unwrap().clone()
+    }
+
+    pub fn save_config(&self, config: BillingConfig) -> Result<(), String> {
+        write_json(&self.dir.join("gepg_config.json"), &config)?;
+        *self.config.lock().unwrap() = config;
+        *self.key.lock().unwrap() = None;
+        *self.gepg_key.lock().unwrap() = None;
+        Ok(())
+    }
+
+    /// Local URL of the running callback server, for the mock gateway.
+    pub fn set_callback_url(&self, url: String) {
+        *self.callback_url.lock().unwrap() = Some(url);
+    }
+
+    pub fn callback_running(&self) -> bool {
+        self.callback_url.lock().unwrap().is_some()
+    }
+
+    /// The mock gateway starts on first use, once it knows where to call back.
+    fn endpoint(&self, config: &BillingConfig) -> Result<String, String> {
+        if !config.mock {
+            return Ok(config.endpoint.clone());
+        }
+
+        let mut mock_url = self.mock_url.lock().unwrap();
+        if let Some(url) = mock_url.as_ref() {
+            return Ok(url.clone());
+        }
+        let callback = self
+            .callback_url
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or("GePG callback server is not running")?;
+        let url = mock::spawn_mock_gepg("127.0.0.1:0", &callback)?;
+        *mock_url = Some(url.clone());
+        Ok(url)
+    }
+
+    fn signing_key(&self, config: &BillingConfig) -> Result<RsaPrivateKey, String> {
+        let mut key = self.key.lock().unwrap();
+        if let Some(k) = key.as_ref() {
+            return Ok(k.clone());
+        }
+
+        let loaded = if config.private_key_path.is_empty() && config.mock {
+            RsaPrivateKey::new(&mut rsa::rand_core::OsRng, 1024)
+                .map_err(|e| format!("Failed to create mock signing key: {}", e))?
+        } else {
+            vfd::load_private_key(&config.private_key_path)?
+        };
+
+        *key = Some(loaded.clone());
+        Ok(loaded)
+    }
+
+    fn gepg_key(&self, config: &BillingConfig) -> Result<Option<RsaPublicKey>, String> {
+        if config.gepg_public_key_path.is_empty() {
+            return Ok(None);
+        }
+        let mut key = self.gepg_key.lock().unwrap();
+        if key.is_none() {
+            *key = Some(gepg::load_public_key(&config.gepg_public_key_path)?);
+        }
+        Ok(key.clone())
+    }
+
+    fn save_bills(&self, bills: &mut Vec<Bill>) -> Result<(), String> {
+        let now = now_secs();
+        for b in bills.iter_mut().filter(|b| b.state.is_open() && b.expires_at <= now) {
+            b.state = BillState::Expired;
+            b.updated_at = now;
+        }
+
+        let finished = |b: &Bill| matches!(b.state, BillState::Paid | BillState::Failed | BillState::Expired);
+        let excess = bills.iter().filter(|b| finished(b)).count().saturating_sub(KEEP_FINISHED);
+        let mut dropped = 0;
+        bills.retain(|b| {
+            if dropped < excess && finished(b) {
+                dropped += 1;
+                false
+            } else {
+                true
+            }
+        });
+
+        write_json(&self.dir.join("gepg_bills.json"), &*bills)
+    }
+
+    fn update<F: FnOnce(&mut Bill)>(&self, bill_id: &str, change: F) -> Option<Bill> {
+        let mut bills = self.bills.lock().unwrap();
+        let bill = bills.iter_mut().find(|b| b.bill_id == bill_id)?;
+        change(bill);
+        bill.updated_at = now_secs();
+        let updated = bill.clone();
+        if let Err(e) = self.save_bills(&mut bills) {
+            println!("[Rust] {}", e);
+        }
+        Some(updated)
+    }
+
+    /// Sends a bill to GePG. The control number arrives later on the
+    /// callback, so the bill comes back waiting for it. Asking again for the
+    /// same bill id returns the existing bill, resending it if GePG never
+    /// acknowledged it.
+    pub fn request_control_number(&self, request: BillRequest) -> Result<Bill, String> {
+        let config = self.config();
+        if !config.enabled {
+            return Err("GePG billing is not enabled".to_string());
+        }
+        if request.amount <= 0.0 {
+            return Err(format!("Bill {} has no amount", request.bill_id));
+        }
+
+        let bill = {
+            let mut bills = self.bills.lock().unwrap();
+            match bills.iter().find(|b| b.bill_id == request.bill_id) {
+                Some(existing) if existing.state != BillState::Submitting => return Ok(existing.clone()),
+                Some(existing) => existing.clone(),
+                None => {
+                    let now = now_secs();
+                    let expires_at = now + Duration::days(config.expiry_days.max(1) as i64).num_seconds() as u64;
+                    let payload = BillPayload {
+                        bill_id: request.bill_id.clone(),
+                        amount: request.amount,
+                        description: request.description.unwrap_or_else(|| "Ada ya Maegesho".to_string()),
+                        payer_name: request.payer_name.unwrap_or_else(|| "Mteja".to_string()),
+                        payer_phone: request.payer_phone.unwrap_or_default(),
+                        generated_at: gepg_time(now),
+                        expires_at: gepg_time(expires_at),
+                        generated_by: request.operator_name.unwrap_or_else(|| "booth".to_string()),
+                    };
+                    let content = gepg::bill_request_xml(&config, &payload);
+
+                    let bill = Bill {
+                        bill_id: payload.bill_id,
+                        amount: payload.amount,
+                        description: payload.description,
+                        payer_name: payload.payer_name,
+                        payer_phone: payload.payer_phone,
+                        control_number: None,
+                        state: BillState::Submitting,
+                        status_code: None,
+                        payments: Vec::new(),
+                        paid_amount: 0.0,
+                        document: gepg::signed_envelope(&self.signing_key(&config)?, &content)?,
+                        last_error: None,
+                        created_at: now,
+                        expires_at,
+                        updated_at: now,
+                    };
+                    bills.push(bill.clone());
+                    self.save_bills(&mut bills)?;
+                    bill
+                }
+            }
+        };
+
+        // Not holding the lock: GePG may call back before it answers
+        let outcome = self
+            .endpoint(&config)
+            .and_then(|endpoint| GepgClient::new(&endpoint).submit_bill(&config, &bill.document));
+
+        let updated = self.update(&bill.bill_id, |b| match outcome {
+            Ok(code) => {
+                println!("[Rust] GePG answered {} for bill {}", code, b.bill_id);
+                if b.state == BillState::Submitting {
+                    b.state = if code == gepg::SUCCESS {
+                        BillState::AwaitingControlNumber
+                    } else {
+                        BillState::Failed
+                    };
+                }
+                b.status_code = Some(code);
+                b.last_error = None;
+            }
+            Err(e) => {
+                println!("[Rust] Bill {} not sent to GePG: {}", b.bill_id, e);
+                b.last_error = Some(e);
+            }
+        });
+        updated.ok_or_else(|| format!("Bill {} disappeared", bill.bill_id))
+    }
+
+    pub fn bills(&self) -> Vec<Bill> {
+        self.bills.lock().unwrap().clone()
+    }
+
+    pub fn bill(&self, bill_id: &str) -> Option<Bill> {
+        self.bills.lock().unwrap().iter().find(|b| b.bill_id == bill_id).cloned()
+    }
+
+    /// Handles a message GePG posted to the callback server. Returns the
+    /// signed acknowledgement to answer with and the bill, if the message
+    /// changed it; a resent message is acknowledged again and nothing more.
+    pub fn handle_callback(&self, xml: &str) -> (String, Option<Bill>) {
+        let config = self.config();
+        let element = gepg::message_element(xml).unwrap_or_default().to_string();
+
+        // Anyone who can reach the callback port could otherwise mark a bill paid
+        let checked = self.gepg_key(&config).and_then(|key| match key {
+            Some(key) => gepg::verify(&key, xml),
+            None if config.mock => Ok(()),
+            None => Err("no GePG public key is configured to verify it".to_string()),
+        });
+        let outcome = match checked {
+            Err(e) => Err(e),
+            Ok(()) => match element.as_str() {
+                "gepgBillSubResp" => self.control_number_received(xml),
+                "gepgPmtSpInfo" => self.payment_received(xml),
+                _ => Err(format!("Unexpected GePG message '{}'", element)),
+            },
+        };
+
+        let (code, bill) = match outcome {
+            Ok(bill) => (gepg::SUCCESS, bill),
+            Err(e) => {
+                println!("[Rust] GePG callback refused: {}", e);
+                (gepg::FAILURE, None)
+            }
+        };
+
+        let ack = gepg::ack_xml(&element, code);
+        let response = self
+            .signing_key(&config)
+            .and_then(|key| gepg::signed_envelope(&key, &ack))
+            .unwrap_or_else(|e| {
+                println!("[Rust] Sending unsigned GePG acknowledgement: {}", e);
+                gepg::envelope(&ack, "")
+            });
+        (response, bill)
+    }
+
+    fn control_number_received(&self, xml: &str) -> Result<Option<Bill>, String> {
+        let bill_id = xml_field(xml, "BillId").ok_or("Control number response has no BillId")?;
+        let status = xml_field(xml, "TrxSts").unwrap_or_default();
+        let code = xml_field(xml, "TrxStsCode");
+        let control_number = xml_field(xml, "PayCntrNum").filter(|c| !c.is_empty() && c != "0");
+
+        let mut changed = false;
+        let bill = self.update(&bill_id, |b| {
+            let before = (b.state, b.control_number.clone());
+            match (status.as_str(), control_number) {
+                ("GS", Some(number)) => {
+                    println!("[Rust] Bill {} has control number {}", b.bill_id, number);
+                    b.control_number = Some(number);
+                    b.state = BillState::Issued;
+                }
+                _ => {
+                    println!("[Rust] GePG refused bill {}: {:?}", b.bill_id, code);
+                    b.state = BillState::Failed;
+                }
+            }
+            b.status_code = code;
+            changed = (b.state, b.control_number.clone()) != before;
+        })
+        .ok_or_else(|| format!("Unknown bill {}", bill_id))?;
+        Ok(changed.then_some(bill))
+    }
+
+    fn payment_received(&self, xml: &str) -> Result<Option<Bill>, String> {
+        let trx_id = xml_field(xml, "TrxId").ok_or("Payment has no TrxId")?;
+        let amount = xml_field(xml, "PaidAmt")
+            .as_deref()
+            .and_then(vfd::parse_amount)
+            .ok_or("Payment has no PaidAmt")?;
+        let control_number = xml_field(xml, "PayCtrNum");
+        let bill_id = {
+            let bills = self.bills.lock().unwrap();
+            xml_field(xml, "BillId")
+                .filter(|id| bills.iter().any(|b| &b.bill_id == id))
+                .or_else(|| {
+                    bills
+                        .iter()
+                        .find(|b| b.control_number.is_some() && b.control_number == control_number)
+                        .map(|b| b.bill_id.clone())
+                })
+                .ok_or_else(|| format!("Payment {} is for an unknown bill", trx_id))?
+        };
+
+        let payment = Payment {
+            trx_id: trx_id.clone(),
+            amount,
+            channel: xml_field(xml, "UsdPayChnl").unwrap_or_default(),
+            psp_name: xml_field(xml, "PspName").unwrap_or_default(),
+            psp_receipt_number: xml_field(xml, "PspReceiptNumber").unwrap_or_default(),
+            payer_phone: xml_field(xml, "PyrCellNum").unwrap_or_default(),
+            paid_at: xml_field(xml, "TrxDtTm").unwrap_or_default(),
+        };
+
+        let mut changed = false;
+        let bill = self.update(&bill_id, |b| {
+            // GePG resends notifications until it sees our acknowledgement
+            if b.payments.iter().any(|p| p.trx_id == trx_id) {
+                return;
+            }
+            changed = true;
+            println!("[Rust] Bill {} paid TZS {} ({})", b.bill_id, amount, trx_id);
+            b.paid_amount += amount;
+            b.payments.push(payment);
+            b.state = if b.paid_amount + 0.005 >= b.amount {
+                BillState::Paid
+            } else {
+                BillState::PartiallyPaid
+            };
+        })
+        .ok_or_else(|| format!("Unknown bill {}", bill_id))?;
+        Ok(changed.then_some(bill))
+    }
+
+    /// Pays a bill through the mock gateway, for training and testing.
+    pub fn simulate_payment(&self, control_number: &str, amount: f64) -> Result<(), String> {
+        let config = self.config();
+        if !config.mock {
+            return Err("Payments can only be simulated against the mock gateway".to_string());
+        }
+        mock::pay(&self.endpoint(&config)?, control_number, amount)
+    }
+}
+
+/// Starts the callback server if billing is on and it isn't running yet.
+pub fn start_callback_server(app: &AppHandle) {
+    let billing = app.state::<Billing>();
+    let config = billing.config();
+    if !config.enabled || billing.callback_running() {
+        return;
+    }
+
+    let handle = app.clone();
+    let started = callback_server::serve("GePG", &config.callback_addr, "application/xml", move |_, body| {
+        let (response, bill) = handle.state::<Billing>().handle_callback(body);
+        if let Some(bill) = bill {
+            // Payment notifications carry a GePG transaction id
+            if let Some(trx_id) = xml_field(body, "TrxId") {
+                sync_queue::record(&handle, sync_queue::ENTITY_PAYMENT, &trx_id, "gepg_paid", &bill);
+            }
+            if let Err(e) = handle.emit(BILL_UPDATED_EVENT, &bill) {
+                println!("[Rust] Failed to emit GePG bill update: {}", e);
+            }
+        }
+        response
+    });
+
+    match started {
+        Ok(url) => billing.set_callback_url(url),
+        Err(e) => println!("[Rust] {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_store;
+    use std::fs;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration as StdDuration, Instant};
+
+    fn mock_billing(name: &str) -> Arc<Billing> {
+        let billing = Arc::new(test_store(&format!("gepg-{}", name), Billing::load));
+        billing
+            .save_config(BillingConfig {
+                enabled: true,
+                mock: true,
+                sp_code: "SP419".to_string(),
+                sp_name: "Chato DC".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        let handler = billing.clone();
+        let url = callback_server::serve("GePG", "127.0.0.1:0", "application/xml", move |_, body| {
+            handler.handle_callback(body).0
+        })
+        .unwrap();
+        billing.set_callback_url(url);
+        billing
+    }
+
+    fn request(bill_id: &str, amount: f64) -> BillRequest {
+        BillRequest {
+            bill_id: bill_id.to_string(),
+            amount,
+            description: None,
+            payer_name: Some("T 123 ABC".to_string()),
+            payer_phone: None,
+            operator_name: Some("Asha".to_string()),
+        }
+    }
+
+    fn wait_for(billing: &Billing, bill_id: &str, state: BillState) -> Bill {
+        let start = Instant::now();
+        loop {
+            let bill = billing.bill(bill_id).unwrap();
+            if bill.state == state || start.elapsed() > StdDuration::from_secs(5) {
+                return bill;
+            }
+            thread::sleep(StdDuration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn control_number_and_payment_arrive_on_the_callback() {
+        let billing = mock_billing("flow");
+        let bill = billing.request_control_number(request("RCP-1", 2000.0)).unwrap();
+        assert_ne!(bill.state, BillState::Submitting);
+
+        let issued = wait_for(&billing, "RCP-1", BillState::Issued);
+        assert_eq!(issued.state, BillState::Issued);
+        let control_number = issued.control_number.clone().unwrap();
+        assert!(control_number.starts_with("99"));
+
+        let qr: serde_json::Value =
+            serde_json::from_str(&payment_qr(&billing.config(), &issued).unwrap()).unwrap();
+        assert_eq!(qr["billReference"], control_number.as_str());
+        assert_eq!(qr["amount"], "2000");
+
+        billing.simulate_payment(&control_number, 2000.0).unwrap();
+        let paid = wait_for(&billing, "RCP-1", BillState::Paid);
+        assert_eq!(paid.state, BillState::Paid);
+        assert_eq!(paid.payments.len(), 1);
+
+        // The same bill id never raises a second bill
+        let again = billing.request_control_number(request("RCP-1", 2000.0)).unwrap();
+        assert_eq!(again.control_number, Some(control_number));
+    }
+
+    #[test]
+    fn payment_notifications_are_matched_and_deduplicated() {
+        let billing = mock_billing("payments");
+        billing.request_control_number(request("RCP-2", 2000.0)).unwrap();
+        let control_number = wait_for(&billing, "RCP-2", BillState::Issued).control_number.unwrap();
+
+        let notification = |trx: &str, amount: &str| {
+            gepg::envelope(
+                &format!(
+                    "<gepgPmtSpInfo><PymtTrxInf><TrxId>{}</TrxId><BillId></BillId><PayCtrNum>{}</PayCtrNum>\
+                     <PaidAmt>{}</PaidAmt><UsdPayChnl>MOBILE</UsdPayChnl></PymtTrxInf></gepgPmtSpInfo>",
+                    trx, control_number, amount
+                ),
+                "",
+            )
+        };
+
+        let (ack, bill) = billing.handle_callback(&notification("T1", "500.00"));
+        assert_eq!(xml_field(&ack, "TrxStsCode").as_deref(), Some(gepg::SUCCESS));
+        assert_eq!(bill.unwrap().state, BillState::PartiallyPaid);
+
+        // A resend is acknowledged but changes nothing, so nothing is synced again
+        let (ack, bill) = billing.handle_callback(&notification("T1", "500.00"));
+        assert_eq!(xml_field(&ack, "TrxStsCode").as_deref(), Some(gepg::SUCCESS));
+        assert!(bill.is_none());
+        assert_eq!(billing.bill("RCP-2").unwrap().paid_amount, 500.0);
+
+        let (_, bill) = billing.handle_callback(&notification("T2", "1500.00"));
+        assert_eq!(bill.unwrap().state, BillState::Paid);
+
+        let unknown = gepg::envelope(
+            "<gepgPmtSpInfo><PymtTrxInf><TrxId>T3</TrxId><PayCtrNum>990000000000</PayCtrNum>\
+             <PaidAmt>100</PaidAmt></PymtTrxInf></gepgPmtSpInfo>",
+            "",
+        );
+        let (ack, bill) = billing.handle_callback(&unknown);
+        assert!(ack.contains("<gepgPmtSpInfoAck><TrxStsCode>7201</TrxStsCode>"));
+        assert!(bill.is_none());
+    }
+
+    #[test]
+    fn unsigned_callbacks_are_refused_when_the_gepg_key_is_set() {
+        use rsa::pkcs8::{EncodePublicKey, LineEnding};
+
+        let billing = mock_billing("verify");
+        let gepg_key = RsaPrivateKey::new(&mut rsa::rand_core::OsRng, 1024).unwrap();
+        let path = billing.dir.join("gepg_public.pem");
+        fs::write(&path, RsaPublicKey::from(&gepg_key).to_public_key_pem(LineEnding::LF).unwrap()).unwrap();
+
+        let mut config = billing.config();
+        config.gepg_public_key_path = path.display().to_string();
+        billing.save_config(config).unwrap();
+        billing.request_control_number(request("RCP-3", 1000.0)).unwrap();
+
+        let content = "<gepgBillSubResp><BillTrxInf><BillId>RCP-3</BillId><TrxSts>GS</TrxSts>\
+                       <PayCntrNum>991111111111</PayCntrNum><TrxStsCode>7101</TrxStsCode></BillTrxInf></gepgBillSubResp>";
+        let (_, forged) = billing.handle_callback(&gepg::envelope(content, "Zm9yZ2Vk"));
+        assert!(forged.is_none());
+
+        let (_, bill) = billing.handle_callback(&gepg::signed_envelope(&gepg_key, content).unwrap());
+        assert_eq!(bill.unwrap().control_number.as_deref(), Some("991111111111"));
+    }
+
+    #[test]
+    fn callbacks_are_refused_without_a_gepg_key_outside_the_mock() {
+        let billing = mock_billing("unsigned");
+        billing.request_control_number(request("RCP-4", 1000.0)).unwrap();
+        wait_for(&billing, "RCP-4", BillState::Issued);
+
+        let mut config = billing.config();
+        config.mock = false;
+        billing.save_config(config).unwrap();
+
+        let forged = gepg::envelope(
+            "<gepgPmtSpInfo><PymtTrxInf><TrxId>T9</TrxId><BillId>RCP-4</BillId>\
+             <PaidAmt>1000</PaidAmt></PymtTrxInf></gepgPmtSpInfo>",
+            "",
+        );
+        let (ack, bill) = billing.handle_callback(&forged);
+        assert!(ack.contains("<TrxStsCode>7201</TrxStsCode>"));
+        assert!(bill.is_none());
+        assert_eq!(billing.bill("RCP-4").unwrap().state, BillState::Issued);
+    }
+}
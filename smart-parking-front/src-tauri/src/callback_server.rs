@@ -0,0 +1,29 @@
+use std::thread;
+
+use tiny_http::{Header, Response, Server};
+
+/// Accepts posts from a payment gateway on `addr` and answers each with what
+/// `handler` returns for the request path and body. Returns the local URL the
+/// server is reachable at.
+pub fn serve<F>(name: &str, addr: &str, content_type: &'static str, handler: F) -> Result<String, String>
+where
+    F: Fn(&str, &str) -> String + Send + 'static,
+{
+    let server = Server::http(addr).map_err(|e| format!("Failed to start {} callback server on {}: {}", name, addr, e))?;
+    let port = server.server_addr().to_ip().map(|a| a.port()).unwrap_or_default();
+    let url = format!("http://127.0.0.1:{}", port);
+    println!("[Rust] {} callback server listening on {}", name, addr);
+
+    thread::spawn(move || {
+        for mut request in server.incoming_requests() {
+            let mut body = String::new();
+            let _ = request.as_reader().read_to_string(&mut body);
+
+            let response = Response::from_string(handler(request.url(), &body))
+                .with_header(Header::from_bytes("Content-Type", content_type).unwrap());
+            let _ = request.respond(response);
+        }
+    });
+
+    Ok(url)
+}
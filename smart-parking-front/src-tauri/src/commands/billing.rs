@@ -0,0 +1,38 @@
+use tauri::{AppHandle, State};
+
+use crate::billing::{self, Bill, BillRequest, Billing, BillingConfig};
+
+#[tauri::command]
+pub fn get_billing_config(billing: State<'_, Billing>) -> BillingConfig {
+    billing.config()
+}
+
+#[tauri::command]
+pub fn save_billing_config(app: AppHandle, billing: State<'_, Billing>, config: BillingConfig) -> Result<(), String> {
+    billing.save_config(config)?;
+    billing::start_callback_server(&app);
+    Ok(())
+}
+
+/// Raises a GePG bill. The control number follows on the
+/// `gepg-bill-updated` event.
+#[tauri::command]
+pub fn request_control_number(billing: State<'_, Billing>, request: BillRequest) -> Result<Bill, String> {
+    billing.request_control_number(request)
+}
+
+#[tauri::command]
+pub fn list_bills(billing: State<'_, Billing>) -> Vec<Bill> {
+    billing.bills()
+}
+
+#[tauri::command]
+pub fn get_bill(billing: State<'_, Billing>, bill_id: String) -> Option<Bill> {
+    billing.bill(&bill_id)
+}
+
+/// Pays a bill through the mock gateway, for training.
+#[tauri::command]
+pub fn simulate_gepg_payment(billing: State<'_, Billing>, control_number: String, amount: f64) -> Result<(), String> {
+    billing.simulate_payment(&control_number, amount)
+}
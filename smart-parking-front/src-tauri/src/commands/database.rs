@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+
+use tauri::{AppHandle, State};
+
+use crate::database::{Database, ExitRecord, GateDevice, NewEntry, ParkingSession, SessionQuery};
+use crate::sync_queue;
+use crate::tariff;
+use crate::util::now_secs;
+
+#[tauri::command]
+pub fn record_vehicle_entry(app: AppHandle, db: State<'_, Database>, entry: NewEntry) -> Result<ParkingSession, String> {
+    let session = db.record_entry(entry)?;
+    sync_queue::queued(&app);
+    Ok(session)
+}
+
+#[tauri::command]
+pub fn record_vehicle_exit(
+    app: AppHandle,
+    db: State<'_, Database>,
+    mut exit: ExitRecord,
+) -> Result<ParkingSession, String> {
+    // Offline the fee comes from the cached tariffs
+    if exit.fee.is_none() {
+        let exit_time = *exit.exit_time.get_or_insert_with(now_secs);
+        let fee = tariff::quote(&db, &db.get_session(exit.session_id)?, exit_time)?;
+        exit.fee = Some(fee.amount as f64);
+    }
+    let session = db.record_exit(exit)?;
+    sync_queue::queued(&app);
+    Ok(session)
+}
+
+#[tauri::command]
+pub fn cancel_parking_session(
+    app: AppHandle,
+    db: State<'_, Database>,
+    session_id: i64,
+) -> Result<ParkingSession, String> {
+    let session = db.cancel_session(session_id)?;
+    sync_queue::queued(&app);
+    Ok(session)
+}
+
+#[tauri::command]
+pub fn get_parking_session(db: State<'_, Database>, session_id: i64) -> Result<ParkingSession, String> {
+    db.get_session(session_id)
+}
+
+/// The vehicle's open session, if it is inside.
+#[tauri::command]
+pub fn find_parked_vehicle(db: State<'_, Database>, plate_number: String) -> Result<Option<ParkingSession>, String> {
+    db.find_parked(&plate_number)
+}
+
+#[tauri::command]
+pub fn list_parking_sessions(
+    db: State<'_, Database>,
+    query: Option<SessionQuery>,
+) -> Result<Vec<ParkingSession>, String> {
+    db.list_sessions(&query.unwrap_or_default())
+}
+
+#[tauri::command]
+pub fn list_gate_devices(db: State<'_, Database>) -> Result<Vec<GateDevice>, String> {
+    db.list_gate_devices()
+}
+
+#[tauri::command]
+pub fn save_gate_device(db: State<'_, Database>, gate: GateDevice) -> Result<GateDevice, String> {
+    db.save_gate_device(gate)
+}
+
+#[tauri::command]
+pub fn delete_gate_device(db: State<'_, Database>, gate_id: i64) -> Result<(), String> {
+    db.delete_gate_device(gate_id)
+}
+
+#[tauri::command]
+pub fn get_config_value(db: State<'_, Database>, key: String) -> Result<Option<serde_json::Value>, String> {
+    let mut value = db.get_config(&key)?;
+    if let Some(value) = &mut value {
+        sync_queue::hide_token(&key, value);
+    }
+    Ok(value)
+}
+
+#[tauri::command]
+pub fn set_config_value(db: State<'_, Database>, key: String, mut value: serde_json::Value) -> Result<(), String> {
+    sync_queue::keep_token(&db, &key, &mut value)?;
+    db.set_config(&key, &value)
+}
+
+#[tauri::command]
+pub fn get_all_config(db: State<'_, Database>) -> Result<BTreeMap<String, serde_json::Value>, String> {
+    let mut config = db.all_config()?;
+    for (key, value) in config.iter_mut() {
+        sync_queue::hide_token(key, value);
+    }
+    Ok(config)
+}
@@ -0,0 +1,34 @@
+use tauri::{AppHandle, State};
+
+use crate::fiscal::{self, Fiscal, FiscalConfig, FiscalReceipt};
+
+/// The VFD password stays on the desktop; the webview gets it blank.
+#[tauri::command]
+pub fn get_fiscal_config(fiscal: State<'_, Fiscal>) -> FiscalConfig {
+    let mut config = fiscal.config();
+    config.password.clear();
+    config
+}
+
+/// A blank password keeps the one already saved.
+#[tauri::command]
+pub fn save_fiscal_config(fiscal: State<'_, Fiscal>, mut config: FiscalConfig) -> Result<(), String> {
+    if config.password.is_empty() {
+        config.password = fiscal.config().password;
+    }
+    fiscal.save_config(config)
+}
+
+/// Fiscal receipts with their VFD upload state, the backlog included.
+#[tauri::command]
+pub fn list_fiscal_receipts(fiscal: State<'_, Fiscal>) -> Vec<FiscalReceipt> {
+    fiscal.receipts()
+}
+
+/// Sends the offline backlog now instead of waiting for the worker.
+#[tauri::command]
+pub fn submit_fiscal_backlog(app: AppHandle, fiscal: State<'_, Fiscal>) -> Result<(), String> {
+    fiscal.retry_pending()?;
+    fiscal::submit_soon(&app);
+    Ok(())
+}
@@ -1,7 +1,9 @@
-use tauri::command;
+use tauri::{command, AppHandle};
+
+use crate::sync_queue;
 
 #[command]
-pub fn open_gate(command: String) -> Result<String, String> {
+pub fn open_gate(app: AppHandle, command: String) -> Result<String, String> {
     use std::process::Command;
 
     // Step 1: Auto-detect first COM port on Windows
@@ -37,6 +39,7 @@ pub fn open_gate(command: String) -> Result<String, String> {
         .map_err(|e| format!("Failed to execute command: {}", e))?;
 
     if output.status.success() {
+        sync_queue::record_gate_opening(&app, Some(&port), &command);
         Ok(format!("Gate opened successfully on port {}", port))
     } else {
         let error = String::from_utf8_lossy(&output.stderr).to_string();
@@ -1,2 +1,14 @@
+pub mod billing;
+pub mod database;
+pub mod fiscal;
 pub mod gate;
-pub mod printer;
\ No newline at end of file
+pub mod numbering;
+pub mod payments;
+pub mod plates;
+pub mod print_queue;
+pub mod printer;
+pub mod receipt_history;
+pub mod shift;
+pub mod sync;
+pub mod tariff;
+pub mod ticket;
\ No newline at end of file
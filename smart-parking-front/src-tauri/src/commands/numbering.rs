@@ -0,0 +1,54 @@
+use tauri::State;
+
+use crate::audit::AuditLog;
+use crate::database::Database;
+use crate::numbering::{self, IssuedNumber, NumberKind, NumberingConfig, SequenceAudit};
+
+#[tauri::command]
+pub fn get_numbering_config(db: State<'_, Database>) -> NumberingConfig {
+    numbering::config(&db)
+}
+
+#[tauri::command]
+pub fn save_numbering_config(db: State<'_, Database>, config: NumberingConfig) -> Result<(), String> {
+    numbering::save_config(&db, &config)
+}
+
+/// A receipt number for when the server can't give one.
+#[tauri::command]
+pub fn next_receipt_number(
+    db: State<'_, Database>,
+    booth_id: String,
+    gate: Option<String>,
+) -> Result<IssuedNumber, String> {
+    numbering::issue(&db, NumberKind::Receipt, &booth_id, gate.as_deref())
+}
+
+/// A ticket number for when the server can't give one.
+#[tauri::command]
+pub fn next_ticket_number(
+    db: State<'_, Database>,
+    booth_id: String,
+    gate: Option<String>,
+) -> Result<IssuedNumber, String> {
+    numbering::issue(&db, NumberKind::Ticket, &booth_id, gate.as_deref())
+}
+
+#[tauri::command]
+pub fn void_number(
+    db: State<'_, Database>,
+    audit: State<'_, AuditLog>,
+    number: String,
+    operator_name: String,
+    reason: String,
+) -> Result<IssuedNumber, String> {
+    let voided = numbering::void(&db, &number, &reason)?;
+    audit.record(&operator_name, "number_voided", &format!("{}: {}", number, reason.trim()));
+    Ok(voided)
+}
+
+/// Every local sequence used on a date (`YYYY-MM-DD`), with its gaps.
+#[tauri::command]
+pub fn audit_number_sequences(db: State<'_, Database>, date: String) -> Result<Vec<SequenceAudit>, String> {
+    numbering::audit(&db, &date)
+}
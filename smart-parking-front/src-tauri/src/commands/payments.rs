@@ -0,0 +1,45 @@
+use tauri::{AppHandle, State};
+
+use crate::payments::{self, MobilePayment, PaymentRequest, Payments, PaymentsConfig};
+
+#[tauri::command]
+pub fn get_payments_config(payments: State<'_, Payments>) -> PaymentsConfig {
+    payments.config()
+}
+
+#[tauri::command]
+pub fn save_payments_config(payments: State<'_, Payments>, config: PaymentsConfig) -> Result<(), String> {
+    payments.save_config(config)
+}
+
+/// Sends a push-USSD prompt to the customer's phone. The outcome follows on
+/// the `mobile-payment-updated` event, and the gate opens by itself once the
+/// payment is confirmed.
+#[tauri::command]
+pub fn start_mobile_payment(app: AppHandle, request: PaymentRequest) -> Result<MobilePayment, String> {
+    payments::start(&app, request)
+}
+
+#[tauri::command]
+pub fn get_mobile_payment(payments: State<'_, Payments>, id: String) -> Option<MobilePayment> {
+    payments.payment(&id)
+}
+
+#[tauri::command]
+pub fn list_mobile_payments(payments: State<'_, Payments>, session_id: Option<String>) -> Vec<MobilePayment> {
+    payments.payments(session_id.as_deref())
+}
+
+/// Has the mock provider's customer accept or decline a prompt, for training.
+#[tauri::command]
+pub fn simulate_mobile_payment(
+    app: AppHandle,
+    payments: State<'_, Payments>,
+    id: String,
+    approve: bool,
+) -> Result<(), String> {
+    if let Some(update) = payments.simulate(&id, approve)? {
+        payments::publish(&app, update);
+    }
+    Ok(())
+}
@@ -0,0 +1,34 @@
+use tauri::{AppHandle, State};
+
+use crate::database::Database;
+use crate::plates::{self, PlateCategory, PlateConfig, PlateDecision, PlateEntry};
+use crate::sync_queue::SyncQueue;
+
+/// Checks a plate read at a gate, opening it for listed vehicles and
+/// alerting operators to blacklisted ones.
+#[tauri::command]
+pub fn check_plate(app: AppHandle, plate_number: String, gate_id: Option<i64>) -> Result<PlateDecision, String> {
+    plates::check(&app, &plate_number, gate_id)
+}
+
+/// Fetches the plate lists from the API, keeping the cached ones if it
+/// can't be reached.
+#[tauri::command]
+pub fn refresh_plate_lists(db: State<'_, Database>, queue: State<'_, SyncQueue>) -> Result<usize, String> {
+    plates::refresh(&db, &queue.config(&db))
+}
+
+#[tauri::command]
+pub fn list_plate_entries(db: State<'_, Database>, category: Option<PlateCategory>) -> Result<Vec<PlateEntry>, String> {
+    plates::entries(&db, category)
+}
+
+#[tauri::command]
+pub fn get_plate_config(db: State<'_, Database>) -> PlateConfig {
+    plates::config(&db)
+}
+
+#[tauri::command]
+pub fn save_plate_config(db: State<'_, Database>, config: PlateConfig) -> Result<(), String> {
+    plates::save_config(&db, &config)
+}
@@ -0,0 +1,25 @@
+use tauri::{AppHandle, State};
+
+use crate::print_queue::{self, PrintJob, PrintQueue};
+use crate::transport::PrintResult;
+
+#[tauri::command]
+pub fn list_print_jobs(queue: State<'_, PrintQueue>) -> Vec<PrintJob> {
+    queue.jobs()
+}
+
+/// Operator retry: resets the attempt count and prints straight away.
+#[tauri::command]
+pub fn retry_print_job(
+    app: AppHandle,
+    queue: State<'_, PrintQueue>,
+    job_id: String,
+) -> Result<PrintResult, String> {
+    queue.retry(&job_id)?;
+    print_queue::process_job(&app, &job_id)
+}
+
+#[tauri::command]
+pub fn cancel_print_job(queue: State<'_, PrintQueue>, job_id: String) -> Result<PrintJob, String> {
+    queue.cancel(&job_id)
+}
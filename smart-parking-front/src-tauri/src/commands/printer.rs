@@ -1,218 +1,408 @@
-#[cfg(windows)]
-use winapi::um::winspool::*;
-#[cfg(windows)]
-use winapi::shared::ntdef::HANDLE;
-#[cfg(windows)]
-use std::ffi::CString;
-#[cfg(windows)]
-use std::ptr;
-
 use serde::{Deserialize, Serialize};
+use tauri::path::BaseDirectory;
+use tauri::{AppHandle, Manager, State};
+
+use crate::escpos::logo::{self, Logo};
+use crate::escpos::models::{PrinterDatabase, PrinterModel};
+use crate::escpos::preview::{self, RenderedPreview};
+use crate::audit::AuditLog;
+use crate::billing::{self, BillState, Billing};
+use crate::database::Database;
+use crate::escpos::status::PrinterStatus;
+use crate::fiscal::{self, Fiscal};
+use crate::numbering;
+use crate::escpos::{Align, EscPos, PrinterProfile};
+use crate::print_queue::{self, PrintQueue};
+use crate::receipt_history::{self, ReceiptHistory};
+use crate::shifts::Shifts;
+use crate::sync_queue;
+use crate::printer_preferences::{PreferredPrinter, PrinterPreferences};
+use crate::transport::{self, print_bytes, DiscoveredPrinter, PrintResult};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PrintReceiptRequest {
     pub printer_name: String,
     pub receipt_data: serde_json::Value,
+    #[serde(default)]
+    pub profile: PrinterProfile,
+    /// Shift the takings count towards; the booth's open shift when not given.
+    #[serde(default)]
+    pub shift_id: Option<String>,
+    /// Booth printing the receipt.
+    #[serde(default)]
+    pub booth_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReprintReceiptRequest {
+    pub printer_name: String,
+    pub receipt_number: String,
+    pub operator_name: String,
+    #[serde(default)]
+    pub profile: PrinterProfile,
+}
+
+/// Marks a reprinted receipt so it can't pass as a second payment.
+struct ReceiptCopy {
+    number: usize,
+    reprinted_at: String,
 }
 
 #[tauri::command]
-pub fn print_receipt(request: PrintReceiptRequest) -> Result<String, String> {
-    let escpos = generate_escpos_receipt(&request.receipt_data);
+pub fn print_receipt(
+    app: AppHandle,
+    queue: State<'_, PrintQueue>,
+    history: State<'_, ReceiptHistory>,
+    audit: State<'_, AuditLog>,
+    fiscal: State<'_, Fiscal>,
+    billing: State<'_, Billing>,
+    request: PrintReceiptRequest,
+) -> Result<PrintResult, String> {
+    let profile = resolve_profile(&app, &request.profile);
+    let mut receipt_data = request.receipt_data.clone();
+    let receipt_number = receipt_data
+        .get("receipt_number")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string());
+    let paid_cash = receipt_data
+        .get("payment_method")
+        .and_then(|v| v.as_str())
+        .is_some_and(|m| m.trim().eq_ignore_ascii_case("cash"));
+
+    // A number that can't be accounted for is audited, but the customer has
+    // paid and still gets the receipt
+    if let Some(n) = &receipt_number {
+        if let Err(e) = numbering::mark_used(&app.state::<Database>(), n) {
+            let operator = receipt_data.get("operator_name").and_then(|v| v.as_str()).unwrap_or("-");
+            audit.record(operator, "receipt_number_unaccounted", &e);
+        }
+    }
 
-    #[cfg(windows)]
-    {
-        print_to_windows_printer(&request.printer_name, &escpos)
-            .map(|_| "Receipt printed successfully".to_string())
-            .map_err(|e| e)
+    // A receipt number already in the history is a retry of that receipt
+    let printed_before = receipt_number.as_ref().and_then(|n| history.get(n).ok());
+
+    // Fiscalised before rendering so the TRA verification code is on paper.
+    // An unreachable VFD only delays the upload, never the receipt. A retry
+    // carries the codes it was first printed with and isn't signed again.
+    if let Some(first) = &printed_before {
+        if let (Some(fields), Some(original)) = (receipt_data.as_object_mut(), first.receipt_data.as_object()) {
+            for key in ["tra_verification_code", "tra_verification_url", "tra_znum"] {
+                if let Some(value) = original.get(key) {
+                    fields.insert(key.into(), value.clone());
+                }
+            }
+        }
+    } else if let Some(n) = &receipt_number {
+        match fiscal.issue(n, &receipt_data) {
+            Ok(Some(f)) => {
+                if let Some(fields) = receipt_data.as_object_mut() {
+                    fields.insert("tra_verification_code".into(), f.verification_code.into());
+                    fields.insert("tra_verification_url".into(), f.verification_url.into());
+                    fields.insert("tra_znum".into(), f.znum.into());
+                }
+                fiscal::submit_soon(&app);
+            }
+            Ok(None) => {}
+            Err(e) => println!("[Rust] Receipt {} printed without TRA fiscalisation: {}", n, e),
+        }
     }
 
-    #[cfg(not(windows))]
-    {
-        Err("Windows printer support only".to_string())
+    // GePG control number, when a bill was raised for this receipt
+    let bill_id = receipt_data
+        .get("bill_id")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+        .or_else(|| receipt_number.clone());
+    if let Some(bill) = bill_id.and_then(|id| billing.bill(&id)) {
+        if let (Some(control_number), Some(fields)) = (&bill.control_number, receipt_data.as_object_mut()) {
+            fields.insert("gepg_control_number".into(), control_number.clone().into());
+            fields.insert("gepg_expires".into(), receipt_history::format_local(bill.expires_at).into());
+            if let Some(qr) = billing::payment_qr(&billing.config(), &bill) {
+                fields.insert("gepg_qr_data".into(), qr.into());
+            }
+            if bill.state == BillState::Paid {
+                fields.insert("gepg_status".into(), "IMELIPWA".into());
+            }
+        }
     }
-}
 
-#[tauri::command]
-pub fn get_available_printers() -> Result<Vec<String>, String> {
-    #[cfg(windows)]
-    {
-        list_windows_printers()
+    let logo = load_logo(&app, &profile);
+    let escpos = generate_escpos_receipt(&receipt_data, &profile, logo.as_ref(), None);
+
+    let description = match &receipt_number {
+        Some(n) => format!("Receipt {}", n),
+        None => "Receipt".to_string(),
+    };
+
+    // Kept even if printing fails, it's what the customer is owed
+    let mut first_print = false;
+    if let Some(n) = &receipt_number {
+        first_print = match history.record(n, &receipt_data, &request.printer_name, &escpos) {
+            Ok(new) => new,
+            Err(e) => {
+                println!("[Rust] {}", e);
+                printed_before.is_none()
+            }
+        };
+        if let Err(e) = app.state::<Shifts>().record_sale(
+            request.shift_id.as_deref(),
+            request.booth_id.as_deref(),
+            n,
+            &receipt_data,
+        ) {
+            println!("[Rust] {}", e);
+        }
+        sync_queue::record(&app, sync_queue::ENTITY_RECEIPT, n, "create", &receipt_data);
     }
 
-    #[cfg(not(windows))]
-    {
-        Err("Windows printer support only".to_string())
+    // Only the first print of a cash receipt opens the drawer; a retry of the
+    // same number, or a reprint, never does. It is kicked straight away rather
+    // than through the queue, so a queued retry can't open it later on either.
+    if paid_cash && first_print {
+        let operator = receipt_data.get("operator_name").and_then(|v| v.as_str()).unwrap_or("-");
+        match kick_drawer(&request.printer_name, &profile) {
+            Ok(()) => audit.record(operator, "cash_drawer_opened", &description),
+            Err(e) => println!("[Rust] Cash drawer did not open: {}", e),
+        }
     }
+
+    let job = queue.enqueue(&request.printer_name, &profile, escpos, &description)?;
+    print_queue::process_job(&app, &job.id)
 }
 
-/* ───────────────────────── WINDOWS RAW PRINT ───────────────────────── */
+/// Opens the cash drawer outside a sale, e.g. for change or a cash count.
+#[tauri::command]
+pub fn open_cash_drawer(
+    audit: State<'_, AuditLog>,
+    db: State<'_, PrinterDatabase>,
+    printer_name: String,
+    profile: PrinterProfile,
+    operator_name: String,
+    reason: Option<String>,
+) -> Result<String, String> {
+    let profile = db.resolve(&profile);
+    kick_drawer(&printer_name, &profile)?;
+    audit.record(&operator_name, "cash_drawer_opened", reason.as_deref().unwrap_or("manual"));
+    Ok("Cash drawer opened".to_string())
+}
 
-#[cfg(windows)]
-fn print_to_windows_printer(printer_name: &str, data: &[u8]) -> Result<(), String> {
-    unsafe {
-        let name = CString::new(printer_name).map_err(|_| "Invalid printer name")?;
-        let mut handle: HANDLE = ptr::null_mut();
+/// Sends ESC p on its own. Skips the status check in `print_bytes`, since a
+/// printer that is out of paper can still open its drawer.
+fn kick_drawer(printer_name: &str, profile: &PrinterProfile) -> Result<(), String> {
+    let mut p = EscPos::new(profile);
+    p.kick_drawer();
+    transport::open(printer_name)?.write_all(&p.finish())
+}
 
-        let mut defaults = PRINTER_DEFAULTSA {
-            pDataType: ptr::null_mut(),
-            pDevMode: ptr::null_mut(),
-            DesiredAccess: PRINTER_ACCESS_USE,
-        };
+/// Prints a copy of a receipt from the local history with a "NAKALA / COPY"
+/// banner. Every reprint is logged against the operator, even if the printer
+/// then fails.
+#[tauri::command]
+pub fn reprint_receipt(
+    app: AppHandle,
+    queue: State<'_, PrintQueue>,
+    history: State<'_, ReceiptHistory>,
+    audit: State<'_, AuditLog>,
+    request: ReprintReceiptRequest,
+) -> Result<PrintResult, String> {
+    let profile = resolve_profile(&app, &request.profile);
+    let record = history.log_reprint(&request.receipt_number, &request.operator_name, &request.printer_name)?;
+    let reprint = record.reprints.last().ok_or("Reprint was not logged")?;
+
+    let copy = ReceiptCopy {
+        number: record.reprints.len(),
+        reprinted_at: receipt_history::format_local(reprint.reprinted_at),
+    };
+    audit.record(
+        &reprint.operator_name,
+        "receipt_reprinted",
+        &format!("Receipt {} copy {}", record.receipt_number, copy.number),
+    );
+
+    let logo = load_logo(&app, &profile);
+    let escpos = generate_escpos_receipt(&record.receipt_data, &profile, logo.as_ref(), Some(&copy));
+
+    let description = format!("Receipt {} (copy {})", record.receipt_number, copy.number);
+    let job = queue.enqueue(&request.printer_name, &profile, escpos, &description)?;
+    print_queue::process_job(&app, &job.id)
+}
 
-        if OpenPrinterA(name.as_ptr() as *mut i8, &mut handle, &mut defaults) == 0 {
-            return Err("Failed to open printer".into());
-        }
+/// Renders the receipt exactly as `print_receipt` would send it, as text and
+/// as a PNG, without touching the printer.
+#[tauri::command]
+pub fn preview_receipt(app: AppHandle, request: PrintReceiptRequest) -> Result<RenderedPreview, String> {
+    let profile = resolve_profile(&app, &request.profile);
+    let logo = load_logo(&app, &profile);
+    let escpos = generate_escpos_receipt(&request.receipt_data, &profile, logo.as_ref(), None);
+    preview::render(&escpos, &profile)
+}
 
-        let doc_name = CString::new("Parking Receipt").unwrap();
-        let doc_type = CString::new("RAW").unwrap();
+#[tauri::command]
+pub fn get_printer_status(
+    db: State<'_, PrinterDatabase>,
+    printer_name: String,
+    profile: PrinterProfile,
+) -> Result<PrinterStatus, String> {
+    let profile = db.resolve(&profile);
+    transport::open(&printer_name)?
+        .status(profile.status_query)?
+        .ok_or_else(|| format!("{} did not report its status", printer_name))
+}
 
-        let mut info = DOC_INFO_1A {
-            pDocName: doc_name.as_ptr() as *mut i8,
-            pOutputFile: ptr::null_mut(),
-            pDatatype: doc_type.as_ptr() as *mut i8,
-        };
+/// Writes the profile's logo into the printer's NV graphics memory under
+/// `key`. Only needed once per printer; afterwards set `logo.nv_key` in the
+/// profile so receipts reference the stored copy.
+#[tauri::command]
+pub fn store_logo_in_printer(
+    app: AppHandle,
+    printer_name: String,
+    profile: PrinterProfile,
+    key: String,
+) -> Result<String, String> {
+    let profile = resolve_profile(&app, &profile);
+    let options = profile.logo.clone().unwrap_or_default();
+    let key = logo::nv_key(&key)?;
+
+    let path = app.path()
+        .resolve(&options.resource, BaseDirectory::Resource)
+        .map_err(|e| format!("Failed to resolve logo resource: {}", e))?;
+    let bitmap = logo::load_bitmap(&path, profile.dot_width as usize)?;
+
+    let message = format!("Logo stored in {} NV memory", printer_name);
+    print_bytes(&printer_name, &logo::define_nv_graphics_commands(&bitmap, key)?, &profile, &message)
+        .map(|r| r.message)
+}
 
-        if StartDocPrinterA(handle, 1, &mut info as *mut _ as *mut u8) == 0 {
-            ClosePrinter(handle);
-            return Err("Failed to start print job".into());
-        }
+#[tauri::command]
+pub fn list_printer_models(db: State<'_, PrinterDatabase>) -> Vec<PrinterModel> {
+    db.models()
+}
+
+/// Adds a model to the booth's printer database, or replaces one by id.
+#[tauri::command]
+pub fn save_printer_model(db: State<'_, PrinterDatabase>, model: PrinterModel) -> Result<(), String> {
+    db.save_model(model)
+}
 
-        StartPagePrinter(handle);
+pub(crate) fn resolve_profile(app: &AppHandle, profile: &PrinterProfile) -> PrinterProfile {
+    app.state::<PrinterDatabase>().resolve(profile)
+}
 
-        let mut written = 0;
-        WritePrinter(
-            handle,
-            data.as_ptr() as *mut _,
-            data.len() as u32,
-            &mut written,
-        );
+/// A missing or unreadable logo shouldn't stop the receipt from printing.
+pub(crate) fn load_logo(app: &AppHandle, profile: &PrinterProfile) -> Option<Logo> {
+    let options = profile.logo.as_ref()?;
 
-        EndPagePrinter(handle);
-        EndDocPrinter(handle);
-        ClosePrinter(handle);
+    if let Some(key) = &options.nv_key {
+        match logo::nv_key(key) {
+            Ok(key) => return Some(Logo::Nv(key)),
+            Err(e) => println!("[Rust] {}, sending logo bitmap instead", e),
+        }
+    }
+
+    let loaded = app.path()
+        .resolve(&options.resource, BaseDirectory::Resource)
+        .map_err(|e| format!("Failed to resolve logo resource: {}", e))
+        .and_then(|path| logo::load_bitmap(&path, profile.dot_width as usize));
 
-        Ok(())
+    match loaded {
+        Ok(bitmap) => Some(Logo::Raster(bitmap)),
+        Err(e) => {
+            println!("[Rust] Printing without logo: {}", e);
+            None
+        }
     }
 }
 
-#[cfg(windows)]
-fn list_windows_printers() -> Result<Vec<String>, String> {
-    unsafe {
-        let mut needed = 0;
-        let mut returned = 0;
-
-        EnumPrintersA(
-            PRINTER_ENUM_LOCAL | PRINTER_ENUM_CONNECTIONS,
-            ptr::null_mut(),
-            1,
-            ptr::null_mut(),
-            0,
-            &mut needed,
-            &mut returned,
-        );
-
-        if needed == 0 {
-            return Ok(vec![]);
-        }
+/// Printer names only, for the original printer picker.
+#[tauri::command]
+pub fn get_available_printers() -> Result<Vec<String>, String> {
+    Ok(transport::discover_printers().into_iter().map(|p| p.name).collect())
+}
 
-        let mut buffer = vec![0u8; needed as usize];
-
-        EnumPrintersA(
-            PRINTER_ENUM_LOCAL | PRINTER_ENUM_CONNECTIONS,
-            ptr::null_mut(),
-            1,
-            buffer.as_mut_ptr(),
-            needed,
-            &mut needed,
-            &mut returned,
-        );
-
-        let mut printers = Vec::new();
-        let mut offset = 0;
-
-        for _ in 0..returned {
-            let info = buffer.as_ptr().add(offset) as *const PRINTER_INFO_1A;
-            let name = CString::from_raw((*info).pName as *mut i8)
-                .to_string_lossy()
-                .to_string();
-            printers.push(name);
-            offset += std::mem::size_of::<PRINTER_INFO_1A>();
-        }
+#[tauri::command]
+pub fn discover_printers() -> Vec<DiscoveredPrinter> {
+    transport::discover_printers()
+}
 
-        Ok(printers)
-    }
+#[tauri::command]
+pub fn get_preferred_printer(
+    preferences: State<'_, PrinterPreferences>,
+    booth_id: String,
+) -> Option<PreferredPrinter> {
+    preferences.get(&booth_id)
 }
 
-/* ───────────────────────── ESC/POS QR CODE ───────────────────────── */
-
-fn generate_qr_code_commands(data: &str) -> Vec<u8> {
-    let bytes = data.as_bytes();
-    let len = bytes.len();
-    let pl = ((len + 3) % 256) as u8;
-    let ph = ((len + 3) / 256) as u8;
-
-    let mut c = Vec::new();
-    c.extend_from_slice(&[0x1D, 0x28, 0x6B, 0x04, 0x00, 0x31, 0x41, 0x32, 0x00]);
-    c.extend_from_slice(&[0x1D, 0x28, 0x6B, 0x03, 0x00, 0x31, 0x43, 0x08]);
-    c.extend_from_slice(&[0x1D, 0x28, 0x6B, 0x03, 0x00, 0x31, 0x45, 0x31]);
-    c.extend_from_slice(&[0x1D, 0x28, 0x6B, pl, ph, 0x31, 0x50, 0x30]);
-    c.extend_from_slice(bytes);
-    c.extend_from_slice(&[0x1D, 0x28, 0x6B, 0x03, 0x00, 0x31, 0x51, 0x30]);
-    c
+#[tauri::command]
+pub fn set_preferred_printer(
+    preferences: State<'_, PrinterPreferences>,
+    booth_id: String,
+    printer_name: String,
+    profile: Option<PrinterProfile>,
+) -> Result<PreferredPrinter, String> {
+    preferences.set(&booth_id, &printer_name, profile.unwrap_or_default())
 }
 
 /* ───────────────────────── ESC/POS RECEIPT ───────────────────────── */
 
-fn generate_escpos_receipt(d: &serde_json::Value) -> Vec<u8> {
-    let mut c = Vec::new();
+fn generate_escpos_receipt(
+    d: &serde_json::Value,
+    profile: &PrinterProfile,
+    logo: Option<&Logo>,
+    copy: Option<&ReceiptCopy>,
+) -> Vec<u8> {
+    let mut p = EscPos::new(profile);
 
     // INIT
-    c.extend_from_slice(&[0x1B, 0x40]);
-    c.extend_from_slice(&[0x1B, 0x61, 0x01]);
+    p.init().align(Align::Center);
+
+    // LOGO
+    if let Some(logo) = logo {
+        p.logo(logo);
+    }
 
     // HEADER
-    c.extend_from_slice(&[0x1B, 0x21, 0x10]);
-    c.extend_from_slice(d.get("company_name")
+    p.double_height(true);
+    p.line(d.get("company_name")
         .and_then(|v| v.as_str())
-        .unwrap_or("CHATO DISTRICT COUNCIL")
-        .as_bytes());
-    c.push(0x0A);
+        .unwrap_or("CHATO DISTRICT COUNCIL"));
 
-    c.extend_from_slice(&[0x1B, 0x21, 0x00]);
-    c.extend_from_slice(d.get("company_subtitle")
+    p.double_height(false);
+    p.line(d.get("company_subtitle")
         .and_then(|v| v.as_str())
-        .unwrap_or("STAKABADHI YA MALIPO")
-        .as_bytes());
-    c.push(0x0A);
+        .unwrap_or("STAKABADHI YA MALIPO"));
 
-    c.extend_from_slice("=".repeat(40).as_bytes());
-    c.push(0x0A);
+    p.rule('=');
+
+    // COPY BANNER
+    if let Some(copy) = copy {
+        p.double_height(true).bold(true);
+        p.line("NAKALA / COPY");
+        p.bold(false).double_height(false);
+        p.line(&format!("Nakala Na. {}  {}", copy.number, copy.reprinted_at));
+        p.rule('=');
+    }
 
     // LEFT ALIGN
-    c.extend_from_slice(&[0x1B, 0x61, 0x00]);
+    p.align(Align::Left);
 
     macro_rules! line {
         ($label:expr, $key:expr) => {
             if let Some(v) = d.get($key).and_then(|v| v.as_str()) {
-                c.extend_from_slice($label);
-                c.extend_from_slice(v.as_bytes());
-                c.push(0x0A);
+                p.text($label).line(v);
             }
         };
     }
 
-    line!(b"Risiti: ", "receipt_number");
-    line!(b"Namba ya Gari: ", "plate_number");
-    line!(b"Muda wa Kuingia: ", "entry_time");
-    line!(b"Muda wa Kutoka: ", "exit_time");
+    line!("Risiti: ", "receipt_number");
+    line!("Namba ya Gari: ", "plate_number");
+    line!("Muda wa Kuingia: ", "entry_time");
+    line!("Muda wa Kutoka: ", "exit_time");
 
-    c.extend_from_slice("-".repeat(40).as_bytes());
-    c.push(0x0A);
+    p.rule('-');
 
     // TABLE HEADER
-    c.extend_from_slice(b"MAELEZO              SIKU     KIASI\n");
-    c.extend_from_slice("-".repeat(40).as_bytes());
-    c.push(0x0A);
+    p.line("MAELEZO              SIKU     KIASI");
+    p.rule('-');
 
     // TABLE ROW
     let desc = d.get("item_description").and_then(|v| v.as_str()).unwrap_or("-");
@@ -222,58 +412,168 @@ fn generate_escpos_receipt(d: &serde_json::Value) -> Vec<u8> {
         .and_then(|v| v.as_str())
         .unwrap_or("0");
 
-    c.extend_from_slice(format!("{:<20}{:>6}{:>14}", desc, siku, amount).as_bytes());
-    c.push(0x0A);
+    p.line(&format!("{:<20}{:>6}{:>14}", desc, siku, amount));
 
-    c.extend_from_slice("=".repeat(40).as_bytes());
-    c.push(0x0A);
+    p.rule('=');
 
     // TOTAL
-    c.extend_from_slice(&[0x1B, 0x61, 0x01, 0x1B, 0x45, 0x01]);
-    c.extend_from_slice(format!("JUMLA: TZS {}", amount).as_bytes());
-    c.extend_from_slice(&[0x1B, 0x45, 0x00]);
-    c.push(0x0A);
+    p.align(Align::Center).bold(true);
+    p.text(&format!("JUMLA: TZS {}", amount));
+    p.bold(false).newline();
 
     // QR
     if let Some(qr) = d.get("qr_code_data").and_then(|v| v.as_str()) {
-        c.push(0x0A);
-        c.extend_from_slice(b"LIPIA KWA TIGOPESA\n");
-        c.extend_from_slice(&generate_qr_code_commands(qr));
-        c.push(0x0A);
+        p.newline();
+        p.line("LIPIA KWA TIGOPESA");
+        p.qr(qr);
+        p.newline();
 
         if let Some(n) = d.get("tigopesa_number").and_then(|v| v.as_str()) {
-            c.extend_from_slice(b"Lipa Namba: ");
-            c.extend_from_slice(n.as_bytes());
-            c.push(0x0A);
+            p.text("Lipa Namba: ").line(n);
+        }
+    }
+
+    // GePG BILL
+    if let Some(control_number) = d.get("gepg_control_number").and_then(|v| v.as_str()) {
+        p.newline();
+        p.bold(true).line("LIPIA KWA GePG").bold(false);
+        p.text("Namba ya Malipo: ").line(control_number);
+        line!("Lipa kabla ya: ", "gepg_expires");
+        line!("Hali: ", "gepg_status");
+        if let Some(qr) = d.get("gepg_qr_data").and_then(|v| v.as_str()) {
+            p.qr(qr);
         }
+        p.newline();
+    }
+
+    // TRA FISCAL RECEIPT
+    if let Some(url) = d.get("tra_verification_url").and_then(|v| v.as_str()) {
+        p.newline();
+        p.bold(true).line("RISITI YA TRA").bold(false);
+        line!("Namba ya Uhakiki: ", "tra_verification_code");
+        line!("Z-Namba: ", "tra_znum");
+        p.qr(url);
+        p.newline();
     }
 
     // OPERATOR + LOCATION
-    c.push(0x0A);
-    line!(b"Mpokea Fedha: ", "operator_name");
-    line!(b"Lango: ", "location");
+    p.newline();
+    line!("Mpokea Fedha: ", "operator_name");
+    line!("Lango: ", "location");
+
+    // BARCODE (receipt number, and the entry ticket when known)
+    p.align(Align::Center);
+    if let Some(code) = d.get("receipt_id")
+        .or_else(|| d.get("receipt_number"))
+        .and_then(|v| v.as_str())
+    {
+        p.newline();
+        p.barcode(code);
+        p.newline();
+    }
+
+    if let Some(ticket) = d.get("ticket_id").and_then(|v| v.as_str()) {
+        p.newline();
+        p.line("Tiketi ya Kuingia");
+        p.barcode(ticket);
+        p.newline();
+    }
 
     // FOOTER (BOTTOM)
-    c.push(0x0A);
-    c.push(0x0A);
+    p.feed(2);
 
-    c.extend_from_slice(&[0x1B, 0x61, 0x01]);
-    c.extend_from_slice("=".repeat(40).as_bytes());
-    c.push(0x0A);
+    p.align(Align::Center);
+    p.rule('=');
 
-    c.extend_from_slice(&[0x1B, 0x21, 0x10]);
-    c.extend_from_slice(b"MWISHO WA STAKABADHI");
-    c.push(0x0A);
+    p.double_height(true);
+    p.line(if copy.is_some() { "NAKALA / COPY" } else { "MWISHO WA STAKABADHI" });
 
-    c.extend_from_slice(&[0x1B, 0x21, 0x00]);
-    c.extend_from_slice("=".repeat(40).as_bytes());
-    c.push(0x0A);
+    p.double_height(false);
+    p.rule('=');
 
     // FEED + CUT
-    c.push(0x0A);
-    c.push(0x0A);
-    c.push(0x0A);
-    c.extend_from_slice(&[0x1D, 0x56, 0x00]);
+    p.feed(3);
+    p.cut();
+
+    p.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::escpos::preview::Preview;
+
+    #[test]
+    fn receipt_layout_matches_golden_text() {
+        let data = serde_json::json!({
+            "receipt_number": "RCP-0042",
+            "plate_number": "T 123 ABC",
+            "entry_time": "2024-05-01 08:15",
+            "exit_time": "2024-05-01 17:40",
+            "item_description": "Maegesho ya Gari",
+            "item_quantity": "1.0",
+            "item_amount": "2,000",
+            "qr_code_data": "*150*01*45107230*2000#",
+            "tigopesa_number": "45107230",
+            "operator_name": "Asha",
+            "location": "Lango Kuu",
+        });
+        let profile = PrinterProfile::default();
+        let escpos = generate_escpos_receipt(&data, &profile, None, None);
+
+        let golden = GOLDEN_RECEIPT.trim_start_matches('\n');
+        assert_eq!(Preview::parse(&escpos, &profile).to_text(), golden);
+    }
+
+    #[test]
+    fn reprint_carries_copy_banner() {
+        let data = serde_json::json!({ "receipt_number": "RCP-0042" });
+        let profile = PrinterProfile::default();
+        let copy = ReceiptCopy {
+            number: 2,
+            reprinted_at: "2024-05-02 09:30".to_string(),
+        };
+        let escpos = generate_escpos_receipt(&data, &profile, None, Some(&copy));
+        let text = Preview::parse(&escpos, &profile).to_text();
+
+        assert!(text.contains("                 NAKALA / COPY\n"));
+        assert!(text.contains("         Nakala Na. 2  2024-05-02 09:30\n"));
+        assert!(!text.contains("MWISHO WA STAKABADHI"));
+    }
+
+    const GOLDEN_RECEIPT: &str = r#"
+             CHATO DISTRICT COUNCIL
+              STAKABADHI YA MALIPO
+    ========================================
+Risiti: RCP-0042
+Namba ya Gari: T 123 ABC
+Muda wa Kuingia: 2024-05-01 08:15
+Muda wa Kutoka: 2024-05-01 17:40
+----------------------------------------
+MAELEZO              SIKU     KIASI
+----------------------------------------
+Maegesho ya Gari       1.0         2,000
+========================================
+                JUMLA: TZS 2,000
+
+               LIPIA KWA TIGOPESA
+          [QR *150*01*45107230*2000#]
+
+              Lipa Namba: 45107230
+
+               Mpokea Fedha: Asha
+                Lango: Lango Kuu
+
+               [BARCODE RCP-0042]
+
+
+
+    ========================================
+              MWISHO WA STAKABADHI
+    ========================================
+
+
 
-    c
+--------------------- CUT ----------------------
+"#;
 }
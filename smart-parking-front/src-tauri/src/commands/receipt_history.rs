@@ -0,0 +1,45 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use crate::escpos::preview::{self, RenderedPreview};
+use crate::escpos::models::PrinterDatabase;
+use crate::escpos::PrinterProfile;
+use crate::receipt_history::{ReceiptHistory, ReceiptQuery, ReceiptRecord};
+
+/// A receipt from the history with the bytes that were actually printed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StoredReceipt {
+    pub record: ReceiptRecord,
+    pub escpos_base64: String,
+    pub preview: RenderedPreview,
+}
+
+#[tauri::command]
+pub fn search_receipts(
+    history: State<'_, ReceiptHistory>,
+    query: ReceiptQuery,
+) -> Result<Vec<ReceiptRecord>, String> {
+    history.search(&query)
+}
+
+/// Loads a receipt for a dispute, failing if the stored bytes no longer match
+/// the hash taken when it was printed.
+#[tauri::command]
+pub fn get_receipt(
+    history: State<'_, ReceiptHistory>,
+    db: State<'_, PrinterDatabase>,
+    receipt_number: String,
+    profile: Option<PrinterProfile>,
+) -> Result<StoredReceipt, String> {
+    let record = history.get(&receipt_number)?;
+    let escpos = history.escpos(&record)?;
+    let preview = preview::render(&escpos, &db.resolve(&profile.unwrap_or_default()))?;
+
+    Ok(StoredReceipt {
+        escpos_base64: BASE64.encode(&escpos),
+        record,
+        preview,
+    })
+}
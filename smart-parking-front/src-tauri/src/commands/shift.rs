@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, State};
+
+use crate::audit::AuditLog;
+use crate::commands::printer::resolve_profile;
+use crate::escpos::{Align, EscPos, PrinterProfile};
+use crate::print_queue::{self, PrintQueue};
+use crate::receipt_history::format_local;
+use crate::shifts::{format_tzs, Shift, ShiftSummary, Shifts, Tally};
+use crate::util::now_secs;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CloseShiftRequest {
+    pub shift_id: String,
+    /// Cash counted in the drawer, opening float included.
+    pub counted_cash: f64,
+    pub notes: Option<String>,
+    pub printer: Option<ReportPrinter>,
+}
+
+/// Where to print a shift report.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReportPrinter {
+    pub printer_name: String,
+    #[serde(default)]
+    pub profile: PrinterProfile,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReportKind {
+    /// Interim figures; the shift stays open.
+    X,
+    /// Closing figures with the cash count.
+    Z,
+}
+
+#[tauri::command]
+pub fn open_shift(
+    shifts: State<'_, Shifts>,
+    audit: State<'_, AuditLog>,
+    booth_id: String,
+    operator_name: String,
+    opening_float: f64,
+) -> Result<Shift, String> {
+    let shift = shifts.open(&booth_id, &operator_name, opening_float)?;
+    audit.record(&operator_name, "shift_opened", &format!("{} float {}", shift.id, format_tzs(opening_float)));
+    Ok(shift)
+}
+
+#[tauri::command]
+pub fn get_current_shift(shifts: State<'_, Shifts>, booth_id: String) -> Option<ShiftSummary> {
+    shifts.current(&booth_id)
+}
+
+#[tauri::command]
+pub fn get_shift(shifts: State<'_, Shifts>, shift_id: String) -> Result<ShiftSummary, String> {
+    shifts.summary(&shift_id)
+}
+
+#[tauri::command]
+pub fn list_shifts(shifts: State<'_, Shifts>, booth_id: Option<String>, limit: Option<usize>) -> Vec<Shift> {
+    shifts.list(booth_id.as_deref(), limit.unwrap_or(50))
+}
+
+/// Prints the figures so far without closing the shift.
+#[tauri::command]
+pub fn print_x_report(
+    app: AppHandle,
+    queue: State<'_, PrintQueue>,
+    shifts: State<'_, Shifts>,
+    shift_id: String,
+    printer: ReportPrinter,
+) -> Result<ShiftSummary, String> {
+    let summary = shifts.x_report(&shift_id)?;
+    print_report(&app, &queue, &summary, ReportKind::X, &printer)?;
+    Ok(summary)
+}
+
+/// Closes the shift with the cash counted in the drawer and prints the Z
+/// report. The shift stays closed even if the printer fails; the report is
+/// left in the print queue.
+#[tauri::command]
+pub fn close_shift(
+    app: AppHandle,
+    queue: State<'_, PrintQueue>,
+    shifts: State<'_, Shifts>,
+    audit: State<'_, AuditLog>,
+    request: CloseShiftRequest,
+) -> Result<ShiftSummary, String> {
+    let shift_id = request.shift_id;
+    let summary = shifts.close(&shift_id, request.counted_cash, request.notes)?;
+    audit.record(
+        &summary.shift.operator_name,
+        "shift_closed",
+        &format!(
+            "{} Z {} counted {} variance {}",
+            shift_id,
+            summary.shift.z_number.unwrap_or_default(),
+            format_tzs(request.counted_cash),
+            format_tzs(summary.variance.unwrap_or_default())
+        ),
+    );
+
+    if let Some(printer) = request.printer {
+        if let Err(e) = print_report(&app, &queue, &summary, ReportKind::Z, &printer) {
+            println!("[Rust] Z report for {} not printed: {}", shift_id, e);
+        }
+    }
+    Ok(summary)
+}
+
+fn print_report(
+    app: &AppHandle,
+    queue: &PrintQueue,
+    summary: &ShiftSummary,
+    kind: ReportKind,
+    printer: &ReportPrinter,
+) -> Result<(), String> {
+    let profile = resolve_profile(app, &printer.profile);
+    let escpos = generate_shift_report(summary, kind, &profile);
+
+    let description = format!("{:?} report {}", kind, summary.shift.id);
+    let job = queue.enqueue(&printer.printer_name, &profile, escpos, &description)?;
+    print_queue::process_job(app, &job.id).map(|_| ())
+}
+
+/* ───────────────────────── ESC/POS SHIFT REPORT ───────────────────────── */
+
+fn generate_shift_report(s: &ShiftSummary, kind: ReportKind, profile: &PrinterProfile) -> Vec<u8> {
+    let mut p = EscPos::new(profile);
+    let shift = &s.shift;
+
+    // INIT
+    p.init().align(Align::Center);
+
+    // HEADER
+    p.double_height(true);
+    p.line("CHATO DISTRICT COUNCIL");
+    p.bold(true);
+    p.line(match kind {
+        ReportKind::X => "X-RIPOTI YA ZAMU",
+        ReportKind::Z => "Z-RIPOTI / KUFUNGA ZAMU",
+    });
+    p.bold(false).double_height(false);
+    p.rule('=');
+
+    // SHIFT
+    p.align(Align::Left);
+    if let Some(z) = shift.z_number {
+        p.text("Z-Namba: ").line(&z.to_string());
+    }
+    p.text("Zamu: ").line(&shift.id);
+    p.text("Mpokea Fedha: ").line(&shift.operator_name);
+    p.text("Kibanda: ").line(&shift.booth_id);
+    p.text("Imeanza: ").line(&format_local(shift.opened_at));
+    match shift.closed_at {
+        Some(at) => p.text("Imefungwa: ").line(&format_local(at)),
+        None => p.text("Muda wa Ripoti: ").line(&format_local(now_secs())),
+    };
+
+    // TOTALS
+    tallies(&mut p, "NJIA YA MALIPO", &s.by_payment_method);
+    tallies(&mut p, "AINA YA GARI", &s.by_vehicle_type);
+
+    p.rule('=');
+    p.line(&format!("{:<20}{:>6}{:>14}", "RISITI", s.receipts, format_tzs(s.total)));
+    p.bold(true);
+    p.line(&format!("JUMLA: TZS {}", format_tzs(s.total)));
+    p.bold(false);
+
+    // CASH
+    p.rule('-');
+    p.bold(true).line("FEDHA TASLIMU").bold(false);
+    let cash = s.by_payment_method.get("cash").map_or(0.0, |t| t.amount);
+    amount_line(&mut p, "Kianzio", shift.opening_float);
+    amount_line(&mut p, "Mauzo Taslimu", cash);
+    amount_line(&mut p, "Inayotarajiwa", s.expected_cash);
+    if let (Some(counted), Some(variance)) = (shift.counted_cash, s.variance) {
+        amount_line(&mut p, "Iliyohesabiwa", counted);
+        p.bold(true);
+        amount_line(&mut p, "Tofauti", variance);
+        p.bold(false);
+    }
+    if let Some(notes) = &shift.notes {
+        p.text("Maelezo: ").line(notes);
+    }
+
+    // SIGNATURE (Z only)
+    if kind == ReportKind::Z {
+        p.feed(2);
+        p.line("Sahihi ya Mpokea Fedha: ____________");
+        p.feed(1);
+        p.line("Sahihi ya Msimamizi:    ____________");
+    }
+
+    // FOOTER
+    p.feed(2);
+    p.align(Align::Center);
+    p.rule('=');
+    p.line(match kind {
+        ReportKind::X => "ZAMU BADO IKO WAZI",
+        ReportKind::Z => "MWISHO WA ZAMU",
+    });
+    p.rule('=');
+
+    // FEED + CUT
+    p.feed(3);
+    p.cut();
+
+    p.finish()
+}
+
+fn tallies(p: &mut EscPos, title: &str, rows: &BTreeMap<String, Tally>) {
+    p.rule('-');
+    p.line(&format!("{:<20}{:>6}{:>14}", title, "IDADI", "KIASI"));
+    p.rule('-');
+    for (name, tally) in rows {
+        let name: String = name.chars().take(19).collect();
+        p.line(&format!("{:<20}{:>6}{:>14}", name, tally.count, format_tzs(tally.amount)));
+    }
+}
+
+fn amount_line(p: &mut EscPos, label: &str, amount: f64) {
+    p.line(&format!("{:<26}{:>14}", label, format_tzs(amount)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::escpos::preview::Preview;
+
+    #[test]
+    fn z_report_shows_totals_and_variance() {
+        let path = std::env::temp_dir().join(format!("shift-report-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let shifts = Shifts::load(path).unwrap();
+        let shift = shifts.open("B1", "Asha", 10_000.0).unwrap();
+        let sale = |amount: &str, method: &str| {
+            serde_json::json!({ "total_amount": amount, "payment_method": method, "vehicle_type": "Bus" })
+        };
+        shifts.record_sale(None, Some("B1"), "R1", &sale("2,000", "cash")).unwrap();
+        shifts.record_sale(None, Some("B1"), "R2", &sale("3,000", "tigopesa")).unwrap();
+        let summary = shifts.close(&shift.id, 11_500.0, None).unwrap();
+
+        let profile = PrinterProfile::default();
+        let text = Preview::parse(&generate_shift_report(&summary, ReportKind::Z, &profile), &profile).to_text();
+
+        assert!(text.contains("Z-RIPOTI / KUFUNGA ZAMU"));
+        assert!(text.contains("\ncash                     1         2,000\n"));
+        assert!(text.contains("\ntigopesa                 1         3,000\n"));
+        assert!(text.contains("\nBus                      2         5,000\n"));
+        assert!(text.contains("\nInayotarajiwa                     12,000\n"));
+        assert!(text.contains("\nTofauti                             -500\n"));
+        assert!(text.contains("MWISHO WA ZAMU"));
+    }
+}
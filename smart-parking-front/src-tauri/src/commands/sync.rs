@@ -0,0 +1,71 @@
+use tauri::{AppHandle, State};
+
+use crate::database::Database;
+use crate::sync_queue::{self, SyncConfig, SyncEvent, SyncQueue, SyncStatus};
+
+/// The API token stays on the desktop; the webview gets it blank.
+#[tauri::command]
+pub fn get_sync_config(db: State<'_, Database>, queue: State<'_, SyncQueue>) -> SyncConfig {
+    let mut config = queue.config(&db);
+    config.api_token.clear();
+    config
+}
+
+/// A blank token keeps the one already saved.
+#[tauri::command]
+pub fn save_sync_config(
+    app: AppHandle,
+    db: State<'_, Database>,
+    queue: State<'_, SyncQueue>,
+    mut config: SyncConfig,
+) -> Result<(), String> {
+    if config.api_token.is_empty() {
+        config.api_token = queue.config(&db).api_token;
+    }
+    queue.save_config(&db, &config)?;
+    sync_queue::sync_soon(&app);
+    Ok(())
+}
+
+/// Queue depth and when the API last took anything.
+#[tauri::command]
+pub fn get_sync_status(db: State<'_, Database>, queue: State<'_, SyncQueue>) -> Result<SyncStatus, String> {
+    queue.status(&db)
+}
+
+/// Replays the queue now rather than waiting for the worker.
+#[tauri::command]
+pub fn sync_now(app: AppHandle, db: State<'_, Database>, queue: State<'_, SyncQueue>) -> Result<SyncStatus, String> {
+    sync_queue::replay_and_emit(&app);
+    queue.status(&db)
+}
+
+/// Events the API refused or kept failing on.
+#[tauri::command]
+pub fn list_sync_issues(db: State<'_, Database>, queue: State<'_, SyncQueue>) -> Result<Vec<SyncEvent>, String> {
+    queue.issues(&db)
+}
+
+#[tauri::command]
+pub fn retry_sync_event(
+    app: AppHandle,
+    db: State<'_, Database>,
+    queue: State<'_, SyncQueue>,
+    event_id: i64,
+) -> Result<SyncEvent, String> {
+    let event = queue.retry(&db, event_id)?;
+    sync_queue::sync_soon(&app);
+    Ok(event)
+}
+
+#[tauri::command]
+pub fn discard_sync_event(
+    app: AppHandle,
+    db: State<'_, Database>,
+    queue: State<'_, SyncQueue>,
+    event_id: i64,
+) -> Result<SyncEvent, String> {
+    let event = queue.discard(&db, event_id)?;
+    sync_queue::emit_status(&app);
+    Ok(event)
+}
@@ -0,0 +1,36 @@
+use tauri::State;
+
+use crate::database::Database;
+use crate::sync_queue::SyncQueue;
+use crate::tariff::{self, Fee, TariffSet};
+use crate::util::now_secs;
+
+#[tauri::command]
+pub fn get_tariffs(db: State<'_, Database>) -> Result<TariffSet, String> {
+    tariff::cached(&db)
+}
+
+/// Fetches the current rules from the API, keeping the cached ones if it
+/// can't be reached.
+#[tauri::command]
+pub fn refresh_tariffs(db: State<'_, Database>, queue: State<'_, SyncQueue>) -> Result<TariffSet, String> {
+    tariff::refresh(&db, &queue.config(&db))
+}
+
+/// What a parked vehicle owes if it leaves at `exit_time` (now by default).
+#[tauri::command]
+pub fn quote_parking_fee(db: State<'_, Database>, session_id: i64, exit_time: Option<u64>) -> Result<Fee, String> {
+    let session = db.get_session(session_id)?;
+    tariff::quote(&db, &session, exit_time.unwrap_or_else(now_secs))
+}
+
+/// The fee for any stay, e.g. to answer a driver asking before they park.
+#[tauri::command]
+pub fn calculate_parking_fee(
+    db: State<'_, Database>,
+    vehicle_type: String,
+    entry_time: u64,
+    exit_time: u64,
+) -> Result<Fee, String> {
+    tariff::cached(&db)?.calculate(&vehicle_type, entry_time, exit_time)
+}
@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+use crate::audit::AuditLog;
+use crate::commands::printer::{load_logo, resolve_profile};
+use crate::database::Database;
+use crate::escpos::{Align, EscPos, Logo, PrinterProfile};
+use crate::numbering;
+use crate::print_queue::{self, PrintQueue};
+use crate::transport::PrintResult;
+
+/// What the exit lane scans to find the ticket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TicketCode {
+    #[default]
+    Barcode,
+    Qr,
+    None,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntryTicketData {
+    pub ticket_number: String,
+    pub plate_number: String,
+    pub entry_time: String,
+    pub vehicle_type: Option<String>,
+    pub gate: Option<String>,
+    pub operator_name: Option<String>,
+    pub company_name: Option<String>,
+    /// Pre-formatted tariff lines, e.g. "Bus - TZS 3,000 / Siku".
+    #[serde(default)]
+    pub tariff_summary: Vec<String>,
+    #[serde(default)]
+    pub code: TicketCode,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PrintEntryTicketRequest {
+    pub printer_name: String,
+    pub ticket: EntryTicketData,
+    #[serde(default)]
+    pub profile: PrinterProfile,
+}
+
+#[tauri::command]
+pub fn print_entry_ticket(
+    app: AppHandle,
+    queue: State<'_, PrintQueue>,
+    request: PrintEntryTicketRequest,
+) -> Result<PrintResult, String> {
+    let profile = resolve_profile(&app, &request.profile);
+    let logo = load_logo(&app, &profile);
+    let escpos = generate_entry_ticket(&request.ticket, &profile, logo.as_ref());
+
+    if let Err(e) = numbering::mark_used(&app.state::<Database>(), &request.ticket.ticket_number) {
+        let operator = request.ticket.operator_name.as_deref().unwrap_or("-");
+        app.state::<AuditLog>().record(operator, "ticket_number_unaccounted", &e);
+    }
+
+    let description = format!("Ticket {}", request.ticket.ticket_number);
+    let job = queue.enqueue(&request.printer_name, &profile, escpos, &description)?;
+    print_queue::process_job(&app, &job.id)
+}
+
+/* ───────────────────────── ESC/POS ENTRY TICKET ───────────────────────── */
+
+fn generate_entry_ticket(
+    t: &EntryTicketData,
+    profile: &PrinterProfile,
+    logo: Option<&Logo>,
+) -> Vec<u8> {
+    let mut p = EscPos::new(profile);
+
+    // INIT
+    p.init().align(Align::Center);
+
+    if let Some(logo) = logo {
+        p.logo(logo);
+    }
+
+    // HEADER
+    p.double_height(true);
+    p.line(t.company_name.as_deref().unwrap_or("CHATO DISTRICT COUNCIL"));
+    p.double_height(false);
+    p.line("TIKETI YA MAEGESHO");
+    p.rule('=');
+
+    // TICKET NUMBER
+    p.double_height(true).bold(true);
+    p.line(&t.ticket_number);
+    p.double_height(false).bold(false);
+    p.rule('-');
+
+    // DETAILS
+    p.align(Align::Left);
+    p.text("Namba ya Gari: ").line(&t.plate_number);
+    if let Some(v) = &t.vehicle_type {
+        p.text("Aina ya Gari: ").line(v);
+    }
+    p.text("Muda wa Kuingia: ").line(&t.entry_time);
+    if let Some(g) = &t.gate {
+        p.text("Lango: ").line(g);
+    }
+    if let Some(o) = &t.operator_name {
+        p.text("Mhudumu: ").line(o);
+    }
+
+    // TARIFF
+    if !t.tariff_summary.is_empty() {
+        p.rule('-');
+        p.bold(true).line("VIWANGO").bold(false);
+        for row in &t.tariff_summary {
+            p.line(row);
+        }
+    }
+
+    p.rule('=');
+
+    // SCAN CODE
+    p.align(Align::Center);
+    match t.code {
+        TicketCode::Barcode => {
+            p.newline();
+            p.barcode(&t.ticket_number);
+            p.newline();
+        }
+        TicketCode::Qr => {
+            p.newline();
+            p.qr(&t.ticket_number);
+            p.newline();
+        }
+        TicketCode::None => {}
+    }
+
+    // FOOTER
+    p.newline();
+    p.line("HIFADHI TIKETI HII");
+    p.line("Onyesha tiketi hii wakati wa kutoka");
+
+    // FEED + CUT
+    p.feed(3);
+    p.cut();
+
+    p.finish()
+}
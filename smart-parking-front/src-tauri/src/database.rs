@@ -0,0 +1,795 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rsa::rand_core::{OsRng, RngCore};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+use crate::sync_queue::{self, ENTITY_PASSAGE};
+use crate::util::{local_day_start, normalize_plate, now_secs};
+
+const DEFAULT_LIST_LIMIT: usize = 200;
+
+/// Schema changes, applied in order. `PRAGMA user_version` records how many
+/// have run, so only append to this list; never edit an entry that shipped.
+const MIGRATIONS: &[&str] = &[
+    // 1: parking sessions, gate devices, configuration
+    "CREATE TABLE parking_sessions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        local_id TEXT NOT NULL UNIQUE,
+        server_id INTEGER,
+        plate_number TEXT NOT NULL,
+        plate_key TEXT NOT NULL,
+        vehicle_type TEXT,
+        status TEXT NOT NULL DEFAULT 'parked' CHECK (status IN ('parked', 'exited', 'cancelled')),
+        entry_time INTEGER NOT NULL,
+        entry_gate_id INTEGER REFERENCES gate_devices(id) ON DELETE SET NULL,
+        entry_operator TEXT,
+        ticket_number TEXT,
+        exit_time INTEGER,
+        exit_gate_id INTEGER REFERENCES gate_devices(id) ON DELETE SET NULL,
+        exit_operator TEXT,
+        fee REAL,
+        payment_method TEXT,
+        payment_reference TEXT,
+        receipt_number TEXT,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE INDEX idx_parking_sessions_plate ON parking_sessions(plate_key);
+    CREATE INDEX idx_parking_sessions_entry_time ON parking_sessions(entry_time);
+    CREATE INDEX idx_parking_sessions_status ON parking_sessions(status);
+    -- A vehicle can only be parked once
+    CREATE UNIQUE INDEX idx_parking_sessions_parked ON parking_sessions(plate_key) WHERE status = 'parked';
+
+    CREATE TABLE gate_devices (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL UNIQUE,
+        direction TEXT NOT NULL DEFAULT 'both' CHECK (direction IN ('entry', 'exit', 'both')),
+        serial_port TEXT,
+        open_command TEXT NOT NULL DEFAULT 'hell',
+        camera_url TEXT,
+        printer_name TEXT,
+        enabled INTEGER NOT NULL DEFAULT 1,
+        updated_at INTEGER NOT NULL
+    );
+
+    CREATE TABLE config (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL,
+        updated_at INTEGER NOT NULL
+    );",
+    // 2: events waiting to be replayed to the API, oldest first
+    "CREATE TABLE desktop_sync_queue (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        entity_type TEXT NOT NULL,
+        entity_id TEXT NOT NULL,
+        action TEXT NOT NULL,
+        data TEXT NOT NULL,
+        idempotency_key TEXT NOT NULL UNIQUE,
+        status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'conflict', 'failed')),
+        sync_attempts INTEGER NOT NULL DEFAULT 0,
+        last_sync_attempt INTEGER,
+        next_attempt_at INTEGER NOT NULL,
+        sync_error TEXT,
+        created_at INTEGER NOT NULL
+    );
+    CREATE INDEX idx_desktop_sync_queue_status ON desktop_sync_queue(status, id);",
+    // 3: local receipt and ticket numbers, and every number handed out
+    "CREATE TABLE number_sequences (
+        kind TEXT NOT NULL CHECK (kind IN ('receipt', 'ticket')),
+        booth TEXT NOT NULL,
+        gate TEXT NOT NULL,
+        day TEXT NOT NULL,
+        last_value INTEGER NOT NULL,
+        PRIMARY KEY (kind, booth, gate, day)
+    );
+
+    CREATE TABLE issued_numbers (
+        number TEXT PRIMARY KEY,
+        kind TEXT NOT NULL,
+        booth TEXT NOT NULL,
+        gate TEXT NOT NULL,
+        day TEXT NOT NULL,
+        value INTEGER NOT NULL,
+        status TEXT NOT NULL DEFAULT 'issued' CHECK (status IN ('issued', 'used', 'voided')),
+        note TEXT,
+        issued_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL,
+        UNIQUE (kind, booth, gate, day, value)
+    );",
+    // 4: permit holders, staff, exempt and blacklisted plates from the API
+    "CREATE TABLE plate_lists (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        server_id INTEGER,
+        plate_number TEXT NOT NULL,
+        plate_key TEXT NOT NULL,
+        category TEXT NOT NULL CHECK (category IN ('permit', 'staff', 'government', 'blacklist')),
+        action TEXT CHECK (action IN ('deny', 'alert')),
+        holder_name TEXT,
+        reason TEXT,
+        valid_from INTEGER,
+        valid_until INTEGER,
+        synced_at INTEGER NOT NULL
+    );
+    CREATE INDEX idx_plate_lists_plate ON plate_lists(plate_key);",
+    // 5: random id of this installation, scoping its sync idempotency keys
+    "INSERT OR IGNORE INTO config (key, value, updated_at)
+     VALUES ('install_id', '\"' || lower(hex(randomblob(16))) || '\"', CAST(strftime('%s', 'now') AS INTEGER));",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStatus {
+    Parked,
+    Exited,
+    Cancelled,
+}
+
+impl SessionStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            SessionStatus::Parked => "parked",
+            SessionStatus::Exited => "exited",
+            SessionStatus::Cancelled => "cancelled",
+        }
+    }
+
+    fn parse(s: &str) -> Self {
+        match s {
+            "exited" => SessionStatus::Exited,
+            "cancelled" => SessionStatus::Cancelled,
+            _ => SessionStatus::Parked,
+        }
+    }
+}
+
+/// One vehicle's stay, from entry to exit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParkingSession {
+    pub id: i64,
+    /// Made on the desktop, so a session has an id before the API sees it.
+    pub local_id: String,
+    /// The API's id, once synced.
+    pub server_id: Option<i64>,
+    pub plate_number: String,
+    pub vehicle_type: Option<String>,
+    pub status: SessionStatus,
+    pub entry_time: u64,
+    pub entry_gate_id: Option<i64>,
+    pub entry_operator: Option<String>,
+    pub ticket_number: Option<String>,
+    pub exit_time: Option<u64>,
+    pub exit_gate_id: Option<i64>,
+    pub exit_operator: Option<String>,
+    pub fee: Option<f64>,
+    pub payment_method: Option<String>,
+    pub payment_reference: Option<String>,
+    pub receipt_number: Option<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewEntry {
+    pub plate_number: String,
+    pub vehicle_type: Option<String>,
+    pub gate_id: Option<i64>,
+    pub operator_name: Option<String>,
+    pub ticket_number: Option<String>,
+    /// Now when not given.
+    pub entry_time: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExitRecord {
+    pub session_id: i64,
+    pub gate_id: Option<i64>,
+    pub operator_name: Option<String>,
+    /// Required by `record_exit`. The `record_vehicle_exit` command works it
+    /// out from the cached tariffs when the webview leaves it out.
+    #[serde(default)]
+    pub fee: Option<f64>,
+    pub payment_method: Option<String>,
+    pub payment_reference: Option<String>,
+    pub receipt_number: Option<String>,
+    /// Now when not given.
+    pub exit_time: Option<u64>,
+}
+
+/// Filters for `list_sessions`. Dates are local `YYYY-MM-DD` on the entry
+/// time, both ends inclusive.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionQuery {
+    pub status: Option<SessionStatus>,
+    pub plate_number: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum GateDirection {
+    Entry,
+    Exit,
+    #[default]
+    Both,
+}
+
+impl GateDirection {
+    fn as_str(self) -> &'static str {
+        match self {
+            GateDirection::Entry => "entry",
+            GateDirection::Exit => "exit",
+            GateDirection::Both => "both",
+        }
+    }
+
+    fn parse(s: &str) -> Self {
+        match s {
+            "entry" => GateDirection::Entry,
+            "exit" => GateDirection::Exit,
+            _ => GateDirection::Both,
+        }
+    }
+}
+
+/// A barrier with its controller, camera and printer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GateDevice {
+    /// `None` to add a new gate.
+    #[serde(default)]
+    pub id: Option<i64>,
+    pub name: String,
+    #[serde(default)]
+    pub direction: GateDirection,
+    pub serial_port: Option<String>,
+    #[serde(default = "default_open_command")]
+    pub open_command: String,
+    pub camera_url: Option<String>,
+    pub printer_name: Option<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub updated_at: u64,
+}
+
+fn default_open_command() -> String {
+    "hell".to_string()
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+pub(crate) fn sql<T>(result: rusqlite::Result<T>, what: &str) -> Result<T, String> {
+    result.map_err(|e| format!("Failed to {}: {}", what, e))
+}
+
+/// This database's random id, made once by migration 5. A reinstalled
+/// desktop gets a new one.
+pub(crate) fn install_id(conn: &Connection) -> Result<String, String> {
+    let value: String = sql(
+        conn.query_row("SELECT value FROM config WHERE key = 'install_id'", [], |row| row.get(0)),
+        "read install id",
+    )?;
+    Ok(value.trim_matches('"').to_string())
+}
+
+/// Unique across booths without asking the API.
+pub(crate) fn new_local_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+    format!("{:016x}{:016x}", nanos, OsRng.next_u64())
+}
+
+fn session_from_row(row: &Row) -> rusqlite::Result<ParkingSession> {
+    Ok(ParkingSession {
+        id: row.get("id")?,
+        local_id: row.get("local_id")?,
+        server_id: row.get("server_id")?,
+        plate_number: row.get("plate_number")?,
+        vehicle_type: row.get("vehicle_type")?,
+        status: SessionStatus::parse(&row.get::<_, String>("status")?),
+        entry_time: row.get("entry_time")?,
+        entry_gate_id: row.get("entry_gate_id")?,
+        entry_operator: row.get("entry_operator")?,
+        ticket_number: row.get("ticket_number")?,
+        exit_time: row.get("exit_time")?,
+        exit_gate_id: row.get("exit_gate_id")?,
+        exit_operator: row.get("exit_operator")?,
+        fee: row.get("fee")?,
+        payment_method: row.get("payment_method")?,
+        payment_reference: row.get("payment_reference")?,
+        receipt_number: row.get("receipt_number")?,
+        created_at: row.get("created_at")?,
+        updated_at: row.get("updated_at")?,
+    })
+}
+
+fn gate_from_row(row: &Row) -> rusqlite::Result<GateDevice> {
+    Ok(GateDevice {
+        id: row.get("id")?,
+        name: row.get("name")?,
+        direction: GateDirection::parse(&row.get::<_, String>("direction")?),
+        serial_port: row.get("serial_port")?,
+        open_command: row.get("open_command")?,
+        camera_url: row.get("camera_url")?,
+        printer_name: row.get("printer_name")?,
+        enabled: row.get("enabled")?,
+        updated_at: row.get("updated_at")?,
+    })
+}
+
+/// The booth's own SQLite store, so entries and exits keep being recorded
+/// while the API server is down.
+pub struct Database {
+    conn: Mutex<Connection>,
+}
+
+impl Database {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let conn = sql(Connection::open(path), &format!("open {}", path.display()))?;
+        // WAL lets the sync worker read while the booth writes
+        sql(
+            conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA foreign_keys = ON; PRAGMA busy_timeout = 5000;"),
+            "configure database",
+        )?;
+        Self::from_connection(conn)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn open_in_memory() -> Result<Self, String> {
+        let conn = sql(Connection::open_in_memory(), "open database")?;
+        sql(conn.execute_batch("PRAGMA foreign_keys = ON;"), "configure database")?;
+        Self::from_connection(conn)
+    }
+
+    fn from_connection(mut conn: Connection) -> Result<Self, String> {
+        migrate(&mut conn)?;
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    /// For modules that keep their own tables in this database.
+    pub(crate) fn lock(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap()
+    }
+
+    /* ───────────── PARKING SESSIONS ───────────── */
+
+    pub fn record_entry(&self, entry: NewEntry) -> Result<ParkingSession, String> {
+        let plate_key = normalize_plate(&entry.plate_number);
+        if plate_key.is_empty() {
+            return Err("Plate number is required".to_string());
+        }
+
+        let mut conn = self.conn.lock().unwrap();
+        let tx = sql(conn.transaction(), "start entry")?;
+        let parked: Option<i64> = sql(
+            tx.query_row(
+                "SELECT id FROM parking_sessions WHERE plate_key = ?1 AND status = 'parked'",
+                params![plate_key],
+                |row| row.get(0),
+            )
+            .optional(),
+            "look up parked vehicle",
+        )?;
+        if let Some(id) = parked {
+            return Err(format!("{} is already parked (session {})", entry.plate_number.trim(), id));
+        }
+
+        let now = now_secs();
+        sql(
+            tx.execute(
+                "INSERT INTO parking_sessions (local_id, plate_number, plate_key, vehicle_type, entry_time,
+                     entry_gate_id, entry_operator, ticket_number, created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?9)",
+                params![
+                    new_local_id(),
+                    entry.plate_number.trim(),
+                    plate_key,
+                    entry.vehicle_type,
+                    entry.entry_time.unwrap_or(now),
+                    entry.gate_id,
+                    entry.operator_name,
+                    entry.ticket_number,
+                    now,
+                ],
+            ),
+            "record entry",
+        )?;
+
+        let parked = session(&tx, tx.last_insert_rowid())?;
+        sync_queue::enqueue(&tx, ENTITY_PASSAGE, &parked.local_id, "entry", &parked)?;
+        sql(tx.commit(), "save entry")?;
+
+        println!("[Rust] Session {} opened for {}", parked.id, parked.plate_number);
+        Ok(parked)
+    }
+
+    pub fn record_exit(&self, exit: ExitRecord) -> Result<ParkingSession, String> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = sql(conn.transaction(), "start exit")?;
+        let current = session(&tx, exit.session_id)?;
+        if current.status != SessionStatus::Parked {
+            return Err(format!(
+                "Session {} is {} already",
+                exit.session_id,
+                current.status.as_str()
+            ));
+        }
+
+        let exit_time = exit.exit_time.unwrap_or_else(now_secs);
+        if exit_time < current.entry_time {
+            return Err(format!("Exit time is before entry for session {}", exit.session_id));
+        }
+        let fee = exit.fee.ok_or_else(|| format!("No fee given for session {}", exit.session_id))?;
+
+        sql(
+            tx.execute(
+                "UPDATE parking_sessions SET status = 'exited', exit_time = ?2, exit_gate_id = ?3, exit_operator = ?4,
+                     fee = ?5, payment_method = ?6, payment_reference = ?7, receipt_number = ?8, updated_at = ?9
+                 WHERE id = ?1",
+                params![
+                    exit.session_id,
+                    exit_time,
+                    exit.gate_id,
+                    exit.operator_name,
+                    fee,
+                    exit.payment_method,
+                    exit.payment_reference,
+                    exit.receipt_number,
+                    now_secs(),
+                ],
+            ),
+            "record exit",
+        )?;
+
+        let exited = session(&tx, exit.session_id)?;
+        sync_queue::enqueue(&tx, ENTITY_PASSAGE, &exited.local_id, "exit", &exited)?;
+        sql(tx.commit(), "save exit")?;
+
+        println!("[Rust] Session {} closed, fee {}", exit.session_id, fee);
+        Ok(exited)
+    }
+
+    /// For entries made by mistake. Exited sessions are kept as they are.
+    pub fn cancel_session(&self, id: i64) -> Result<ParkingSession, String> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = sql(conn.transaction(), "start cancel")?;
+        let changed = sql(
+            tx.execute(
+                "UPDATE parking_sessions SET status = 'cancelled', updated_at = ?2 WHERE id = ?1 AND status = 'parked'",
+                params![id, now_secs()],
+            ),
+            "cancel session",
+        )?;
+        if changed == 0 {
+            return Err(format!("Session {} is not parked", id));
+        }
+        let cancelled = session(&tx, id)?;
+        sync_queue::enqueue(&tx, ENTITY_PASSAGE, &cancelled.local_id, "cancel", &cancelled)?;
+        sql(tx.commit(), "save cancel")?;
+        Ok(cancelled)
+    }
+
+    pub fn get_session(&self, id: i64) -> Result<ParkingSession, String> {
+        session(&self.conn.lock().unwrap(), id)
+    }
+
+    /// A session by its `local_id`, or by its row id.
+    pub fn find_session(&self, key: &str) -> Result<Option<ParkingSession>, String> {
+        let conn = self.conn.lock().unwrap();
+        sql(
+            conn.query_row(
+                "SELECT * FROM parking_sessions WHERE local_id = ?1 OR CAST(id AS TEXT) = ?1",
+                params![key.trim()],
+                session_from_row,
+            )
+            .optional(),
+            "look up session",
+        )
+    }
+
+    /// The open session for a plate, however it was typed.
+    pub fn find_parked(&self, plate_number: &str) -> Result<Option<ParkingSession>, String> {
+        let conn = self.conn.lock().unwrap();
+        sql(
+            conn.query_row(
+                "SELECT * FROM parking_sessions WHERE plate_key = ?1 AND status = 'parked'",
+                params![normalize_plate(plate_number)],
+                session_from_row,
+            )
+            .optional(),
+            "look up parked vehicle",
+        )
+    }
+
+    /// Most recent entries first.
+    pub fn list_sessions(&self, query: &SessionQuery) -> Result<Vec<ParkingSession>, String> {
+        let from = query.from.as_deref().map(local_day_start).transpose()?.unwrap_or(0);
+        let to = match query.to.as_deref() {
+            Some(d) => local_day_start(d)? + 24 * 60 * 60,
+            None => i64::MAX as u64,
+        };
+        let limit = query.limit.unwrap_or(DEFAULT_LIST_LIMIT) as i64;
+        let plate = query.plate_number.as_deref().map(normalize_plate);
+
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = sql(
+            conn.prepare(
+                "SELECT * FROM parking_sessions
+                 WHERE entry_time >= ?1 AND entry_time < ?2
+                   AND (?3 IS NULL OR status = ?3)
+                   AND (?4 IS NULL OR plate_key LIKE '%' || ?4 || '%')
+                 ORDER BY entry_time DESC, id DESC
+                 LIMIT ?5",
+            ),
+            "list sessions",
+        )?;
+        let rows = sql(
+            stmt.query_map(
+                params![from, to, query.status.map(|s| s.as_str()), plate, limit],
+                session_from_row,
+            ),
+            "list sessions",
+        )?;
+        sql(rows.collect(), "read sessions")
+    }
+
+    /* ───────────── GATE DEVICES ───────────── */
+
+    pub fn gate_device(&self, id: i64) -> Result<GateDevice, String> {
+        let conn = self.conn.lock().unwrap();
+        sql(
+            conn.query_row("SELECT * FROM gate_devices WHERE id = ?1", params![id], gate_from_row)
+                .optional(),
+            "read gate",
+        )?
+        .ok_or_else(|| format!("Gate {} not found", id))
+    }
+
+    pub fn list_gate_devices(&self) -> Result<Vec<GateDevice>, String> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = sql(conn.prepare("SELECT * FROM gate_devices ORDER BY name"), "list gates")?;
+        let rows = sql(stmt.query_map([], gate_from_row), "list gates")?;
+        sql(rows.collect(), "read gates")
+    }
+
+    /// Adds the gate, or replaces the one with the same id.
+    pub fn save_gate_device(&self, gate: GateDevice) -> Result<GateDevice, String> {
+        if gate.name.trim().is_empty() {
+            return Err("Gate needs a name".to_string());
+        }
+
+        let conn = self.conn.lock().unwrap();
+        let now = now_secs();
+        let values = params![
+            gate.name.trim(),
+            gate.direction.as_str(),
+            gate.serial_port,
+            gate.open_command,
+            gate.camera_url,
+            gate.printer_name,
+            gate.enabled,
+            now,
+            gate.id,
+        ];
+        let id = match gate.id {
+            Some(id) => {
+                let changed = sql(
+                    conn.execute(
+                        "UPDATE gate_devices SET name = ?1, direction = ?2, serial_port = ?3, open_command = ?4,
+                             camera_url = ?5, printer_name = ?6, enabled = ?7, updated_at = ?8
+                         WHERE id = ?9",
+                        values,
+                    ),
+                    "save gate",
+                )?;
+                if changed == 0 {
+                    return Err(format!("Gate {} not found", id));
+                }
+                id
+            }
+            None => {
+                sql(
+                    conn.execute(
+                        "INSERT INTO gate_devices (name, direction, serial_port, open_command, camera_url,
+                             printer_name, enabled, updated_at)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                        &values[..8],
+                    ),
+                    "add gate",
+                )?;
+                conn.last_insert_rowid()
+            }
+        };
+
+        sql(
+            conn.query_row("SELECT * FROM gate_devices WHERE id = ?1", params![id], gate_from_row),
+            "read gate",
+        )
+    }
+
+    pub fn delete_gate_device(&self, id: i64) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        let changed = sql(conn.execute("DELETE FROM gate_devices WHERE id = ?1", params![id]), "delete gate")?;
+        if changed == 0 {
+            return Err(format!("Gate {} not found", id));
+        }
+        Ok(())
+    }
+
+    /* ───────────── CONFIGURATION ───────────── */
+
+    pub fn get_config(&self, key: &str) -> Result<Option<serde_json::Value>, String> {
+        let conn = self.conn.lock().unwrap();
+        let value: Option<String> = sql(
+            conn.query_row("SELECT value FROM config WHERE key = ?1", params![key], |row| row.get(0))
+                .optional(),
+            "read config",
+        )?;
+        value
+            .map(|v| serde_json::from_str(&v).map_err(|e| format!("Config '{}' is not valid JSON: {}", key, e)))
+            .transpose()
+    }
+
+    pub fn set_config(&self, key: &str, value: &serde_json::Value) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        sql(
+            conn.execute(
+                "INSERT INTO config (key, value, updated_at) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at",
+                params![key, value.to_string(), now_secs()],
+            ),
+            "save config",
+        )
+        .map(|_| ())
+    }
+
+    pub fn all_config(&self) -> Result<BTreeMap<String, serde_json::Value>, String> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = sql(conn.prepare("SELECT key, value FROM config"), "read config")?;
+        let rows = sql(
+            stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))),
+            "read config",
+        )?;
+
+        let mut config = BTreeMap::new();
+        for row in rows {
+            let (key, value) = sql(row, "read config")?;
+            config.insert(key, serde_json::from_str(&value).unwrap_or(serde_json::Value::String(value)));
+        }
+        Ok(config)
+    }
+}
+
+fn session(conn: &Connection, id: i64) -> Result<ParkingSession, String> {
+    sql(
+        conn.query_row("SELECT * FROM parking_sessions WHERE id = ?1", params![id], session_from_row)
+            .optional(),
+        "read session",
+    )?
+    .ok_or_else(|| format!("Session {} not found", id))
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), String> {
+    let applied: usize = sql(conn.query_row("PRAGMA user_version", [], |row| row.get(0)), "read schema version")?;
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+        let tx = sql(conn.transaction(), "start migration")?;
+        sql(tx.execute_batch(migration), &format!("apply migration {}", i + 1))?;
+        sql(tx.pragma_update(None, "user_version", i + 1), "record schema version")?;
+        sql(tx.commit(), "commit migration")?;
+        println!("[Rust] Database migrated to version {}", i + 1);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(plate: &str) -> NewEntry {
+        NewEntry {
+            plate_number: plate.to_string(),
+            vehicle_type: Some("Bus".to_string()),
+            gate_id: None,
+            operator_name: Some("Asha".to_string()),
+            ticket_number: None,
+            entry_time: Some(1_000),
+        }
+    }
+
+    #[test]
+    fn migrations_run_once() {
+        let db = Database::open_in_memory().unwrap();
+        let mut conn = db.conn.lock().unwrap();
+        migrate(&mut conn).unwrap();
+        let version: usize = conn.query_row("PRAGMA user_version", [], |r| r.get(0)).unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+    }
+
+    #[test]
+    fn session_runs_from_entry_to_exit() {
+        let db = Database::open_in_memory().unwrap();
+        let parked = db.record_entry(entry("T 123 ABC")).unwrap();
+        assert_eq!(parked.status, SessionStatus::Parked);
+        assert!(db.record_entry(entry("t123abc")).is_err());
+        assert_eq!(db.find_parked("T123ABC").unwrap().unwrap().id, parked.id);
+
+        let exit = ExitRecord {
+            session_id: parked.id,
+            gate_id: None,
+            operator_name: Some("Juma".to_string()),
+            fee: Some(2_000.0),
+            payment_method: Some("cash".to_string()),
+            payment_reference: None,
+            receipt_number: Some("RCP-1".to_string()),
+            exit_time: Some(5_000),
+        };
+        let exited = db.record_exit(exit.clone()).unwrap();
+        assert_eq!((exited.status, exited.exit_time, exited.fee), (SessionStatus::Exited, Some(5_000), Some(2_000.0)));
+        assert!(db.record_exit(exit).is_err());
+        assert!(db.find_parked("T 123 ABC").unwrap().is_none());
+
+        // Parked again after leaving
+        db.record_entry(entry("T 123 ABC")).unwrap();
+        let query = SessionQuery {
+            plate_number: Some("123".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(db.list_sessions(&query).unwrap().len(), 2);
+        let query = SessionQuery {
+            status: Some(SessionStatus::Exited),
+            ..Default::default()
+        };
+        assert_eq!(db.list_sessions(&query).unwrap()[0].receipt_number.as_deref(), Some("RCP-1"));
+    }
+
+    #[test]
+    fn gates_and_config_round_trip() {
+        let db = Database::open_in_memory().unwrap();
+        let gate = db
+            .save_gate_device(GateDevice {
+                id: None,
+                name: "Lango Kuu".to_string(),
+                direction: GateDirection::Entry,
+                serial_port: Some("COM4".to_string()),
+                open_command: default_open_command(),
+                camera_url: None,
+                printer_name: None,
+                enabled: true,
+                updated_at: 0,
+            })
+            .unwrap();
+
+        let mut renamed = gate.clone();
+        renamed.name = "Lango la Kusini".to_string();
+        db.save_gate_device(renamed).unwrap();
+        let gates = db.list_gate_devices().unwrap();
+        assert_eq!((gates.len(), gates[0].name.as_str()), (1, "Lango la Kusini"));
+
+        let mut parked = entry("T 1 AAA");
+        parked.gate_id = gate.id;
+        let session = db.record_entry(parked).unwrap();
+        db.delete_gate_device(gate.id.unwrap()).unwrap();
+        assert_eq!(db.get_session(session.id).unwrap().entry_gate_id, None);
+
+        db.set_config("booth_id", &serde_json::json!("B1")).unwrap();
+        db.set_config("grace_minutes", &serde_json::json!(15)).unwrap();
+        db.set_config("grace_minutes", &serde_json::json!(10)).unwrap();
+        assert_eq!(db.get_config("grace_minutes").unwrap(), Some(serde_json::json!(10)));
+        assert_eq!(db.get_config("missing").unwrap(), None);
+        // Two set here, plus the install id the migrations create
+        let config = db.all_config().unwrap();
+        assert_eq!(config.len(), 3);
+        assert!(config.contains_key("install_id"));
+    }
+}
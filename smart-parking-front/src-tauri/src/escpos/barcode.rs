@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+
+use super::GS;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BarcodeSymbology {
+    #[default]
+    Code128,
+    Itf,
+    Ean13,
+    Ean8,
+}
+
+/// Where the human readable text goes (GS H n).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum HriPosition {
+    None,
+    Above,
+    #[default]
+    Below,
+    Both,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BarcodeOptions {
+    pub symbology: BarcodeSymbology,
+    pub hri: HriPosition,
+    /// Bar height in dots (GS h n).
+    pub height: u8,
+    /// Narrow bar width in dots, 2-6 (GS w n).
+    pub module_width: u8,
+}
+
+impl Default for BarcodeOptions {
+    fn default() -> Self {
+        Self {
+            symbology: BarcodeSymbology::Code128,
+            hri: HriPosition::Below,
+            height: 80,
+            module_width: 2,
+        }
+    }
+}
+
+/// GS k in function B format. ITF and EAN only take digits, so data that
+/// doesn't fit the configured symbology is printed as CODE128 instead.
+pub fn barcode_commands(data: &str, options: &BarcodeOptions) -> Result<Vec<u8>, String> {
+    let (m, payload) = match encode_payload(data, options.symbology) {
+        Ok(encoded) => encoded,
+        Err(e) if options.symbology != BarcodeSymbology::Code128 => {
+            println!("[Rust] {}, falling back to CODE128", e);
+            encode_payload(data, BarcodeSymbology::Code128)?
+        }
+        Err(e) => return Err(e),
+    };
+
+    let mut c = Vec::new();
+    c.extend_from_slice(&[GS, 0x48, hri_value(options.hri)]);
+    c.extend_from_slice(&[GS, 0x68, options.height.max(1)]);
+    c.extend_from_slice(&[GS, 0x77, options.module_width.clamp(2, 6)]);
+    c.extend_from_slice(&[GS, 0x6B, m, payload.len() as u8]);
+    c.extend_from_slice(&payload);
+    Ok(c)
+}
+
+fn hri_value(position: HriPosition) -> u8 {
+    match position {
+        HriPosition::None => 0,
+        HriPosition::Above => 1,
+        HriPosition::Below => 2,
+        HriPosition::Both => 3,
+    }
+}
+
+fn encode_payload(data: &str, symbology: BarcodeSymbology) -> Result<(u8, Vec<u8>), String> {
+    let digits = !data.is_empty() && data.bytes().all(|b| b.is_ascii_digit());
+
+    match symbology {
+        BarcodeSymbology::Code128 => {
+            if data.is_empty() || !data.bytes().all(|b| (0x20..0x7F).contains(&b)) {
+                return Err(format!("'{}' can't be encoded as CODE128", data));
+            }
+            if data.len() > 250 {
+                return Err("CODE128 data is too long".to_string());
+            }
+
+            // Code set C packs two digits per symbol, so numeric ticket
+            // numbers print at half the width.
+            if digits && data.len() % 2 == 0 {
+                let mut payload = b"{C".to_vec();
+                for pair in data.as_bytes().chunks(2) {
+                    payload.push((pair[0] - b'0') * 10 + (pair[1] - b'0'));
+                }
+                Ok((73, payload))
+            } else {
+                // `{` starts a code set or function character, so a literal
+                // one is sent twice.
+                let mut payload = b"{B".to_vec();
+                for &b in data.as_bytes() {
+                    if b == b'{' {
+                        payload.push(b'{');
+                    }
+                    payload.push(b);
+                }
+                if payload.len() > 255 {
+                    return Err("CODE128 data is too long".to_string());
+                }
+                Ok((73, payload))
+            }
+        }
+        BarcodeSymbology::Itf => {
+            if !digits || data.len() % 2 != 0 {
+                return Err(format!("'{}' is not an even number of digits for ITF", data));
+            }
+            Ok((70, data.as_bytes().to_vec()))
+        }
+        BarcodeSymbology::Ean13 => {
+            if !digits || !(12..=13).contains(&data.len()) {
+                return Err(format!("'{}' is not 12 or 13 digits for EAN-13", data));
+            }
+            Ok((67, data.as_bytes().to_vec()))
+        }
+        BarcodeSymbology::Ean8 => {
+            if !digits || !(7..=8).contains(&data.len()) {
+                return Err(format!("'{}' is not 7 or 8 digits for EAN-8", data));
+            }
+            Ok((68, data.as_bytes().to_vec()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code128_escapes_braces() {
+        let (m, payload) = encode_payload("A{1}", BarcodeSymbology::Code128).unwrap();
+        assert_eq!(m, 73);
+        assert_eq!(payload, b"{BA{{1}".to_vec());
+
+        let (_, digits) = encode_payload("2024", BarcodeSymbology::Code128).unwrap();
+        assert_eq!(digits, vec![b'{', b'C', 20, 24]);
+        assert!(encode_payload(&"{".repeat(200), BarcodeSymbology::Code128).is_err());
+    }
+}
@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+
+use super::ESC;
+
+/// Drawer kick-out connector pin the drawer's solenoid is wired to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DrawerPin {
+    #[default]
+    Pin2,
+    Pin5,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DrawerOptions {
+    pub pin: DrawerPin,
+    /// Pulse on time in milliseconds, sent in 2ms units.
+    pub on_ms: u16,
+    /// Pulse off time in milliseconds, sent in 2ms units.
+    pub off_ms: u16,
+}
+
+impl Default for DrawerOptions {
+    fn default() -> Self {
+        Self {
+            pin: DrawerPin::Pin2,
+            on_ms: 100,
+            off_ms: 200,
+        }
+    }
+}
+
+/// ESC p m t1 t2: pulse the drawer kick-out connector.
+pub fn drawer_kick_command(options: &DrawerOptions) -> [u8; 5] {
+    let m = match options.pin {
+        DrawerPin::Pin2 => 0,
+        DrawerPin::Pin5 => 1,
+    };
+    let units = |ms: u16| (ms / 2).clamp(1, 255) as u8;
+
+    [ESC, 0x70, m, units(options.on_ms), units(options.off_ms)]
+}
@@ -0,0 +1,127 @@
+use std::path::Path;
+
+use image::imageops::FilterType;
+use serde::{Deserialize, Serialize};
+
+use super::raster::Bitmap;
+use super::GS;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogoOptions {
+    /// PNG or BMP file, relative to the app resource directory.
+    pub resource: String,
+    /// Two character key the logo was stored under with
+    /// `store_logo_in_printer`. When set, receipts print the copy held in the
+    /// printer's NV memory instead of sending the bitmap every time.
+    pub nv_key: Option<String>,
+}
+
+impl Default for LogoOptions {
+    fn default() -> Self {
+        Self {
+            resource: "resources/logo.png".to_string(),
+            nv_key: None,
+        }
+    }
+}
+
+/// Header image as it goes into the byte stream.
+pub enum Logo {
+    Raster(Bitmap),
+    Nv([u8; 2]),
+}
+
+/// Loads an image, scales it down to the printer's dot width if needed and
+/// dithers it to 1-bit. Transparent pixels count as paper.
+pub fn load_bitmap(path: &Path, dot_width: usize) -> Result<Bitmap, String> {
+    let img = image::open(path)
+        .map_err(|e| format!("Failed to load image {}: {}", path.display(), e))?;
+
+    let img = if img.width() as usize > dot_width {
+        let height = (img.height() as u64 * dot_width as u64 / img.width() as u64).max(1);
+        img.resize_exact(dot_width as u32, height as u32, FilterType::Triangle)
+    } else {
+        img
+    };
+
+    let rgba = img.to_rgba8();
+    let (width, height) = (rgba.width() as usize, rgba.height() as usize);
+
+    let mut luma: Vec<f32> = rgba
+        .pixels()
+        .map(|p| {
+            let [r, g, b, a] = p.0;
+            let gray = 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32;
+            let alpha = a as f32 / 255.0;
+            gray * alpha + 255.0 * (1.0 - alpha)
+        })
+        .collect();
+
+    Ok(dither(&mut luma, width, height))
+}
+
+/// Floyd-Steinberg error diffusion from 8-bit gray to printer dots.
+fn dither(luma: &mut [f32], width: usize, height: usize) -> Bitmap {
+    let mut bitmap = Bitmap::new(width, height);
+
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            let old = luma[i];
+            let black = old < 128.0;
+            let error = old - if black { 0.0 } else { 255.0 };
+            bitmap.set(x, y, black);
+
+            if x + 1 < width {
+                luma[i + 1] += error * 7.0 / 16.0;
+            }
+            if y + 1 < height {
+                if x > 0 {
+                    luma[i + width - 1] += error * 3.0 / 16.0;
+                }
+                luma[i + width] += error * 5.0 / 16.0;
+                if x + 1 < width {
+                    luma[i + width + 1] += error / 16.0;
+                }
+            }
+        }
+    }
+
+    bitmap
+}
+
+pub fn nv_key(key: &str) -> Result<[u8; 2], String> {
+    match key.as_bytes() {
+        [a, b] if (0x20..0x7F).contains(a) && (0x20..0x7F).contains(b) => Ok([*a, *b]),
+        _ => Err(format!("NV key must be two printable ASCII characters, got '{}'", key)),
+    }
+}
+
+/// GS ( L fn 67: define raster graphics in NV memory under `key`.
+pub fn define_nv_graphics_commands(bitmap: &Bitmap, key: [u8; 2]) -> Result<Vec<u8>, String> {
+    let raster = bitmap.to_raster_command();
+    let data = &raster[8..];
+    let len = 11 + data.len();
+    if len > 0xFFFF {
+        return Err("Logo is too large to store in NV memory".to_string());
+    }
+
+    let mut c = Vec::with_capacity(len + 5);
+    c.extend_from_slice(&[GS, 0x28, 0x4C, (len % 256) as u8, (len / 256) as u8]);
+    c.extend_from_slice(&[0x30, 0x43, 0x30, key[0], key[1], 0x01]);
+    c.extend_from_slice(&[
+        (bitmap.width % 256) as u8,
+        (bitmap.width / 256) as u8,
+        (bitmap.height % 256) as u8,
+        (bitmap.height / 256) as u8,
+        0x31,
+    ]);
+    c.extend_from_slice(data);
+    Ok(c)
+}
+
+/// GS ( L fn 69: print the NV graphics stored under `key` at normal size.
+pub fn print_nv_graphics_commands(key: [u8; 2]) -> Vec<u8> {
+    vec![GS, 0x28, 0x4C, 0x06, 0x00, 0x30, 0x45, key[0], key[1], 0x01, 0x01]
+}
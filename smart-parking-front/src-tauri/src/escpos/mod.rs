@@ -0,0 +1,153 @@
+pub mod barcode;
+pub mod drawer;
+pub mod encoding;
+pub mod logo;
+pub mod models;
+pub mod preview;
+pub mod profile;
+pub mod qr;
+pub mod raster;
+pub mod status;
+
+pub use barcode::BarcodeOptions;
+pub use drawer::DrawerOptions;
+pub use encoding::Codepage;
+pub use logo::{Logo, LogoOptions};
+pub use profile::PrinterProfile;
+pub use qr::{QrMode, QrOptions};
+
+pub const ESC: u8 = 0x1B;
+pub const GS: u8 = 0x1D;
+pub const LF: u8 = 0x0A;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left = 0,
+    Center = 1,
+    Right = 2,
+}
+
+/// Byte stream builder for ESC/POS printers.
+///
+/// All text goes through the printer's codepage, so templates never write
+/// raw UTF-8 to the printer.
+pub struct EscPos {
+    buf: Vec<u8>,
+    profile: PrinterProfile,
+}
+
+impl EscPos {
+    pub fn new(profile: &PrinterProfile) -> Self {
+        Self {
+            buf: Vec::new(),
+            profile: profile.clone(),
+        }
+    }
+
+    /// ESC @ followed by ESC t n, since a reset also resets the codepage.
+    pub fn init(&mut self) -> &mut Self {
+        self.buf.extend_from_slice(&[ESC, 0x40]);
+        self.buf.extend_from_slice(&[ESC, 0x74, self.profile.page_number()]);
+        self
+    }
+
+    pub fn align(&mut self, align: Align) -> &mut Self {
+        self.buf.extend_from_slice(&[ESC, 0x61, align as u8]);
+        self
+    }
+
+    pub fn bold(&mut self, on: bool) -> &mut Self {
+        self.buf.extend_from_slice(&[ESC, 0x45, on as u8]);
+        self
+    }
+
+    /// ESC ! n with only the double height bit. ESC ! also clears emphasis,
+    /// so turn bold on after this.
+    pub fn double_height(&mut self, on: bool) -> &mut Self {
+        self.buf.extend_from_slice(&[ESC, 0x21, if on { 0x10 } else { 0x00 }]);
+        self
+    }
+
+    pub fn text(&mut self, text: &str) -> &mut Self {
+        let encoded = self.profile.codepage.encode(text);
+        self.buf.extend_from_slice(&encoded);
+        self
+    }
+
+    pub fn line(&mut self, text: &str) -> &mut Self {
+        self.text(text).newline()
+    }
+
+    /// Horizontal rule, 40 characters or the paper width if that's narrower.
+    pub fn rule(&mut self, ch: char) -> &mut Self {
+        let columns = (self.profile.dot_width / 12).clamp(1, 40) as usize;
+        self.line(&ch.to_string().repeat(columns))
+    }
+
+    pub fn newline(&mut self) -> &mut Self {
+        self.buf.push(LF);
+        self
+    }
+
+    pub fn feed(&mut self, lines: usize) -> &mut Self {
+        for _ in 0..lines {
+            self.buf.push(LF);
+        }
+        self
+    }
+
+    /// QR code using the profile's QR mode. If the data can't be rendered as a
+    /// raster symbol the text itself is printed instead.
+    pub fn qr(&mut self, data: &str) -> &mut Self {
+        let options = self.profile.qr.clone();
+
+        match options.mode {
+            QrMode::Native => {
+                self.buf.extend_from_slice(&qr::native_qr_commands(data, &options));
+            }
+            QrMode::Raster => match qr::raster_qr_commands(data, &options, self.profile.dot_width as usize) {
+                Ok(bytes) => self.buf.extend_from_slice(&bytes),
+                Err(e) => {
+                    println!("[Rust] QR raster failed, printing text instead: {}", e);
+                    self.line(data);
+                }
+            },
+        }
+        self
+    }
+
+    /// Barcode using the profile's symbology and HRI settings. Data that can't
+    /// be encoded at all is left off the receipt.
+    pub fn barcode(&mut self, data: &str) -> &mut Self {
+        match barcode::barcode_commands(data, &self.profile.barcode) {
+            Ok(bytes) => self.buf.extend_from_slice(&bytes),
+            Err(e) => println!("[Rust] Skipping barcode: {}", e),
+        }
+        self
+    }
+
+    pub fn logo(&mut self, logo: &Logo) -> &mut Self {
+        match logo {
+            Logo::Raster(bitmap) => self.buf.extend_from_slice(&bitmap.to_raster_command()),
+            Logo::Nv(key) => self.buf.extend_from_slice(&logo::print_nv_graphics_commands(*key)),
+        }
+        self
+    }
+
+    /// Opens the cash drawer wired to the printer.
+    pub fn kick_drawer(&mut self) -> &mut Self {
+        self.buf.extend_from_slice(&drawer::drawer_kick_command(&self.profile.drawer));
+        self
+    }
+
+    /// Cuts with the profile's cut command; nothing on printers without a
+    /// cutter.
+    pub fn cut(&mut self) -> &mut Self {
+        self.buf.extend_from_slice(&self.profile.cut.unwrap_or_default().bytes());
+        self
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use super::status::StatusQuery;
+use super::{Codepage, PrinterProfile, QrMode, GS};
+use crate::util::{read_json, write_json};
+
+const BUILT_IN_MODELS: &str = include_str!("printer_models.json");
+
+/// How the paper is cut at the end of a job (GS V).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CutCommand {
+    /// GS V 0
+    #[default]
+    Full,
+    /// GS V 1
+    Partial,
+    /// GS V 65 0: feed to the cutter, then full cut.
+    FeedFull,
+    /// GS V 66 0: feed to the cutter, then partial cut.
+    FeedPartial,
+    /// No cutter fitted.
+    None,
+}
+
+impl CutCommand {
+    pub fn bytes(self) -> Vec<u8> {
+        match self {
+            CutCommand::Full => vec![GS, 0x56, 0x00],
+            CutCommand::Partial => vec![GS, 0x56, 0x01],
+            CutCommand::FeedFull => vec![GS, 0x56, 65, 0],
+            CutCommand::FeedPartial => vec![GS, 0x56, 66, 0],
+            CutCommand::None => vec![],
+        }
+    }
+}
+
+/// What a printer model's firmware can do.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrinterModel {
+    pub id: String,
+    pub vendor: String,
+    pub name: String,
+    pub dot_width: u16,
+    pub cut: CutCommand,
+    /// GS ( k QR support. Without it QR codes are sent as raster images.
+    pub native_qr: bool,
+    /// Codepages in the firmware, with the `ESC t` number for each.
+    pub codepages: BTreeMap<Codepage, u8>,
+    pub status_query: StatusQuery,
+}
+
+/// Built-in printer models plus the ones added at the booth, which are kept
+/// in `printer_models.json` and replace built-ins with the same id.
+pub struct PrinterDatabase {
+    path: PathBuf,
+    user_models: Mutex<Vec<PrinterModel>>,
+}
+
+fn built_in() -> Vec<PrinterModel> {
+    serde_json::from_str(BUILT_IN_MODELS).expect("built-in printer models are valid JSON")
+}
+
+impl PrinterDatabase {
+    pub fn load(path: PathBuf) -> Self {
+        let user_models: Vec<PrinterModel> = read_json(&path).unwrap_or_else(|e| {
+            println!("[Rust] Ignoring user printer models: {}", e);
+            Vec::new()
+        });
+
+        println!("[Rust] Printer database loaded: {} user model(s)", user_models.len());
+
+        Self {
+            path,
+            user_models: Mutex::new(user_models),
+        }
+    }
+
+    pub fn models(&self) -> Vec<PrinterModel> {
+        let user = self.user_models.lock().unwrap();
+        let mut models: Vec<PrinterModel> = built_in()
+            .into_iter()
+            .filter(|m| !user.iter().any(|u| u.id == m.id))
+            .collect();
+        models.extend(user.iter().cloned());
+        models
+    }
+
+    pub fn get(&self, id: &str) -> Option<PrinterModel> {
+        self.models().into_iter().find(|m| m.id == id)
+    }
+
+    /// Adds or replaces a user model.
+    pub fn save_model(&self, model: PrinterModel) -> Result<(), String> {
+        if model.id.trim().is_empty() {
+            return Err("Printer model needs an id".to_string());
+        }
+
+        let mut user = self.user_models.lock().unwrap();
+        user.retain(|m| m.id != model.id);
+        user.push(model);
+        write_json(&self.path, &*user)
+    }
+
+    /// The profile with the model's capabilities applied. Without a model, or
+    /// with an unknown one, the profile is used as sent.
+    pub fn resolve(&self, profile: &PrinterProfile) -> PrinterProfile {
+        let Some(id) = profile.model.as_deref() else {
+            return profile.clone();
+        };
+        match self.get(id) {
+            Some(model) => apply_model(profile, &model),
+            None => {
+                println!("[Rust] Unknown printer model '{}', using profile as sent", id);
+                profile.clone()
+            }
+        }
+    }
+}
+
+fn apply_model(profile: &PrinterProfile, model: &PrinterModel) -> PrinterProfile {
+    let mut resolved = profile.clone();
+    resolved.dot_width = model.dot_width;
+
+    if resolved.cut.is_none() {
+        resolved.cut = Some(model.cut);
+    }
+
+    if !model.native_qr {
+        resolved.qr.mode = QrMode::Raster;
+    }
+
+    if resolved.codepage_number.is_none() {
+        match model.codepages.get(&profile.codepage) {
+            Some(&n) => resolved.codepage_number = Some(n),
+            None => {
+                // Everything has CP437, and text is transliterated to fit
+                let (&codepage, &n) = model
+                    .codepages
+                    .iter()
+                    .next()
+                    .unwrap_or((&Codepage::Cp437, &0));
+                println!(
+                    "[Rust] {} has no {:?}, printing in {:?}",
+                    model.name, profile.codepage, codepage
+                );
+                resolved.codepage = codepage;
+                resolved.codepage_number = Some(n);
+            }
+        }
+    }
+
+    resolved.status_query = match (model.status_query, profile.status_query) {
+        (StatusQuery::None, _) => StatusQuery::None,
+        (supported, StatusQuery::None) => supported,
+        (_, requested) => requested,
+    };
+
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database() -> PrinterDatabase {
+        PrinterDatabase::load(std::env::temp_dir().join("no-such-printer-models.json"))
+    }
+
+    #[test]
+    fn built_in_models_parse_with_unique_ids() {
+        let models = built_in();
+        let mut ids: Vec<&str> = models.iter().map(|m| m.id.as_str()).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), models.len());
+    }
+
+    #[test]
+    fn model_capabilities_replace_profile_settings() {
+        let profile = PrinterProfile {
+            model: Some("xprinter-xp-58iih".to_string()),
+            codepage: Codepage::Cp1252,
+            status_query: StatusQuery::DleEot,
+            ..Default::default()
+        };
+        let resolved = database().resolve(&profile);
+
+        assert_eq!(resolved.dot_width, 384);
+        assert_eq!(resolved.cut, Some(CutCommand::None));
+        assert_eq!(resolved.qr.mode, QrMode::Raster);
+        assert_eq!(resolved.codepage, Codepage::Cp437);
+        assert_eq!(resolved.page_number(), 0);
+        assert_eq!(resolved.status_query, StatusQuery::None);
+    }
+
+    #[test]
+    fn explicit_settings_win_where_the_model_allows() {
+        let profile = PrinterProfile {
+            model: Some("epson-tm-t20ii".to_string()),
+            codepage: Codepage::Cp858,
+            cut: Some(CutCommand::Full),
+            status_query: StatusQuery::GsR,
+            ..Default::default()
+        };
+        let resolved = database().resolve(&profile);
+
+        assert_eq!(resolved.cut, Some(CutCommand::Full));
+        assert_eq!(resolved.page_number(), 19);
+        assert_eq!(resolved.status_query, StatusQuery::GsR);
+        assert_eq!(resolved.qr.mode, QrMode::Native);
+    }
+
+    #[test]
+    fn user_models_replace_built_ins_and_survive_a_restart() {
+        let path = std::env::temp_dir().join(format!("printer-models-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut model = built_in().into_iter().find(|m| m.id == "epson-tm-t20ii").unwrap();
+        model.dot_width = 512;
+        PrinterDatabase::load(path.clone()).save_model(model).unwrap();
+
+        let reloaded = PrinterDatabase::load(path.clone());
+        assert_eq!(reloaded.get("epson-tm-t20ii").unwrap().dot_width, 512);
+        assert_eq!(reloaded.models().len(), built_in().len());
+        assert!(!path.with_extension("json.tmp").exists());
+        let _ = std::fs::remove_file(&path);
+    }
+}
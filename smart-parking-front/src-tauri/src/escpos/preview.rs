@@ -0,0 +1,624 @@
+use std::convert::Infallible;
+use std::io::Cursor;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use embedded_graphics::mono_font::iso_8859_1::FONT_10X20;
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use embedded_graphics::text::{Baseline, Text};
+use serde::{Deserialize, Serialize};
+
+use super::qr::{self, QrErrorCorrection, QrOptions};
+use super::raster::Bitmap;
+use super::status::{DLE, EOT};
+use super::{Align, Codepage, PrinterProfile, ESC, GS, LF};
+
+/// Font A character cell in dots, 48 columns on 80mm paper.
+const CELL_WIDTH: usize = 12;
+const CELL_HEIGHT: usize = 24;
+
+const FS: u8 = 0x1C;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Style {
+    bold: bool,
+    width: usize,
+    height: usize,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            bold: false,
+            width: 1,
+            height: 1,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Row {
+    Text { chars: Vec<(char, Style)>, align: Align },
+    Image { bitmap: Bitmap, align: Align, label: String },
+    Cut,
+}
+
+/// What a byte stream would look like on paper, as understood by a small
+/// ESC/POS interpreter. Covers the commands our templates emit; anything else
+/// is skipped with its parameters.
+#[derive(Debug)]
+pub struct Preview {
+    rows: Vec<Row>,
+    dot_width: usize,
+}
+
+/// Preview in the form the frontend shows it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RenderedPreview {
+    pub text: String,
+    /// PNG as a `data:` URL.
+    pub image: String,
+    pub width: usize,
+    pub height: usize,
+}
+
+pub fn render(bytes: &[u8], profile: &PrinterProfile) -> Result<RenderedPreview, String> {
+    let preview = Preview::parse(bytes, profile);
+    let bitmap = preview.to_bitmap();
+    let png = to_png(&bitmap)?;
+
+    Ok(RenderedPreview {
+        text: preview.to_text(),
+        image: format!("data:image/png;base64,{}", BASE64.encode(png)),
+        width: bitmap.width,
+        height: bitmap.height,
+    })
+}
+
+struct Interpreter<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    codepage: Codepage,
+    style: Style,
+    align: Align,
+    line: Vec<(char, Style)>,
+    line_align: Align,
+    qr: QrOptions,
+    qr_data: String,
+    dot_width: usize,
+    rows: Vec<Row>,
+}
+
+impl<'a> Interpreter<'a> {
+    fn take(&mut self, n: usize) -> &'a [u8] {
+        let end = (self.pos + n).min(self.bytes.len());
+        let taken = &self.bytes[self.pos..end];
+        self.pos = end;
+        taken
+    }
+
+    fn byte(&mut self) -> u8 {
+        self.take(1).first().copied().unwrap_or(0)
+    }
+
+    fn flush_line(&mut self) {
+        let chars = std::mem::take(&mut self.line);
+        self.rows.push(Row::Text {
+            chars,
+            align: self.line_align,
+        });
+    }
+
+    /// Images and cuts start on a fresh line.
+    fn end_pending_line(&mut self) {
+        if !self.line.is_empty() {
+            self.flush_line();
+        }
+    }
+
+    fn push_char(&mut self, c: char) {
+        // ESC a only applies at the start of a line
+        if self.line.is_empty() {
+            self.line_align = self.align;
+        }
+        self.line.push((c, self.style));
+    }
+
+    fn push_label(&mut self, label: String) {
+        self.end_pending_line();
+        self.rows.push(Row::Text {
+            chars: label.chars().map(|c| (c, Style::default())).collect(),
+            align: self.align,
+        });
+    }
+
+    fn run(mut self) -> Vec<Row> {
+        while self.pos < self.bytes.len() {
+            let b = self.byte();
+            match b {
+                LF => self.flush_line(),
+                ESC => self.esc(),
+                GS => self.gs(),
+                DLE => {
+                    // DLE EOT n, DLE DC4 fn a b
+                    let n = if self.byte() == EOT { 1 } else { 3 };
+                    self.take(n);
+                }
+                FS => {
+                    if self.byte() == 0x70 {
+                        let key = self.take(2).first().copied().unwrap_or(0);
+                        self.push_label(format!("[LOGO {}]", key));
+                    }
+                }
+                0x00..=0x1F => {}
+                _ => {
+                    let c = self.codepage.decode_byte(b);
+                    self.push_char(c);
+                }
+            }
+        }
+
+        self.end_pending_line();
+        self.rows
+    }
+
+    fn esc(&mut self) {
+        match self.byte() {
+            0x40 => {
+                self.style = Style::default();
+                self.align = Align::Left;
+                self.qr = QrOptions::default();
+            }
+            0x61 => {
+                self.align = match self.byte() {
+                    1 | b'1' => Align::Center,
+                    2 | b'2' => Align::Right,
+                    _ => Align::Left,
+                };
+            }
+            0x45 => self.style.bold = self.byte() & 1 != 0,
+            0x21 => {
+                let n = self.byte();
+                self.style.bold = n & 0x08 != 0;
+                self.style.height = if n & 0x10 != 0 { 2 } else { 1 };
+                self.style.width = if n & 0x20 != 0 { 2 } else { 1 };
+            }
+            0x64 => {
+                let n = self.byte() as usize;
+                let pending = !self.line.is_empty() as usize;
+                self.end_pending_line();
+                for _ in pending..n {
+                    self.flush_line();
+                }
+            }
+            0x70 => {
+                self.take(3);
+            }
+            0x63 => {
+                self.take(2);
+            }
+            // ESC t, ESC -, ESC M, ESC G, ESC J, ESC 3, ESC R, ESC V, ESC {
+            0x74 | 0x2D | 0x4D | 0x47 | 0x4A | 0x33 | 0x52 | 0x56 | 0x7B => {
+                self.take(1);
+            }
+            _ => {}
+        }
+    }
+
+    fn gs(&mut self) {
+        match self.byte() {
+            0x56 => {
+                let m = self.byte();
+                if matches!(m, 65 | 66 | 97 | 98 | 103 | 104) {
+                    self.take(1);
+                }
+                self.end_pending_line();
+                self.rows.push(Row::Cut);
+            }
+            0x21 => {
+                let n = self.byte() as usize;
+                self.style.width = (n >> 4) + 1;
+                self.style.height = (n & 0x0F) + 1;
+            }
+            0x76 => {
+                let header = self.take(6);
+                if header.len() == 6 {
+                    let width_bytes = header[2] as usize + header[3] as usize * 256;
+                    let height = header[4] as usize + header[5] as usize * 256;
+                    let data = self.take(width_bytes * height);
+                    let bitmap = unpack_raster(data, width_bytes, height);
+                    let label = format!("[IMAGE {}x{}]", bitmap.width, bitmap.height);
+                    self.end_pending_line();
+                    self.rows.push(Row::Image {
+                        bitmap,
+                        align: self.align,
+                        label,
+                    });
+                }
+            }
+            0x28 => {
+                let function = self.byte();
+                let len = self.take(2);
+                let len = len.first().copied().unwrap_or(0) as usize
+                    + len.get(1).copied().unwrap_or(0) as usize * 256;
+                let params = self.take(len);
+                match function {
+                    0x6B => self.qr_function(params),
+                    // GS ( L fn 69: print NV graphics
+                    0x4C if params.len() >= 4 && params[1] == 0x45 => {
+                        let key = String::from_utf8_lossy(&params[2..4]).to_string();
+                        self.push_label(format!("[LOGO {}]", key));
+                    }
+                    _ => {}
+                }
+            }
+            0x6B => {
+                let m = self.byte();
+                let data: Vec<u8> = if m <= 6 {
+                    let start = self.pos;
+                    while self.pos < self.bytes.len() && self.bytes[self.pos] != 0 {
+                        self.pos += 1;
+                    }
+                    let data = self.bytes[start..self.pos].to_vec();
+                    self.take(1);
+                    data
+                } else {
+                    let n = self.byte() as usize;
+                    self.take(n).to_vec()
+                };
+                let label = format!("[BARCODE {}]", barcode_text(&data));
+                self.push_label(label);
+            }
+            0x48 | 0x68 | 0x77 | 0x66 | 0x72 | 0x42 | 0x61 => {
+                self.take(1);
+            }
+            0x4C | 0x57 => {
+                self.take(2);
+            }
+            _ => {}
+        }
+    }
+
+    /// GS ( k for model 2 QR codes (cn = 49).
+    fn qr_function(&mut self, params: &[u8]) {
+        if params.len() < 2 || params[0] != 0x31 {
+            return;
+        }
+
+        match params[1] {
+            0x43 => {
+                if let Some(&size) = params.get(2) {
+                    self.qr.module_size = size;
+                }
+            }
+            0x45 => {
+                self.qr.error_correction = match params.get(2) {
+                    Some(0x30) => QrErrorCorrection::L,
+                    Some(0x32) => QrErrorCorrection::Q,
+                    Some(0x33) => QrErrorCorrection::H,
+                    _ => QrErrorCorrection::M,
+                };
+            }
+            0x50 => {
+                self.qr_data = String::from_utf8_lossy(params.get(3..).unwrap_or(&[])).to_string();
+            }
+            0x51 => {
+                let data = self.qr_data.clone();
+                match qr::qr_bitmap(&data, &self.qr, self.dot_width) {
+                    Ok(bitmap) => {
+                        self.end_pending_line();
+                        self.rows.push(Row::Image {
+                            bitmap,
+                            align: self.align,
+                            label: format!("[QR {}]", data),
+                        });
+                    }
+                    Err(_) => self.push_label(format!("[QR {}]", data)),
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn unpack_raster(data: &[u8], width_bytes: usize, height: usize) -> Bitmap {
+    let mut bitmap = Bitmap::new(width_bytes * 8, height);
+    for (i, byte) in data.iter().enumerate() {
+        let (y, xb) = (i / width_bytes, i % width_bytes);
+        for bit in 0..8 {
+            if byte & (0x80 >> bit) != 0 {
+                bitmap.set(xb * 8 + bit, y, true);
+            }
+        }
+    }
+    bitmap
+}
+
+/// The value a scanner would read, for CODE128 code sets B and C.
+fn barcode_text(data: &[u8]) -> String {
+    match data {
+        [b'{', b'C', rest @ ..] => rest.iter().map(|b| format!("{:02}", b)).collect(),
+        [b'{', b'B', rest @ ..] => String::from_utf8_lossy(rest).replace("{{", "{"),
+        _ => String::from_utf8_lossy(data).to_string(),
+    }
+}
+
+fn offset(align: Align, content: usize, total: usize) -> usize {
+    match align {
+        Align::Left => 0,
+        Align::Center => total.saturating_sub(content) / 2,
+        Align::Right => total.saturating_sub(content),
+    }
+}
+
+/// Splits a text row into printed lines at the paper width.
+fn wrap(chars: &[(char, Style)], columns: usize) -> Vec<Vec<(char, Style)>> {
+    let mut lines = vec![Vec::new()];
+    let mut used = 0;
+
+    for &(c, style) in chars {
+        if used + style.width > columns && used > 0 {
+            lines.push(Vec::new());
+            used = 0;
+        }
+        lines.last_mut().unwrap().push((c, style));
+        used += style.width;
+    }
+
+    lines
+}
+
+impl Preview {
+    pub fn parse(bytes: &[u8], profile: &PrinterProfile) -> Self {
+        let interpreter = Interpreter {
+            bytes,
+            pos: 0,
+            codepage: profile.codepage,
+            style: Style::default(),
+            align: Align::Left,
+            line: Vec::new(),
+            line_align: Align::Left,
+            qr: QrOptions::default(),
+            qr_data: String::new(),
+            dot_width: profile.dot_width as usize,
+            rows: Vec::new(),
+        };
+
+        Self {
+            rows: interpreter.run(),
+            dot_width: profile.dot_width as usize,
+        }
+    }
+
+    fn columns(&self) -> usize {
+        (self.dot_width / CELL_WIDTH).max(1)
+    }
+
+    /// Plain text rendering, one printed line per text line.
+    pub fn to_text(&self) -> String {
+        let columns = self.columns();
+        let mut out = String::new();
+
+        let mut push = |content: String, width: usize, align: Align| {
+            let content = content.trim_end();
+            if !content.is_empty() {
+                out.push_str(&" ".repeat(offset(align, width, columns)));
+                out.push_str(content);
+            }
+            out.push('\n');
+        };
+
+        for row in &self.rows {
+            match row {
+                Row::Text { chars, align } => {
+                    for line in wrap(chars, columns) {
+                        let width = line.iter().map(|(_, s)| s.width).sum();
+                        push(line.iter().map(|(c, _)| *c).collect(), width, *align);
+                    }
+                }
+                Row::Image { label, align, .. } => {
+                    push(label.clone(), label.chars().count(), *align);
+                }
+                Row::Cut => push(format!("{:-^width$}", " CUT ", width = columns), columns, Align::Left),
+            }
+        }
+
+        out
+    }
+
+    /// 1-bit rendering at the printer's resolution.
+    pub fn to_bitmap(&self) -> Bitmap {
+        let width = self.dot_width;
+        let columns = self.columns();
+        let mut bands: Vec<Bitmap> = Vec::new();
+
+        for row in &self.rows {
+            match row {
+                Row::Text { chars, align } => {
+                    for line in wrap(chars, columns) {
+                        let height = line.iter().map(|(_, s)| s.height).max().unwrap_or(1) * CELL_HEIGHT;
+                        let line_width: usize = line.iter().map(|(_, s)| s.width * CELL_WIDTH).sum();
+                        let mut band = Bitmap::new(width, height);
+
+                        let mut x = offset(*align, line_width, width);
+                        for (c, style) in line {
+                            let glyph = render_glyph(c, style.bold);
+                            // Taller characters sit on the same baseline
+                            let y = height - style.height * CELL_HEIGHT;
+                            blit_scaled(&mut band, &glyph, x, y, style.width, style.height);
+                            x += style.width * CELL_WIDTH;
+                        }
+                        bands.push(band);
+                    }
+                }
+                Row::Image { bitmap, align, .. } => {
+                    let mut band = Bitmap::new(width, bitmap.height);
+                    let x = offset(*align, bitmap.width, width);
+                    blit_scaled(&mut band, bitmap, x, 0, 1, 1);
+                    bands.push(band);
+                }
+                Row::Cut => {
+                    let mut band = Bitmap::new(width, CELL_HEIGHT);
+                    for x in (0..width).filter(|x| x % 16 < 8) {
+                        band.set(x, CELL_HEIGHT / 2, true);
+                    }
+                    bands.push(band);
+                }
+            }
+        }
+
+        let height = bands.iter().map(|b| b.height).sum::<usize>().max(1);
+        let mut page = Bitmap::new(width, height);
+        let mut y = 0;
+        for band in bands {
+            blit_scaled(&mut page, &band, 0, y, 1, 1);
+            y += band.height;
+        }
+        page
+    }
+}
+
+/// Draw target for the glyph renderer.
+struct Canvas<'a>(&'a mut Bitmap);
+
+impl OriginDimensions for Canvas<'_> {
+    fn size(&self) -> Size {
+        Size::new(self.0.width as u32, self.0.height as u32)
+    }
+}
+
+impl DrawTarget for Canvas<'_> {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            let (x, y) = (point.x as usize, point.y as usize);
+            if point.x >= 0 && point.y >= 0 && x < self.0.width && y < self.0.height {
+                self.0.set(x, y, color.is_on());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn render_glyph(c: char, bold: bool) -> Bitmap {
+    let mut cell = Bitmap::new(CELL_WIDTH, CELL_HEIGHT);
+    let style = MonoTextStyle::new(&FONT_10X20, BinaryColor::On);
+    let mut text = [0u8; 4];
+    let _ = Text::with_baseline(c.encode_utf8(&mut text), Point::new(1, 2), style, Baseline::Top)
+        .draw(&mut Canvas(&mut cell));
+
+    if bold {
+        // Emphasis on thermal printers is a double strike one dot over
+        for y in 0..CELL_HEIGHT {
+            for x in (1..CELL_WIDTH).rev() {
+                if cell.get(x - 1, y) {
+                    cell.set(x, y, true);
+                }
+            }
+        }
+    }
+
+    cell
+}
+
+fn blit_scaled(dest: &mut Bitmap, src: &Bitmap, x0: usize, y0: usize, sx: usize, sy: usize) {
+    for y in 0..src.height * sy {
+        for x in 0..src.width * sx {
+            let (dx, dy) = (x0 + x, y0 + y);
+            if dx < dest.width && dy < dest.height && src.get(x / sx, y / sy) {
+                dest.set(dx, dy, true);
+            }
+        }
+    }
+}
+
+fn to_png(bitmap: &Bitmap) -> Result<Vec<u8>, String> {
+    let pixels = bitmap.pixels.iter().map(|&black| if black { 0 } else { 255 }).collect();
+    let image = image::GrayImage::from_raw(bitmap.width as u32, bitmap.height as u32, pixels)
+        .ok_or("Preview bitmap has the wrong size")?;
+
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode preview: {}", e))?;
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::escpos::EscPos;
+
+    fn narrow() -> PrinterProfile {
+        PrinterProfile {
+            dot_width: 240,
+            ..Default::default()
+        }
+    }
+
+    fn text_of(build: impl FnOnce(&mut EscPos)) -> String {
+        let profile = narrow();
+        let mut p = EscPos::new(&profile);
+        p.init();
+        build(&mut p);
+        Preview::parse(&p.finish(), &profile).to_text()
+    }
+
+    #[test]
+    fn aligns_and_wraps_at_paper_width() {
+        let text = text_of(|p| {
+            p.align(Align::Center).line("JUMLA");
+            p.align(Align::Right).line("TZS 2000");
+            p.align(Align::Left).line(&"x".repeat(25));
+        });
+        assert_eq!(
+            text,
+            "       JUMLA\n            TZS 2000\nxxxxxxxxxxxxxxxxxxxx\nxxxxx\n"
+        );
+    }
+
+    #[test]
+    fn double_width_takes_two_columns() {
+        let bytes = [ESC, 0x61, 1, GS, 0x21, 0x11, b'A', b'B', LF];
+        let preview = Preview::parse(&bytes, &narrow());
+        assert_eq!(preview.to_text(), "        AB\n");
+        assert_eq!(preview.to_bitmap().height, 2 * CELL_HEIGHT);
+    }
+
+    #[test]
+    fn decodes_through_the_codepage() {
+        assert_eq!(text_of(|p| { p.line("Josée ½"); }), "Josée ½\n");
+    }
+
+    #[test]
+    fn marks_qr_barcode_and_cut() {
+        let text = text_of(|p| {
+            p.qr("*150*01*45107230*3000#");
+            p.barcode("20240001");
+            p.feed(1).cut();
+        });
+        assert_eq!(
+            text,
+            "[QR *150*01*45107230*3000#]\n[BARCODE 20240001]\n\n------- CUT --------\n"
+        );
+    }
+
+    #[test]
+    fn renders_png_at_paper_width() {
+        let profile = PrinterProfile::default();
+        let mut p = EscPos::new(&profile);
+        p.init().double_height(true).line("CHATO").cut();
+
+        let rendered = render(&p.finish(), &profile).unwrap();
+        assert_eq!(rendered.width, 576);
+        assert_eq!(rendered.height, 2 * CELL_HEIGHT + CELL_HEIGHT);
+        assert!(rendered.image.starts_with("data:image/png;base64,"));
+    }
+}
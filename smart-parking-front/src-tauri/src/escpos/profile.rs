@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+
+use super::models::CutCommand;
+use super::status::StatusQuery;
+use super::{BarcodeOptions, Codepage, DrawerOptions, LogoOptions, QrOptions};
+
+/// Per-printer settings sent along with a print request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrinterProfile {
+    /// Printer model id from the printer database. Its capabilities replace
+    /// the matching settings below when a job is rendered.
+    pub model: Option<String>,
+    pub codepage: Codepage,
+    /// `ESC t` page number, for printers that don't use Epson's numbering.
+    pub codepage_number: Option<u8>,
+    /// Printable width in dots: 576 for 80mm paper, 384 for 58mm.
+    pub dot_width: u16,
+    pub qr: QrOptions,
+    pub barcode: BarcodeOptions,
+    /// Header image printed above the company name.
+    pub logo: Option<LogoOptions>,
+    pub status_query: StatusQuery,
+    /// Cut command, when it differs from the model's (or GS V 0 without one).
+    pub cut: Option<CutCommand>,
+    /// Cash drawer chained to the printer's kick-out connector.
+    pub drawer: DrawerOptions,
+}
+
+impl Default for PrinterProfile {
+    fn default() -> Self {
+        Self {
+            model: None,
+            codepage: Codepage::default(),
+            codepage_number: None,
+            dot_width: 576,
+            qr: QrOptions::default(),
+            barcode: BarcodeOptions::default(),
+            logo: None,
+            status_query: StatusQuery::default(),
+            cut: None,
+            drawer: DrawerOptions::default(),
+        }
+    }
+}
+
+impl PrinterProfile {
+    pub fn page_number(&self) -> u8 {
+        self.codepage_number
+            .unwrap_or_else(|| self.codepage.default_page_number())
+    }
+}
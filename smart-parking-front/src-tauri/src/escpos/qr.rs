@@ -0,0 +1,149 @@
+use qrcode::{Color, EcLevel, QrCode};
+use serde::{Deserialize, Serialize};
+
+use super::raster::Bitmap;
+use super::GS;
+
+/// How a printer gets QR codes: its own GS ( k encoder, or a bitmap we render.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum QrMode {
+    #[default]
+    Native,
+    Raster,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum QrErrorCorrection {
+    L,
+    #[default]
+    M,
+    Q,
+    H,
+}
+
+impl QrErrorCorrection {
+    fn escpos_value(self) -> u8 {
+        match self {
+            QrErrorCorrection::L => 0x30,
+            QrErrorCorrection::M => 0x31,
+            QrErrorCorrection::Q => 0x32,
+            QrErrorCorrection::H => 0x33,
+        }
+    }
+
+    fn ec_level(self) -> EcLevel {
+        match self {
+            QrErrorCorrection::L => EcLevel::L,
+            QrErrorCorrection::M => EcLevel::M,
+            QrErrorCorrection::Q => EcLevel::Q,
+            QrErrorCorrection::H => EcLevel::H,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QrOptions {
+    pub mode: QrMode,
+    /// Module size in dots (1-16).
+    pub module_size: u8,
+    pub error_correction: QrErrorCorrection,
+}
+
+impl Default for QrOptions {
+    fn default() -> Self {
+        Self {
+            mode: QrMode::Native,
+            module_size: 8,
+            error_correction: QrErrorCorrection::M,
+        }
+    }
+}
+
+impl QrOptions {
+    fn clamped_module_size(&self) -> u8 {
+        self.module_size.clamp(1, 16)
+    }
+}
+
+/// GS ( k: model 2, module size, error correction, store data, print.
+pub fn native_qr_commands(data: &str, options: &QrOptions) -> Vec<u8> {
+    let bytes = data.as_bytes();
+    let len = bytes.len();
+    let pl = ((len + 3) % 256) as u8;
+    let ph = ((len + 3) / 256) as u8;
+
+    let mut c = Vec::new();
+    c.extend_from_slice(&[GS, 0x28, 0x6B, 0x04, 0x00, 0x31, 0x41, 0x32, 0x00]);
+    c.extend_from_slice(&[GS, 0x28, 0x6B, 0x03, 0x00, 0x31, 0x43, options.clamped_module_size()]);
+    c.extend_from_slice(&[
+        GS, 0x28, 0x6B, 0x03, 0x00, 0x31, 0x45,
+        options.error_correction.escpos_value(),
+    ]);
+    c.extend_from_slice(&[GS, 0x28, 0x6B, pl, ph, 0x31, 0x50, 0x30]);
+    c.extend_from_slice(bytes);
+    c.extend_from_slice(&[GS, 0x28, 0x6B, 0x03, 0x00, 0x31, 0x51, 0x30]);
+    c
+}
+
+/// Modules of white space a scanner needs around the symbol.
+const QUIET_ZONE: usize = 4;
+
+/// Encodes the QR symbol here and scales every module to `module_size` dots,
+/// or less if the symbol and its quiet zone wouldn't fit `dot_width`.
+pub fn qr_bitmap(data: &str, options: &QrOptions, dot_width: usize) -> Result<Bitmap, String> {
+    let code = QrCode::with_error_correction_level(data, options.error_correction.ec_level())
+        .map_err(|e| format!("Failed to encode QR code: {}", e))?;
+
+    let modules = code.width();
+    let span = modules + 2 * QUIET_ZONE;
+    let scale = (options.clamped_module_size() as usize).min(dot_width / span).max(1);
+    let colors = code.to_colors();
+    let symbol = QUIET_ZONE..QUIET_ZONE + modules;
+
+    let mut bitmap = Bitmap::new(span * scale, span * scale);
+    for y in 0..bitmap.height {
+        for x in 0..bitmap.width {
+            let (mx, my) = (x / scale, y / scale);
+            if symbol.contains(&mx) && symbol.contains(&my) {
+                let module = colors[(my - QUIET_ZONE) * modules + mx - QUIET_ZONE];
+                bitmap.set(x, y, module == Color::Dark);
+            }
+        }
+    }
+
+    Ok(bitmap)
+}
+
+/// QR code as a GS v 0 raster image, for printers that ignore GS ( k.
+pub fn raster_qr_commands(data: &str, options: &QrOptions, dot_width: usize) -> Result<Vec<u8>, String> {
+    qr_bitmap(data, options, dot_width).map(|b| b.to_raster_command())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raster_fits_narrow_paper_with_quiet_zone() {
+        // 100 characters needs a version 5+ symbol, 37+ modules wide
+        let data = "https://verify.tra.go.tz/".to_string() + &"A1B2C3D4E5".repeat(8);
+        let options = QrOptions::default();
+
+        let bitmap = qr_bitmap(&data, &options, 384).unwrap();
+        assert!(bitmap.width <= 384);
+        assert_eq!(bitmap.width, bitmap.height);
+        let scale = bitmap.width / (QrCode::new(data.as_bytes()).unwrap().width() + 2 * QUIET_ZONE);
+        assert!((1..8).contains(&scale));
+
+        // The quiet zone stays white and the finder pattern starts right after it
+        let edge = QUIET_ZONE * scale;
+        assert!((0..bitmap.width).all(|x| (0..edge).all(|y| !bitmap.get(x, y))));
+        assert!(bitmap.get(edge, edge));
+
+        // Small symbols keep the configured size
+        let small = qr_bitmap("RL0001", &options, 576).unwrap();
+        assert_eq!(small.width, (21 + 2 * QUIET_ZONE) * 8);
+    }
+}
@@ -0,0 +1,56 @@
+use super::GS;
+
+/// 1-bit image, row-major, `true` is a printed (black) dot.
+#[derive(Debug, Clone)]
+pub struct Bitmap {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<bool>,
+}
+
+impl Bitmap {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![false; width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, black: bool) {
+        self.pixels[y * self.width + x] = black;
+    }
+
+    /// GS v 0 m xL xH yL yH d1...dk, with rows padded to whole bytes.
+    pub fn to_raster_command(&self) -> Vec<u8> {
+        let bytes_per_row = self.width.div_ceil(8);
+
+        let mut c = Vec::with_capacity(8 + bytes_per_row * self.height);
+        c.extend_from_slice(&[GS, 0x76, 0x30, 0x00]);
+        c.extend_from_slice(&[
+            (bytes_per_row % 256) as u8,
+            (bytes_per_row / 256) as u8,
+            (self.height % 256) as u8,
+            (self.height / 256) as u8,
+        ]);
+
+        for y in 0..self.height {
+            for byte in 0..bytes_per_row {
+                let mut b = 0u8;
+                for bit in 0..8 {
+                    let x = byte * 8 + bit;
+                    if x < self.width && self.get(x, y) {
+                        b |= 0x80 >> bit;
+                    }
+                }
+                c.push(b);
+            }
+        }
+
+        c
+    }
+}
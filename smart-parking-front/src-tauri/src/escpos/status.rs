@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+
+use super::GS;
+
+pub const DLE: u8 = 0x10;
+pub const EOT: u8 = 0x04;
+
+/// Which real-time status commands the printer answers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusQuery {
+    /// No read-back: only what the OS knows about the printer is reported.
+    #[default]
+    None,
+    /// DLE EOT 1-4.
+    DleEot,
+    /// DLE EOT 1-3, with the paper sensor read through GS r 1 for printers
+    /// that don't implement DLE EOT 4.
+    GsR,
+}
+
+impl StatusQuery {
+    /// Commands to send, each answered by a single status byte.
+    pub fn requests(self) -> Vec<[u8; 3]> {
+        match self {
+            StatusQuery::None => vec![],
+            StatusQuery::DleEot => vec![
+                [DLE, EOT, 1],
+                [DLE, EOT, 2],
+                [DLE, EOT, 3],
+                [DLE, EOT, 4],
+            ],
+            StatusQuery::GsR => vec![
+                [DLE, EOT, 1],
+                [DLE, EOT, 2],
+                [DLE, EOT, 3],
+                [GS, 0x72, 1],
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrinterStatus {
+    pub online: bool,
+    pub paper_near_end: bool,
+    pub paper_out: bool,
+    pub cover_open: bool,
+    pub error: bool,
+    /// Human readable causes, e.g. "autocutter error".
+    pub details: Vec<String>,
+}
+
+impl PrinterStatus {
+    /// Why a receipt sent now would not come out, if anything.
+    pub fn blocking_reason(&self) -> Option<String> {
+        if self.paper_out {
+            Some("Printer is out of paper".to_string())
+        } else if self.cover_open {
+            Some("Printer cover is open".to_string())
+        } else if self.error {
+            Some(match self.details.first() {
+                Some(d) => format!("Printer error: {}", d),
+                None => "Printer reported an error".to_string(),
+            })
+        } else if !self.online {
+            Some("Printer is offline".to_string())
+        } else {
+            None
+        }
+    }
+}
+
+/// DLE EOT replies always have bits 1 and 4 set and bits 0 and 7 clear.
+fn is_dle_eot_reply(b: u8) -> bool {
+    b & 0x93 == 0x12
+}
+
+/// Decodes the replies to `query.requests()`, in the same order.
+pub fn parse_status(query: StatusQuery, replies: &[u8]) -> PrinterStatus {
+    let mut s = PrinterStatus {
+        online: true,
+        ..Default::default()
+    };
+
+    for (i, &b) in replies.iter().enumerate() {
+        let gs_r = query == StatusQuery::GsR && i == 3;
+        if !gs_r && !is_dle_eot_reply(b) {
+            s.details.push(format!("unexpected status byte 0x{:02X}", b));
+            continue;
+        }
+
+        match (i, gs_r) {
+            // Printer status
+            (0, _) => s.online = b & 0x08 == 0,
+            // Offline cause
+            (1, _) => {
+                if b & 0x04 != 0 {
+                    s.cover_open = true;
+                }
+                if b & 0x20 != 0 {
+                    s.paper_out = true;
+                }
+                if b & 0x40 != 0 {
+                    s.error = true;
+                }
+            }
+            // Error cause
+            (2, _) => {
+                let causes = [
+                    (0x04, "mechanical error"),
+                    (0x08, "autocutter error"),
+                    (0x20, "unrecoverable error"),
+                    (0x40, "auto-recoverable error"),
+                ];
+                for (bit, cause) in causes {
+                    if b & bit != 0 {
+                        s.error = true;
+                        s.details.push(cause.to_string());
+                    }
+                }
+            }
+            // Roll paper sensor (DLE EOT 4)
+            (3, false) => {
+                if b & 0x0C != 0 {
+                    s.paper_near_end = true;
+                }
+                if b & 0x60 != 0 {
+                    s.paper_out = true;
+                }
+            }
+            // Paper sensor (GS r 1)
+            (3, true) => {
+                if b & 0x03 != 0 {
+                    s.paper_near_end = true;
+                }
+                if b & 0x0C != 0 {
+                    s.paper_out = true;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    s
+}
@@ -0,0 +1,70 @@
+use std::thread;
+
+use tiny_http::{Header, Response, Server};
+
+use super::vfd::xml_field;
+
+const MOCK_TOKEN: &str = "mock-vfd-token";
+
+/// Stand-in for the TRA VFD API on localhost, for training and testing
+/// without registering a device. Accepts every well formed receipt that
+/// carries the token it handed out, and rejects the rest the way the VFD does.
+///
+/// Returns the base URL to point the client at.
+pub fn spawn_mock_vfd(addr: &str) -> Result<String, String> {
+    let server = Server::http(addr).map_err(|e| format!("Failed to start mock VFD on {}: {}", addr, e))?;
+    let url = format!("http://{}", server.server_addr());
+    println!("[Rust] Mock VFD listening on {}", url);
+
+    thread::spawn(move || {
+        let mut acks = 0u64;
+
+        for mut request in server.incoming_requests() {
+            let mut body = String::new();
+            let _ = request.as_reader().read_to_string(&mut body);
+
+            let response = match request.url() {
+                "/vfdtoken" => Response::from_string(format!(
+                    "{{\"access_token\":\"{}\",\"token_type\":\"bearer\",\"expires_in\":86400}}",
+                    MOCK_TOKEN
+                ))
+                .with_header(Header::from_bytes("Content-Type", "application/json").unwrap()),
+                "/api/efdmsRctInfo" => {
+                    let authorized = request
+                        .headers()
+                        .iter()
+                        .any(|h| h.field.equiv("Authorization") && h.value.as_str() == format!("bearer {}", MOCK_TOKEN));
+
+                    let (code, message) = if !authorized {
+                        ("3", "Invalid token")
+                    } else if !has_field(&body, "EFDMSSIGNATURE") {
+                        ("2", "Invalid signature")
+                    } else if !has_field(&body, "RCTVNUM") {
+                        ("4", "Invalid receipt")
+                    } else {
+                        ("0", "Success")
+                    };
+
+                    let rctnum = xml_field(&body, "RCTNUM").unwrap_or_default();
+                    acks += 1;
+                    Response::from_string(format!(
+                        "<?xml version=\"1.0\" encoding=\"UTF-8\"?><EFDMS><RCTACK><RCTNUM>{}</RCTNUM>\
+                         <ACKNUM>{}</ACKNUM><ACKCODE>{}</ACKCODE><ACKMSG>{}</ACKMSG></RCTACK>\
+                         <EFDMSSIGNATURE>MOCK</EFDMSSIGNATURE></EFDMS>",
+                        rctnum, acks, code, message
+                    ))
+                    .with_header(Header::from_bytes("Content-Type", "application/xml").unwrap())
+                }
+                _ => Response::from_string("Not found").with_status_code(404),
+            };
+
+            let _ = request.respond(response);
+        }
+    });
+
+    Ok(url)
+}
+
+fn has_field(xml: &str, tag: &str) -> bool {
+    xml_field(xml, tag).is_some_and(|v| !v.is_empty())
+}
@@ -0,0 +1,534 @@
+pub mod mock;
+pub mod vfd;
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use chrono::Local;
+use rsa::RsaPrivateKey;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::util::{corrupt_copy, now_secs, read_json, write_json};
+use vfd::{Item, ReceiptPayload, VfdClient};
+
+/// Event emitted with a `FiscalReceipt` whenever its upload state changes.
+pub const FISCAL_UPDATED_EVENT: &str = "fiscal-receipt-updated";
+
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+const MAX_BACKOFF_SECS: u64 = 15 * 60;
+/// Uploaded receipts kept in the ledger after the VFD has answered.
+const KEEP_FINISHED: usize = 1000;
+
+/// VFD registration details, kept in `fiscal_config.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FiscalConfig {
+    pub enabled: bool,
+    /// Base URL of the TRA VFD API.
+    pub endpoint: String,
+    /// Send receipts to the built-in mock VFD instead of `endpoint`.
+    pub mock: bool,
+    pub tin: String,
+    pub reg_id: String,
+    pub efd_serial: String,
+    /// RECEIPTCODE from registration; prefixes every verification code.
+    pub receipt_code: String,
+    pub cert_serial: String,
+    pub username: String,
+    pub password: String,
+    /// PEM (PKCS#8 or PKCS#1) export of the key in the TRA issued certificate.
+    pub private_key_path: String,
+    /// 1 = standard rate, 5 = exempt. Council parking levies are exempt.
+    pub tax_code: u8,
+    pub verify_url: String,
+}
+
+impl Default for FiscalConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: "https://vfd.tra.go.tz".to_string(),
+            mock: false,
+            tin: String::new(),
+            reg_id: String::new(),
+            efd_serial: String::new(),
+            receipt_code: String::new(),
+            cert_serial: String::new(),
+            username: String::new(),
+            password: String::new(),
+            private_key_path: String::new(),
+            tax_code: 5,
+            verify_url: "https://verify.tra.go.tz/".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubmissionState {
+    Pending,
+    Acknowledged,
+    Rejected,
+}
+
+/// A receipt signed for the VFD. The verification code is assigned locally
+/// from the counters, so it can be printed before the upload goes through.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FiscalReceipt {
+    pub receipt_number: String,
+    pub gc: u64,
+    pub dc: u64,
+    pub znum: String,
+    pub verification_code: String,
+    pub verification_url: String,
+    pub date: String,
+    pub time: String,
+    pub document: String,
+    pub state: SubmissionState,
+    pub ack_code: Option<String>,
+    pub ack_message: Option<String>,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub issued_at: u64,
+    pub next_attempt_at: u64,
+}
+
+/// Counters and the upload backlog, kept in `fiscal_ledger.json`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Ledger {
+    /// Global counter, never reset.
+    gc: u64,
+    /// Daily counter for `day`.
+    dc: u64,
+    day: String,
+    receipts: Vec<FiscalReceipt>,
+    /// GC of every receipt number ever fiscalised. Unlike `receipts` this is
+    /// never pruned, so an old receipt can't be signed a second time.
+    #[serde(default)]
+    issued: BTreeMap<String, u64>,
+}
+
+pub struct Fiscal {
+    dir: PathBuf,
+    config: Mutex<FiscalConfig>,
+    ledger: Mutex<Ledger>,
+    key: Mutex<Option<RsaPrivateKey>>,
+    mock_url: Mutex<Option<String>>,
+    /// Held while uploading, so the worker and a manual retry don't send the
+    /// same receipt twice.
+    submitting: Mutex<()>,
+}
+
+
+/// TRA payment types for the `payment_method` the frontend sends.
+fn payment_type(method: Option<&str>) -> &'static str {
+    match method.map(|m| m.trim().to_lowercase()).as_deref() {
+        Some("cash") | None => "CASH",
+        Some("card") => "CCARD",
+        Some("cheque") => "CHEQUE",
+        Some("invoice") | Some("gepg") => "INVOICE",
+        Some(_) => "EMONEY",
+    }
+}
+
+impl Fiscal {
+    /// Loads the config and ledger. A ledger that can't be read stops the app
+    /// rather than starting the TRA counters again from zero, and keeps
+    /// stopping it until the ledger is put back.
+    pub fn load(dir: PathBuf) -> Result<Self, String> {
+        let config: FiscalConfig = read_json(&dir.join("fiscal_config.json"))?;
+        let ledger_path = dir.join("fiscal_ledger.json");
+        if !ledger_path.exists() {
+            if let Some(aside) = corrupt_copy(&dir, "fiscal_ledger.json") {
+                return Err(format!(
+                    "Fiscal ledger is missing and {} was set aside as corrupt; restore the counters into {} before issuing receipts",
+                    aside.display(),
+                    ledger_path.display()
+                ));
+            }
+        }
+        let mut ledger: Ledger = read_json(&ledger_path)?;
+        for r in &ledger.receipts {
+            ledger.issued.entry(r.receipt_number.clone()).or_insert(r.gc);
+        }
+
+        let pending = ledger.receipts.iter().filter(|r| r.state == SubmissionState::Pending).count();
+        println!("[Rust] Fiscal ledger loaded: GC {}, {} receipt(s) waiting for the VFD", ledger.gc, pending);
+
+        let fiscal = Self {
+            dir,
+            config: Mutex::new(config),
+            ledger: Mutex::new(ledger),
+            key: Mutex::new(None),
+            mock_url: Mutex::new(None),
+            submitting: Mutex::new(()),
+        };
+        fiscal.start_mock_if_configured();
+        Ok(fiscal)
+    }
+
+    fn start_mock_if_configured(&self) {
+        let mut mock_url = self.mock_url.lock().unwrap();
+        if self.config().mock && mock_url.is_none() {
+            match mock::spawn_mock_vfd("127.0.0.1:0") {
+                Ok(url) => *mock_url = Some(url),
+                Err(e) => println!("[Rust] {}", e),
+            }
+        }
+    }
+
+    pub fn config(&self) -> FiscalConfig {
+        self.config.lock().unwrap().clone()
+    }
+
+    pub fn save_config(&self, config: FiscalConfig) -> Result<(), String> {
+        write_json(&self.dir.join("fiscal_config.json"), &config)?;
+        *self.config.lock().unwrap() = config;
+        *self.key.lock().unwrap() = None;
+        self.start_mock_if_configured();
+        Ok(())
+    }
+
+    fn endpoint(&self, config: &FiscalConfig) -> Result<String, String> {
+        if config.mock {
+            return self.mock_url.lock().unwrap().clone().ok_or_else(|| "Mock VFD is not running".to_string());
+        }
+        Ok(config.endpoint.clone())
+    }
+
+    /// The signing key, loaded once. The mock VFD doesn't check signatures,
+    /// so without a key it gets one made up for the session.
+    fn signing_key(&self, config: &FiscalConfig) -> Result<RsaPrivateKey, String> {
+        let mut key = self.key.lock().unwrap();
+        if let Some(k) = key.as_ref() {
+            return Ok(k.clone());
+        }
+
+        let loaded = if config.private_key_path.is_empty() && config.mock {
+            RsaPrivateKey::new(&mut rsa::rand_core::OsRng, 1024)
+                .map_err(|e| format!("Failed to create mock signing key: {}", e))?
+        } else {
+            vfd::load_private_key(&config.private_key_path)?
+        };
+
+        *key = Some(loaded.clone());
+        Ok(loaded)
+    }
+
+    fn save_ledger(&self, ledger: &mut Ledger) -> Result<(), String> {
+        let finished = |r: &FiscalReceipt| r.state != SubmissionState::Pending;
+        let excess = ledger.receipts.iter().filter(|r| finished(r)).count().saturating_sub(KEEP_FINISHED);
+        let mut dropped = 0;
+        ledger.receipts.retain(|r| {
+            if dropped < excess && finished(r) {
+                dropped += 1;
+                false
+            } else {
+                true
+            }
+        });
+
+        write_json(&self.dir.join("fiscal_ledger.json"), &*ledger)
+    }
+
+    /// Signs a receipt and adds it to the upload backlog. `None` when
+    /// fiscalisation is switched off. The same receipt number always gets the
+    /// same fiscal receipt, so a retried print doesn't use up a counter; one
+    /// pruned from the ledger since is refused rather than signed again.
+    pub fn issue(&self, receipt_number: &str, data: &serde_json::Value) -> Result<Option<FiscalReceipt>, String> {
+        let config = self.config();
+        if !config.enabled {
+            return Ok(None);
+        }
+
+        let mut ledger = self.ledger.lock().unwrap();
+        if let Some(existing) = ledger.receipts.iter().find(|r| r.receipt_number == receipt_number) {
+            return Ok(Some(existing.clone()));
+        }
+        if let Some(gc) = ledger.issued.get(receipt_number) {
+            return Err(format!("Receipt {} was already fiscalised as GC {}", receipt_number, gc));
+        }
+
+        let str_field = |key: &str| data.get(key).and_then(|v| v.as_str());
+        let amount = str_field("item_amount")
+            .or_else(|| str_field("total_amount"))
+            .and_then(vfd::parse_amount)
+            .ok_or_else(|| format!("Receipt {} has no amount to fiscalise", receipt_number))?;
+
+        let now = Local::now();
+        let today = now.format("%Y%m%d").to_string();
+        let dc = if ledger.day == today { ledger.dc + 1 } else { 1 };
+        let gc = ledger.gc + 1;
+        let time = now.format("%H:%M:%S").to_string();
+        let verification_code = format!("{}{}", config.receipt_code, gc);
+
+        let payload = ReceiptPayload {
+            date: now.format("%Y-%m-%d").to_string(),
+            time: time.clone(),
+            gc,
+            dc,
+            znum: today.clone(),
+            rct_vnum: verification_code.clone(),
+            items: vec![Item {
+                description: str_field("item_description").unwrap_or("Maegesho").to_string(),
+                quantity: str_field("item_quantity").and_then(vfd::parse_amount).unwrap_or(1.0),
+                amount,
+            }],
+            payment_type: payment_type(str_field("payment_method")).to_string(),
+        };
+
+        let rct = vfd::receipt_xml(&config, &payload);
+        let signature = vfd::sign(&self.signing_key(&config)?, &rct)?;
+
+        let receipt = FiscalReceipt {
+            receipt_number: receipt_number.to_string(),
+            gc,
+            dc,
+            znum: today.clone(),
+            verification_url: format!("{}{}_{}", config.verify_url, verification_code, time.replace(':', "")),
+            verification_code,
+            date: payload.date,
+            time,
+            document: vfd::signed_document(&rct, &signature),
+            state: SubmissionState::Pending,
+            ack_code: None,
+            ack_message: None,
+            attempts: 0,
+            last_error: None,
+            issued_at: now_secs(),
+            next_attempt_at: now_secs(),
+        };
+
+        // Counters only move once the receipt is signed
+        ledger.gc = gc;
+        ledger.dc = dc;
+        ledger.day = today;
+        ledger.issued.insert(receipt_number.to_string(), gc);
+        ledger.receipts.push(receipt.clone());
+        self.save_ledger(&mut ledger)?;
+
+        println!("[Rust] Fiscal receipt {} issued as GC {} ({})", receipt_number, gc, receipt.verification_code);
+        Ok(Some(receipt))
+    }
+
+    pub fn receipts(&self) -> Vec<FiscalReceipt> {
+        self.ledger.lock().unwrap().receipts.clone()
+    }
+
+    /// Makes every pending receipt due now.
+    pub fn retry_pending(&self) -> Result<(), String> {
+        let mut ledger = self.ledger.lock().unwrap();
+        let now = now_secs();
+        for r in ledger.receipts.iter_mut().filter(|r| r.state == SubmissionState::Pending) {
+            r.next_attempt_at = now;
+        }
+        self.save_ledger(&mut ledger)
+    }
+
+    /// Uploads the receipts that are due and returns the ones that changed.
+    pub fn submit_due(&self) -> Vec<FiscalReceipt> {
+        let Ok(_guard) = self.submitting.try_lock() else {
+            return vec![];
+        };
+
+        let now = now_secs();
+        let due: Vec<FiscalReceipt> = self
+            .ledger
+            .lock()
+            .unwrap()
+            .receipts
+            .iter()
+            .filter(|r| r.state == SubmissionState::Pending && r.next_attempt_at <= now)
+            .cloned()
+            .collect();
+        if due.is_empty() {
+            return vec![];
+        }
+
+        let config = self.config();
+        let outcomes: Vec<(String, Result<vfd::Ack, String>)> = match self.endpoint(&config) {
+            Ok(endpoint) => {
+                let client = VfdClient::new(&endpoint);
+                match client.token(&config) {
+                    Ok(token) => due
+                        .iter()
+                        .map(|r| (r.receipt_number.clone(), client.submit(&config, &token, &r.document)))
+                        .collect(),
+                    Err(e) => due.iter().map(|r| (r.receipt_number.clone(), Err(e.clone()))).collect(),
+                }
+            }
+            Err(e) => due.iter().map(|r| (r.receipt_number.clone(), Err(e.clone()))).collect(),
+        };
+
+        let mut ledger = self.ledger.lock().unwrap();
+        let mut updated = Vec::new();
+        for (number, outcome) in outcomes {
+            let Some(r) = ledger.receipts.iter_mut().find(|r| r.receipt_number == number) else {
+                continue;
+            };
+            r.attempts += 1;
+
+            match outcome {
+                Ok(ack) => {
+                    r.state = if ack.accepted() {
+                        SubmissionState::Acknowledged
+                    } else {
+                        SubmissionState::Rejected
+                    };
+                    println!("[Rust] VFD answered {} for receipt {}: {}", ack.code, number, ack.message);
+                    r.ack_code = Some(ack.code);
+                    r.ack_message = Some(ack.message);
+                    r.last_error = None;
+                }
+                Err(e) => {
+                    println!("[Rust] Fiscal receipt {} kept in the backlog: {}", number, e);
+                    let backoff = (POLL_INTERVAL.as_secs() << r.attempts.min(5)).min(MAX_BACKOFF_SECS);
+                    r.next_attempt_at = now_secs() + backoff;
+                    r.last_error = Some(e);
+                }
+            }
+            updated.push(r.clone());
+        }
+
+        if let Err(e) = self.save_ledger(&mut ledger) {
+            println!("[Rust] {}", e);
+        }
+        updated
+    }
+}
+
+fn submit_and_emit(app: &AppHandle) {
+    for receipt in app.state::<Fiscal>().submit_due() {
+        if let Err(e) = app.emit(FISCAL_UPDATED_EVENT, &receipt) {
+            println!("[Rust] Failed to emit fiscal receipt update: {}", e);
+        }
+    }
+}
+
+/// Uploads in the background straight away, so printing never waits on TRA.
+pub fn submit_soon(app: &AppHandle) {
+    let app = app.clone();
+    thread::spawn(move || submit_and_emit(&app));
+}
+
+/// Background thread that works through the offline backlog.
+pub fn start_worker(app: AppHandle) {
+    thread::spawn(move || loop {
+        submit_and_emit(&app);
+        thread::sleep(POLL_INTERVAL);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use base64::Engine;
+    use rsa::{Pkcs1v15Sign, RsaPublicKey};
+    use sha1::{Digest, Sha1};
+
+    use crate::util::test_store;
+
+    fn mock_fiscal(name: &str) -> Fiscal {
+        let fiscal = test_store(&format!("fiscal-{}", name), Fiscal::load);
+        fiscal
+            .save_config(FiscalConfig {
+                enabled: true,
+                mock: true,
+                tin: "123456789".to_string(),
+                receipt_code: "9A2B4C".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        fiscal
+    }
+
+    fn receipt(amount: &str) -> serde_json::Value {
+        serde_json::json!({ "item_description": "Maegesho", "item_amount": amount, "payment_method": "cash" })
+    }
+
+    #[test]
+    fn issues_signed_receipts_with_running_counters() {
+        let fiscal = mock_fiscal("issue");
+        let first = fiscal.issue("RCP-1", &receipt("2,000")).unwrap().unwrap();
+        let second = fiscal.issue("RCP-2", &receipt("500")).unwrap().unwrap();
+        let again = fiscal.issue("RCP-1", &receipt("2,000")).unwrap().unwrap();
+
+        assert_eq!((first.gc, first.dc, second.gc, second.dc), (1, 1, 2, 2));
+        assert_eq!(again.gc, 1);
+        assert_eq!(first.verification_code, "9A2B4C1");
+        assert_eq!(first.znum, Local::now().format("%Y%m%d").to_string());
+        assert!(first.verification_url.starts_with("https://verify.tra.go.tz/9A2B4C1_"));
+        assert!(first.document.contains("<TOTALTAXINCL>2000.00</TOTALTAXINCL>"));
+
+        let rct = &first.document[first.document.find("<RCT>").unwrap()..first.document.find("<EFDMSSIGNATURE>").unwrap()];
+        let signature = BASE64.decode(vfd::xml_field(&first.document, "EFDMSSIGNATURE").unwrap()).unwrap();
+        let public = RsaPublicKey::from(fiscal.signing_key(&fiscal.config()).unwrap());
+        public
+            .verify(Pkcs1v15Sign::new::<Sha1>(), &Sha1::digest(rct.as_bytes()), &signature)
+            .unwrap();
+    }
+
+    #[test]
+    fn uploads_backlog_to_mock_vfd() {
+        let fiscal = mock_fiscal("upload");
+        fiscal.issue("RCP-1", &receipt("2,000")).unwrap();
+        fiscal.issue("RCP-2", &receipt("1,000")).unwrap();
+
+        let updated = fiscal.submit_due();
+        assert_eq!(updated.len(), 2);
+        assert!(updated.iter().all(|r| r.state == SubmissionState::Acknowledged));
+        assert_eq!(updated[0].ack_code.as_deref(), Some("0"));
+        assert!(fiscal.submit_due().is_empty());
+    }
+
+    #[test]
+    fn keeps_receipts_queued_while_vfd_is_unreachable() {
+        let fiscal = mock_fiscal("offline");
+        fiscal.issue("RCP-1", &receipt("2,000")).unwrap();
+
+        let mut config = fiscal.config();
+        config.mock = false;
+        config.endpoint = "http://127.0.0.1:9".to_string();
+        fiscal.save_config(config).unwrap();
+
+        let updated = fiscal.submit_due();
+        assert_eq!(updated[0].state, SubmissionState::Pending);
+        assert!(updated[0].last_error.is_some());
+        assert!(updated[0].next_attempt_at > now_secs());
+    }
+
+    #[test]
+    fn corrupt_ledger_is_set_aside_not_reset() {
+        let fiscal = mock_fiscal("corrupt");
+        fiscal.issue("RCP-1", &receipt("2,000")).unwrap();
+        let path = fiscal.dir.join("fiscal_ledger.json");
+        fs::write(&path, "{\"gc\": 1, \"dc\"").unwrap();
+
+        let error = Fiscal::load(fiscal.dir.clone()).err().unwrap();
+        assert!(error.contains("is corrupt"));
+        assert!(!path.exists());
+        let aside = corrupt_copy(&fiscal.dir, "fiscal_ledger.json").unwrap();
+        assert_eq!(fs::read_to_string(&aside).unwrap(), "{\"gc\": 1, \"dc\"");
+
+        // Still refuses on the next start instead of counting from 1 again
+        assert!(Fiscal::load(fiscal.dir.clone()).err().unwrap().contains("restore the counters"));
+    }
+
+    #[test]
+    fn pruned_receipts_are_never_signed_again() {
+        let fiscal = mock_fiscal("pruned");
+        let first = fiscal.issue("RCP-1", &receipt("2,000")).unwrap().unwrap();
+
+        fiscal.ledger.lock().unwrap().receipts.clear();
+        let error = fiscal.issue("RCP-1", &receipt("2,000")).err().unwrap();
+        assert!(error.contains(&format!("GC {}", first.gc)));
+        assert_eq!(fiscal.issue("RCP-2", &receipt("500")).unwrap().unwrap().gc, first.gc + 1);
+    }
+}
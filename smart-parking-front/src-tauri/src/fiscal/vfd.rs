@@ -0,0 +1,225 @@
+use std::fs;
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use rsa::pkcs1::DecodeRsaPrivateKey;
+use rsa::pkcs8::DecodePrivateKey;
+use rsa::{Pkcs1v15Sign, RsaPrivateKey};
+use sha1::{Digest, Sha1};
+
+use super::FiscalConfig;
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// One line of a fiscal receipt.
+#[derive(Debug, Clone)]
+pub struct Item {
+    pub description: String,
+    pub quantity: f64,
+    pub amount: f64,
+}
+
+/// Everything in the `<RCT>` element of a VFD receipt upload.
+#[derive(Debug, Clone)]
+pub struct ReceiptPayload {
+    pub date: String,
+    pub time: String,
+    pub gc: u64,
+    pub dc: u64,
+    pub znum: String,
+    pub rct_vnum: String,
+    pub items: Vec<Item>,
+    pub payment_type: String,
+}
+
+/// TRA tax codes: 1 = A standard rate, 2 = B special rate, 3 = C zero rated,
+/// 4 = D special relief, 5 = E exempt.
+fn vat_rate(tax_code: u8) -> (&'static str, f64) {
+    match tax_code {
+        1 => ("A", 0.18),
+        2 => ("B", 0.0),
+        3 => ("C", 0.0),
+        4 => ("D", 0.0),
+        _ => ("E", 0.0),
+    }
+}
+
+pub fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Amounts on receipts come formatted for people ("2,000").
+pub fn parse_amount(s: &str) -> Option<f64> {
+    s.chars()
+        .filter(|c| c.is_ascii_digit() || *c == '.')
+        .collect::<String>()
+        .parse()
+        .ok()
+}
+
+/// The `<RCT>` element, exactly as it is signed.
+pub fn receipt_xml(config: &FiscalConfig, r: &ReceiptPayload) -> String {
+    let (rate, vat) = vat_rate(config.tax_code);
+    let total: f64 = r.items.iter().map(|i| i.amount).sum();
+    let net = total / (1.0 + vat);
+
+    let items: String = r
+        .items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            format!(
+                "<ITEM><ID>{}</ID><DESC>{}</DESC><QTY>{}</QTY><TAXCODE>{}</TAXCODE><AMT>{:.2}</AMT></ITEM>",
+                i + 1,
+                escape_xml(&item.description),
+                item.quantity,
+                config.tax_code,
+                item.amount
+            )
+        })
+        .collect();
+
+    format!(
+        "<RCT><DATE>{}</DATE><TIME>{}</TIME><TIN>{}</TIN><REGID>{}</REGID><EFDSERIAL>{}</EFDSERIAL>\
+         <CUSTIDTYPE>6</CUSTIDTYPE><CUSTID></CUSTID><CUSTNAME></CUSTNAME><MOBILENUM></MOBILENUM>\
+         <RCTNUM>{}</RCTNUM><DC>{}</DC><GC>{}</GC><ZNUM>{}</ZNUM><RCTVNUM>{}</RCTVNUM>\
+         <ITEMS>{}</ITEMS>\
+         <TOTALS><TOTALTAXEXCL>{:.2}</TOTALTAXEXCL><TOTALTAXINCL>{:.2}</TOTALTAXINCL><DISCOUNT>0.00</DISCOUNT></TOTALS>\
+         <PAYMENTS><PMTTYPE>{}</PMTTYPE><PMTAMOUNT>{:.2}</PMTAMOUNT></PAYMENTS>\
+         <VATTOTALS><VATRATE>{}</VATRATE><NETTAMOUNT>{:.2}</NETTAMOUNT><TAXAMOUNT>{:.2}</TAXAMOUNT></VATTOTALS></RCT>",
+        r.date,
+        r.time,
+        escape_xml(&config.tin),
+        escape_xml(&config.reg_id),
+        escape_xml(&config.efd_serial),
+        r.gc,
+        r.dc,
+        r.gc,
+        r.znum,
+        r.rct_vnum,
+        items,
+        net,
+        total,
+        escape_xml(&r.payment_type),
+        total,
+        rate,
+        net,
+        total - net
+    )
+}
+
+pub fn load_private_key(path: &str) -> Result<RsaPrivateKey, String> {
+    let pem = fs::read_to_string(path).map_err(|e| format!("Failed to read VFD signing key {}: {}", path, e))?;
+
+    RsaPrivateKey::from_pkcs8_pem(&pem)
+        .or_else(|_| RsaPrivateKey::from_pkcs1_pem(&pem))
+        .map_err(|e| format!("Invalid VFD signing key {}: {}", path, e))
+}
+
+/// SHA1withRSA over the `<RCT>` element, base64 encoded, as the VFD expects.
+pub fn sign(key: &RsaPrivateKey, rct: &str) -> Result<String, String> {
+    let digest = Sha1::digest(rct.as_bytes());
+    key.sign(Pkcs1v15Sign::new::<Sha1>(), &digest)
+        .map(|sig| BASE64.encode(sig))
+        .map_err(|e| format!("Failed to sign fiscal receipt: {}", e))
+}
+
+/// The complete signed document posted to the VFD.
+pub fn signed_document(rct: &str, signature: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?><EFDMS>{}<EFDMSSIGNATURE>{}</EFDMSSIGNATURE></EFDMS>",
+        rct, signature
+    )
+}
+
+/// Text of the first `<tag>` in a VFD response.
+pub fn xml_field(xml: &str, tag: &str) -> Option<String> {
+    let open = format!("<{}>", tag);
+    let start = xml.find(&open)? + open.len();
+    let end = xml[start..].find(&format!("</{}>", tag))? + start;
+    Some(xml[start..end].trim().to_string())
+}
+
+/// What the VFD said about an upload.
+#[derive(Debug)]
+pub struct Ack {
+    pub code: String,
+    pub message: String,
+}
+
+impl Ack {
+    pub fn accepted(&self) -> bool {
+        self.code == "0"
+    }
+}
+
+/// Talks to the VFD API at one base URL.
+pub struct VfdClient {
+    base_url: String,
+    agent: ureq::Agent,
+}
+
+impl VfdClient {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            agent: ureq::AgentBuilder::new().timeout(HTTP_TIMEOUT).build(),
+        }
+    }
+
+    pub fn token(&self, config: &FiscalConfig) -> Result<String, String> {
+        let response = self
+            .agent
+            .post(&format!("{}/vfdtoken", self.base_url))
+            .send_form(&[
+                ("username", &config.username),
+                ("password", &config.password),
+                ("grant_type", "password"),
+            ])
+            .map_err(|e| format!("VFD token request failed: {}", e))?;
+
+        let body: serde_json::Value = response
+            .into_json()
+            .map_err(|e| format!("Invalid VFD token response: {}", e))?;
+
+        body.get("access_token")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string())
+            .ok_or_else(|| "VFD token response has no access_token".to_string())
+    }
+
+    /// Posts one signed receipt. `Err` means it never got an answer and should
+    /// be tried again; a rejection comes back as an `Ack`.
+    pub fn submit(&self, config: &FiscalConfig, token: &str, document: &str) -> Result<Ack, String> {
+        let response = self
+            .agent
+            .post(&format!("{}/api/efdmsRctInfo", self.base_url))
+            .set("Content-Type", "application/xml")
+            .set("Routing-Key", "vfdrct")
+            .set("Cert-Serial", &BASE64.encode(config.cert_serial.as_bytes()))
+            .set("Client", "webapi")
+            .set("Authorization", &format!("bearer {}", token))
+            .send_string(document);
+
+        let body = match response {
+            Ok(r) => r.into_string(),
+            // The VFD answers rejections with an error status and an ACK body
+            Err(ureq::Error::Status(_, r)) => r.into_string(),
+            Err(e) => return Err(format!("VFD upload failed: {}", e)),
+        }
+        .map_err(|e| format!("Failed to read VFD response: {}", e))?;
+
+        match (xml_field(&body, "ACKCODE"), xml_field(&body, "ACKMSG")) {
+            (Some(code), message) => Ok(Ack {
+                code,
+                message: message.unwrap_or_default(),
+            }),
+            _ => Err(format!("Unexpected VFD response: {}", body.chars().take(200).collect::<String>())),
+        }
+    }
+}
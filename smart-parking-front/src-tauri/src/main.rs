@@ -1,17 +1,145 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod audit;
+mod billing;
+mod callback_server;
 mod commands;  // This imports the entire 'commands' folder/module
+mod database;
+mod escpos;
+mod fiscal;
+mod numbering;
+mod payments;
+mod plates;
+mod print_queue;
+mod printer_preferences;
+mod receipt_history;
 mod serial;
+mod shifts;
+mod sync_queue;
+mod tariff;
+mod transport;
+mod util;
 
-use tauri::Builder;
+use tauri::{Builder, Manager};
 
 fn main() {
     Builder::default()
+        .setup(|app| {
+            let data_dir = app.path().app_data_dir()?;
+            std::fs::create_dir_all(&data_dir)?;
+
+            // Sessions, gates and settings; kept locally so the booth works offline
+            app.manage(database::Database::open(&data_dir.join("parking.sqlite"))?);
+
+            // Local changes replayed to the API whenever it is reachable
+            app.manage(sync_queue::SyncQueue::default());
+            sync_queue::start_worker(app.handle().clone());
+            tariff::start_worker(app.handle().clone());
+            plates::start_worker(app.handle().clone());
+
+            app.manage(escpos::models::PrinterDatabase::load(data_dir.join("printer_models.json")));
+            app.manage(printer_preferences::PrinterPreferences::load(data_dir.join("printer_preferences.json")));
+
+            // Durable print queue, retried in the background until printers come back
+            app.manage(print_queue::PrintQueue::load(data_dir.join("print_queue.json")));
+            print_queue::start_worker(app.handle().clone());
+
+            // TRA fiscalisation, uploaded in the background so printing never waits
+            app.manage(fiscal::Fiscal::load(data_dir.clone())?);
+            fiscal::start_worker(app.handle().clone());
+
+            // GePG bills; control numbers and payments arrive on the callback server
+            app.manage(billing::Billing::load(data_dir.clone())?);
+            billing::start_callback_server(app.handle());
+
+            // Mobile money push payments; confirmations open the gate
+            app.manage(payments::Payments::load(data_dir.clone())?);
+            payments::start_worker(app.handle().clone());
+            payments::start_callback_server(app.handle());
+
+            app.manage(audit::AuditLog::new(data_dir.join("audit.log")));
+            app.manage(receipt_history::ReceiptHistory::load(data_dir.join("receipt_history"))?);
+            app.manage(shifts::Shifts::load(data_dir.join("shifts.json"))?);
+
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             commands::gate::open_gate,  // Registers your gate command
             commands::printer::print_receipt,
+            commands::printer::preview_receipt,
+            commands::printer::reprint_receipt,
             commands::printer::get_available_printers,
+            commands::printer::discover_printers,
+            commands::printer::get_preferred_printer,
+            commands::printer::set_preferred_printer,
+            commands::printer::get_printer_status,
+            commands::printer::store_logo_in_printer,
+            commands::printer::open_cash_drawer,
+            commands::printer::list_printer_models,
+            commands::printer::save_printer_model,
+            commands::ticket::print_entry_ticket,
+            commands::print_queue::list_print_jobs,
+            commands::print_queue::retry_print_job,
+            commands::print_queue::cancel_print_job,
+            commands::fiscal::get_fiscal_config,
+            commands::fiscal::save_fiscal_config,
+            commands::fiscal::list_fiscal_receipts,
+            commands::fiscal::submit_fiscal_backlog,
+            commands::billing::get_billing_config,
+            commands::billing::save_billing_config,
+            commands::billing::request_control_number,
+            commands::billing::list_bills,
+            commands::billing::get_bill,
+            commands::billing::simulate_gepg_payment,
+            commands::payments::get_payments_config,
+            commands::payments::save_payments_config,
+            commands::payments::start_mobile_payment,
+            commands::payments::get_mobile_payment,
+            commands::payments::list_mobile_payments,
+            commands::payments::simulate_mobile_payment,
+            commands::shift::open_shift,
+            commands::shift::get_current_shift,
+            commands::shift::get_shift,
+            commands::shift::list_shifts,
+            commands::shift::print_x_report,
+            commands::shift::close_shift,
+            commands::database::record_vehicle_entry,
+            commands::database::record_vehicle_exit,
+            commands::database::cancel_parking_session,
+            commands::database::get_parking_session,
+            commands::database::find_parked_vehicle,
+            commands::database::list_parking_sessions,
+            commands::database::list_gate_devices,
+            commands::database::save_gate_device,
+            commands::database::delete_gate_device,
+            commands::database::get_config_value,
+            commands::database::set_config_value,
+            commands::database::get_all_config,
+            commands::sync::get_sync_config,
+            commands::sync::save_sync_config,
+            commands::sync::get_sync_status,
+            commands::sync::sync_now,
+            commands::sync::list_sync_issues,
+            commands::sync::retry_sync_event,
+            commands::sync::discard_sync_event,
+            commands::tariff::get_tariffs,
+            commands::tariff::refresh_tariffs,
+            commands::tariff::quote_parking_fee,
+            commands::tariff::calculate_parking_fee,
+            commands::numbering::get_numbering_config,
+            commands::numbering::save_numbering_config,
+            commands::numbering::next_receipt_number,
+            commands::numbering::next_ticket_number,
+            commands::numbering::void_number,
+            commands::numbering::audit_number_sequences,
+            commands::plates::check_plate,
+            commands::plates::refresh_plate_lists,
+            commands::plates::list_plate_entries,
+            commands::plates::get_plate_config,
+            commands::plates::save_plate_config,
+            commands::receipt_history::search_receipts,
+            commands::receipt_history::get_receipt,
             serial::list_serial_ports,
             serial::open_gate_all_ports,
             serial::open_gate_specific_port,
@@ -0,0 +1,491 @@
+use chrono::{Local, TimeZone};
+use rusqlite::{params, Connection, Row};
+use serde::{Deserialize, Serialize};
+
+use crate::database::{install_id, sql, Database};
+use crate::util::now_secs;
+
+const CONFIG_KEY: &str = "numbering";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NumberKind {
+    Receipt,
+    Ticket,
+}
+
+impl NumberKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            NumberKind::Receipt => "receipt",
+            NumberKind::Ticket => "ticket",
+        }
+    }
+
+    fn parse(s: &str) -> Self {
+        match s {
+            "ticket" => NumberKind::Ticket,
+            _ => NumberKind::Receipt,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NumberStatus {
+    /// Handed out, not printed yet.
+    Issued,
+    /// On a printed receipt or ticket.
+    Used,
+    /// Cancelled by an operator, with a reason.
+    Voided,
+}
+
+impl NumberStatus {
+    fn parse(s: &str) -> Self {
+        match s {
+            "used" => NumberStatus::Used,
+            "voided" => NumberStatus::Voided,
+            _ => NumberStatus::Issued,
+        }
+    }
+}
+
+/// How local numbers look, kept under `numbering` in the config table.
+/// Numbers read `{prefix}-{desktop}-{booth}-{gate}-{YYMMDD}-{counter}`, e.g.
+/// `RL-3F9A1C-B1-G2-240501-0007`. The desktop code comes from the install id,
+/// so two desktops set to the same booth, or a reinstalled one starting its
+/// counters again, never make the same number.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NumberingConfig {
+    pub receipt_prefix: String,
+    pub ticket_prefix: String,
+    /// Counters are zero padded to at least this many digits.
+    pub min_digits: usize,
+}
+
+impl Default for NumberingConfig {
+    fn default() -> Self {
+        Self {
+            receipt_prefix: "RL".to_string(),
+            ticket_prefix: "TL".to_string(),
+            min_digits: 4,
+        }
+    }
+}
+
+impl NumberingConfig {
+    fn prefix(&self, kind: NumberKind) -> &str {
+        match kind {
+            NumberKind::Receipt => &self.receipt_prefix,
+            NumberKind::Ticket => &self.ticket_prefix,
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for prefix in [&self.receipt_prefix, &self.ticket_prefix] {
+            if prefix.is_empty() || !prefix.chars().all(|c| c.is_ascii_alphanumeric()) {
+                return Err(format!("Number prefix '{}' must be letters and digits", prefix));
+            }
+        }
+        if self.receipt_prefix.eq_ignore_ascii_case(&self.ticket_prefix) {
+            return Err("Receipts and tickets need different prefixes".to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether a number has the shape of one made on the desktop with this
+    /// code, rather than by the server or another desktop.
+    pub fn is_local(&self, desktop: &str, number: &str) -> bool {
+        let parts: Vec<&str> = number.split('-').collect();
+        parts.len() == 6
+            && (parts[0].eq_ignore_ascii_case(&self.receipt_prefix) || parts[0].eq_ignore_ascii_case(&self.ticket_prefix))
+            && parts[1].eq_ignore_ascii_case(desktop)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssuedNumber {
+    pub number: String,
+    pub kind: NumberKind,
+    pub booth: String,
+    pub gate: String,
+    /// Local date, `YYYY-MM-DD`.
+    pub day: String,
+    pub value: i64,
+    pub status: NumberStatus,
+    pub note: Option<String>,
+    pub issued_at: u64,
+    pub updated_at: u64,
+}
+
+/// One day's sequence for a booth and gate, checked for gaps.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SequenceAudit {
+    pub kind: NumberKind,
+    pub booth: String,
+    pub gate: String,
+    pub day: String,
+    pub last_value: i64,
+    pub issued: usize,
+    /// Counter values with no record at all. Only a tampered database has
+    /// these.
+    pub missing: Vec<i64>,
+    /// Handed out but never printed or voided.
+    pub unused: Vec<IssuedNumber>,
+    pub voided: Vec<IssuedNumber>,
+    /// Missing or unused numbers for someone to explain.
+    pub has_gaps: bool,
+}
+
+fn number_from_row(row: &Row) -> rusqlite::Result<IssuedNumber> {
+    Ok(IssuedNumber {
+        number: row.get("number")?,
+        kind: NumberKind::parse(&row.get::<_, String>("kind")?),
+        booth: row.get("booth")?,
+        gate: row.get("gate")?,
+        day: row.get("day")?,
+        value: row.get("value")?,
+        status: NumberStatus::parse(&row.get::<_, String>("status")?),
+        note: row.get("note")?,
+        issued_at: row.get("issued_at")?,
+        updated_at: row.get("updated_at")?,
+    })
+}
+
+/// Booth and gate codes go into the number, so only letters and digits.
+fn code(value: &str, what: &str) -> Result<String, String> {
+    let code: String = value
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_uppercase();
+    if code.is_empty() {
+        return Err(format!("{} code '{}' needs letters or digits", what, value));
+    }
+    Ok(code)
+}
+
+pub fn config(db: &Database) -> NumberingConfig {
+    db.get_config(CONFIG_KEY)
+        .ok()
+        .flatten()
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_config(db: &Database, config: &NumberingConfig) -> Result<(), String> {
+    config.validate()?;
+    let value = serde_json::to_value(config).map_err(|e| format!("Failed to encode numbering config: {}", e))?;
+    db.set_config(CONFIG_KEY, &value)
+}
+
+/// This desktop's code in its numbers: the start of the install id.
+fn desktop_code(conn: &Connection) -> Result<String, String> {
+    let id = install_id(conn)?;
+    id.get(..6)
+        .map(|code| code.to_uppercase())
+        .ok_or_else(|| format!("Install id '{}' is too short", id))
+}
+
+/// Hands out the next number for a booth and gate. The counter and the
+/// record of the number are written in one transaction, so a number is
+/// never given out twice, even across a crash.
+pub fn issue(db: &Database, kind: NumberKind, booth: &str, gate: Option<&str>) -> Result<IssuedNumber, String> {
+    issue_at(db, kind, booth, gate, now_secs())
+}
+
+fn issue_at(db: &Database, kind: NumberKind, booth: &str, gate: Option<&str>, at: u64) -> Result<IssuedNumber, String> {
+    let config = config(db);
+    let booth = code(booth, "Booth")?;
+    let gate = match gate {
+        Some(g) => code(g, "Gate")?,
+        None => "0".to_string(),
+    };
+    let date = Local
+        .timestamp_opt(at as i64, 0)
+        .single()
+        .ok_or_else(|| format!("Invalid time {}", at))?;
+    let day = date.format("%Y-%m-%d").to_string();
+
+    let mut conn = db.lock();
+    let desktop = desktop_code(&conn)?;
+    let tx = sql(conn.transaction(), "start numbering")?;
+    let value: i64 = sql(
+        tx.query_row(
+            "INSERT INTO number_sequences (kind, booth, gate, day, last_value) VALUES (?1, ?2, ?3, ?4, 1)
+             ON CONFLICT (kind, booth, gate, day) DO UPDATE SET last_value = last_value + 1
+             RETURNING last_value",
+            params![kind.as_str(), booth, gate, day],
+            |row| row.get(0),
+        ),
+        "advance sequence",
+    )?;
+
+    let number = format!(
+        "{}-{}-{}-{}-{}-{:0width$}",
+        config.prefix(kind),
+        desktop,
+        booth,
+        gate,
+        date.format("%y%m%d"),
+        value,
+        width = config.min_digits
+    );
+    sql(
+        tx.execute(
+            "INSERT INTO issued_numbers (number, kind, booth, gate, day, value, issued_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?7)",
+            params![number, kind.as_str(), booth, gate, day, value, at],
+        ),
+        "record number",
+    )?;
+    let issued = sql(
+        tx.query_row("SELECT * FROM issued_numbers WHERE number = ?1", params![number], number_from_row),
+        "read number",
+    )?;
+    sql(tx.commit(), "save number")?;
+
+    println!("[Rust] Issued {} {}", kind.as_str(), issued.number);
+    Ok(issued)
+}
+
+/// Marks a number issued here as printed, whatever the prefixes are now.
+/// Server numbers are left alone, but one that looks like ours and was never
+/// issued here is an error for the caller to audit.
+pub fn mark_used(db: &Database, number: &str) -> Result<(), String> {
+    let config = config(db);
+    let conn = db.lock();
+    let changed = sql(
+        conn.execute(
+            "UPDATE issued_numbers SET status = 'used', updated_at = ?2 WHERE number = ?1 AND status = 'issued'",
+            params![number, now_secs()],
+        ),
+        "mark number used",
+    )?;
+    if changed > 0 {
+        return Ok(());
+    }
+
+    let issued_here: bool = sql(
+        conn.query_row("SELECT EXISTS(SELECT 1 FROM issued_numbers WHERE number = ?1)", params![number], |row| row.get(0)),
+        "read number",
+    )?;
+    if !issued_here && config.is_local(&desktop_code(&conn)?, number) {
+        return Err(format!(
+            "{} uses this desktop's {} or {} prefix but was never issued here",
+            number, config.receipt_prefix, config.ticket_prefix
+        ));
+    }
+    Ok(())
+}
+
+/// Cancels a number that will never be printed, e.g. one handed out for a
+/// receipt the customer walked away from. A printed number can't be voided,
+/// so a sale never drops out of the gap audit.
+pub fn void(db: &Database, number: &str, reason: &str) -> Result<IssuedNumber, String> {
+    if reason.trim().is_empty() {
+        return Err("A reason is needed to void a number".to_string());
+    }
+    let conn = db.lock();
+    let changed = sql(
+        conn.execute(
+            "UPDATE issued_numbers SET status = 'voided', note = ?2, updated_at = ?3
+             WHERE number = ?1 AND status = 'issued'",
+            params![number, reason.trim(), now_secs()],
+        ),
+        "void number",
+    )?;
+    if changed == 0 {
+        return Err(format!("{} is not an unprinted local number", number));
+    }
+    sql(
+        conn.query_row("SELECT * FROM issued_numbers WHERE number = ?1", params![number], number_from_row),
+        "read number",
+    )
+}
+
+/// Checks every sequence used on a local date (`YYYY-MM-DD`).
+pub fn audit(db: &Database, day: &str) -> Result<Vec<SequenceAudit>, String> {
+    let conn = db.lock();
+    let mut stmt = sql(
+        conn.prepare(
+            "SELECT kind, booth, gate, last_value FROM number_sequences WHERE day = ?1 ORDER BY kind, booth, gate",
+        ),
+        "read sequences",
+    )?;
+    let rows = sql(
+        stmt.query_map(params![day], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?,
+            ))
+        }),
+        "read sequences",
+    )?;
+    let sequences: Vec<(String, String, String, i64)> = sql(rows.collect(), "read sequences")?;
+
+    let mut numbers = sql(
+        conn.prepare(
+            "SELECT * FROM issued_numbers WHERE kind = ?1 AND booth = ?2 AND gate = ?3 AND day = ?4 ORDER BY value",
+        ),
+        "read numbers",
+    )?;
+    let mut audits = Vec::new();
+    for (kind, booth, gate, last_value) in sequences {
+        let rows = sql(numbers.query_map(params![kind, booth, gate, day], number_from_row), "read numbers")?;
+        let issued: Vec<IssuedNumber> = sql(rows.collect(), "read numbers")?;
+
+        let mut missing = Vec::new();
+        let mut values = issued.iter().map(|n| n.value).peekable();
+        for value in 1..=last_value {
+            if values.next_if_eq(&value).is_none() {
+                missing.push(value);
+            }
+        }
+
+        let with = |status| issued.iter().filter(|n| n.status == status).cloned().collect::<Vec<_>>();
+        let unused = with(NumberStatus::Issued);
+        audits.push(SequenceAudit {
+            kind: NumberKind::parse(&kind),
+            booth,
+            gate,
+            day: day.to_string(),
+            last_value,
+            issued: issued.len(),
+            has_gaps: !missing.is_empty() || !unused.is_empty(),
+            missing,
+            unused,
+            voided: with(NumberStatus::Voided),
+        });
+    }
+    Ok(audits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Midday, so the local date is the same in any timezone's tests.
+    const NOON: u64 = 1_714_564_800;
+    const DAY: u64 = 24 * 60 * 60;
+
+    #[test]
+    fn numbers_are_scoped_per_booth_gate_and_day() {
+        let db = Database::open_in_memory().unwrap();
+        let first = issue_at(&db, NumberKind::Receipt, "b1", Some("Gate 2"), NOON).unwrap();
+        let second = issue_at(&db, NumberKind::Receipt, "B1", Some("gate2"), NOON + 60).unwrap();
+        let other_gate = issue_at(&db, NumberKind::Receipt, "B1", None, NOON).unwrap();
+        let ticket = issue_at(&db, NumberKind::Ticket, "B1", Some("GATE2"), NOON).unwrap();
+        let next_day = issue_at(&db, NumberKind::Receipt, "B1", Some("GATE2"), NOON + DAY).unwrap();
+
+        let date = first.day.replace('-', "");
+        let desktop = desktop_code(&db.lock()).unwrap();
+        assert_eq!(first.number, format!("RL-{}-B1-GATE2-{}-0001", desktop, &date[2..]));
+        assert_eq!(second.value, 2);
+        assert_eq!((other_gate.gate.as_str(), other_gate.value), ("0", 1));
+        assert!(ticket.number.starts_with(&format!("TL-{}-B1-GATE2-", desktop)));
+        assert_eq!(next_day.value, 1);
+        assert_ne!(next_day.day, first.day);
+
+        let config = config(&db);
+        assert!(config.is_local(&desktop, &second.number));
+        assert!(!config.is_local(&desktop, "RCP-0042"));
+        assert!(issue_at(&db, NumberKind::Receipt, " - ", None, NOON).is_err());
+    }
+
+    #[test]
+    fn audit_finds_unused_voided_and_missing_numbers() {
+        let db = Database::open_in_memory().unwrap();
+        let numbers: Vec<IssuedNumber> = (0..4)
+            .map(|i| issue_at(&db, NumberKind::Receipt, "B1", Some("G1"), NOON + i).unwrap())
+            .collect();
+        mark_used(&db, &numbers[0].number).unwrap();
+        assert!(void(&db, &numbers[0].number, "Customer complained").is_err());
+        void(&db, &numbers[1].number, "Printer jam").unwrap();
+        assert!(void(&db, &numbers[1].number, "again").is_err());
+        assert!(void(&db, &numbers[2].number, " ").is_err());
+        // Someone deletes the fourth number's record
+        db.lock()
+            .execute("DELETE FROM issued_numbers WHERE number = ?1", params![numbers[3].number])
+            .unwrap();
+
+        let audit = audit(&db, &numbers[0].day).unwrap();
+        assert_eq!(audit.len(), 1);
+        let a = &audit[0];
+        assert_eq!((a.last_value, a.issued, a.missing.clone()), (4, 3, vec![4]));
+        assert_eq!(a.unused.len(), 1);
+        assert_eq!(a.unused[0].number, numbers[2].number);
+        assert_eq!(a.voided[0].note.as_deref(), Some("Printer jam"));
+        assert!(a.has_gaps);
+
+        // A deleted record never frees its number
+        let next = issue_at(&db, NumberKind::Receipt, "B1", Some("G1"), NOON).unwrap();
+        assert_eq!(next.value, 5);
+    }
+
+    #[test]
+    fn numbers_stay_local_after_a_prefix_change() {
+        let db = Database::open_in_memory().unwrap();
+        let old = issue_at(&db, NumberKind::Receipt, "B1", Some("G1"), NOON).unwrap();
+        save_config(
+            &db,
+            &NumberingConfig {
+                receipt_prefix: "RX".to_string(),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        mark_used(&db, &old.number).unwrap();
+        assert!(audit(&db, &old.day).unwrap()[0].unused.is_empty());
+        mark_used(&db, &old.number).unwrap();
+
+        // Server numbers pass, unless they pose as ours
+        mark_used(&db, "RCP-0042").unwrap();
+        let desktop = desktop_code(&db.lock()).unwrap();
+        assert!(mark_used(&db, &format!("RX-{}-B1-G1-240501-0099", desktop)).is_err());
+    }
+
+    #[test]
+    fn desktops_on_the_same_booth_never_clash() {
+        let first = Database::open_in_memory().unwrap();
+        let second = Database::open_in_memory().unwrap();
+        let a = issue_at(&first, NumberKind::Receipt, "B1", Some("G1"), NOON).unwrap();
+        let b = issue_at(&second, NumberKind::Receipt, "B1", Some("G1"), NOON).unwrap();
+        assert_eq!((a.value, b.value), (1, 1));
+        assert_ne!(a.number, b.number);
+
+        // The other desktop's receipts come back through the server as theirs
+        let config = config(&first);
+        assert!(!config.is_local(&desktop_code(&first.lock()).unwrap(), &b.number));
+        mark_used(&first, &b.number).unwrap();
+    }
+
+    #[test]
+    fn prefixes_must_stay_distinct() {
+        let db = Database::open_in_memory().unwrap();
+        let clash = NumberingConfig {
+            ticket_prefix: "rl".to_string(),
+            ..Default::default()
+        };
+        assert!(save_config(&db, &clash).is_err());
+        let dashed = NumberingConfig {
+            receipt_prefix: "R-L".to_string(),
+            ..Default::default()
+        };
+        assert!(save_config(&db, &dashed).is_err());
+
+        let custom = NumberingConfig {
+            receipt_prefix: "OFR".to_string(),
+            min_digits: 6,
+            ..Default::default()
+        };
+        save_config(&db, &custom).unwrap();
+        let n = issue_at(&db, NumberKind::Receipt, "B1", None, NOON).unwrap();
+        assert!(n.number.starts_with("OFR-") && n.number.contains("-B1-0-") && n.number.ends_with("-000001"));
+    }
+}
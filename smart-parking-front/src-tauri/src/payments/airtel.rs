@@ -0,0 +1,167 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::{CallbackUpdate, MobileMoneyProvider, MobilePayment, ProviderStatus};
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Airtel Money Africa open API application credentials.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AirtelConfig {
+    pub endpoint: String,
+    pub client_id: String,
+    pub client_secret: String,
+    /// Sent back as `?secret=` on the callback URL registered with Airtel.
+    pub callback_secret: String,
+}
+
+impl Default for AirtelConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: "https://openapi.airtel.africa".to_string(),
+            client_id: String::new(),
+            client_secret: String::new(),
+            callback_secret: String::new(),
+        }
+    }
+}
+
+/// Airtel Money collection API: push request, status query and callback.
+pub struct Airtel {
+    config: AirtelConfig,
+    agent: ureq::Agent,
+}
+
+/// Airtel transaction status codes: TS success, TF failed, TA/TIP in progress.
+fn status(code: &str, transaction_id: Option<String>, message: Option<String>) -> ProviderStatus {
+    match code {
+        "TS" => ProviderStatus::Confirmed {
+            transaction_id: transaction_id.unwrap_or_default(),
+        },
+        "TF" | "TE" => ProviderStatus::Failed {
+            reason: message.unwrap_or_else(|| "Payment failed".to_string()),
+        },
+        _ => ProviderStatus::Pending,
+    }
+}
+
+impl Airtel {
+    pub fn new(config: AirtelConfig) -> Self {
+        Self {
+            config,
+            agent: ureq::AgentBuilder::new().timeout(HTTP_TIMEOUT).build(),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.config.endpoint.trim_end_matches('/'), path)
+    }
+
+    fn token(&self) -> Result<String, String> {
+        let body: serde_json::Value = self
+            .agent
+            .post(&self.url("/auth/oauth2/token"))
+            .send_json(serde_json::json!({
+                "client_id": self.config.client_id,
+                "client_secret": self.config.client_secret,
+                "grant_type": "client_credentials",
+            }))
+            .map_err(|e| format!("Airtel Money token request failed: {}", e))?
+            .into_json()
+            .map_err(|e| format!("Invalid Airtel Money token response: {}", e))?;
+
+        body.get("access_token")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string())
+            .ok_or_else(|| "Airtel Money token response has no access_token".to_string())
+    }
+
+    fn request(&self, method: &str, path: &str) -> Result<ureq::Request, String> {
+        Ok(self
+            .agent
+            .request(method, &self.url(path))
+            .set("Authorization", &format!("Bearer {}", self.token()?))
+            .set("X-Country", "TZ")
+            .set("X-Currency", "TZS"))
+    }
+}
+
+impl MobileMoneyProvider for Airtel {
+    fn request_payment(&self, payment: &MobilePayment) -> Result<ProviderStatus, String> {
+        let body: serde_json::Value = self
+            .request("POST", "/merchant/v1/payments/")?
+            .send_json(serde_json::json!({
+                "reference": format!("Maegesho {}", payment.plate_number.as_deref().unwrap_or(&payment.session_id)),
+                "subscriber": {
+                    "country": "TZ",
+                    "currency": "TZS",
+                    // Airtel wants the number without the country code
+                    "msisdn": payment.phone.trim_start_matches("255"),
+                },
+                "transaction": {
+                    "amount": payment.amount,
+                    "country": "TZ",
+                    "currency": "TZS",
+                    "id": payment.id,
+                },
+            }))
+            .map_err(|e| format!("Airtel Money push request failed: {}", e))?
+            .into_json()
+            .map_err(|e| format!("Invalid Airtel Money response: {}", e))?;
+
+        let accepted = body.pointer("/status/success").and_then(|v| v.as_bool()) == Some(true);
+        if accepted {
+            Ok(ProviderStatus::Pending)
+        } else {
+            Ok(ProviderStatus::Failed {
+                reason: body
+                    .pointer("/status/message")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("Airtel Money refused the request")
+                    .to_string(),
+            })
+        }
+    }
+
+    fn query_status(&self, payment: &MobilePayment) -> Result<ProviderStatus, String> {
+        let body: serde_json::Value = self
+            .request("GET", &format!("/standard/v1/payments/{}", payment.id))?
+            .call()
+            .map_err(|e| format!("Airtel Money status query failed: {}", e))?
+            .into_json()
+            .map_err(|e| format!("Invalid Airtel Money response: {}", e))?;
+
+        let field = |key: &str| {
+            body.pointer(&format!("/data/transaction/{}", key))
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string())
+        };
+        Ok(status(
+            &field("status").unwrap_or_default(),
+            field("airtel_money_id"),
+            field("message"),
+        ))
+    }
+
+    fn callback_secret(&self) -> &str {
+        &self.config.callback_secret
+    }
+
+    fn parse_callback(&self, body: &str) -> Option<CallbackUpdate> {
+        let v: serde_json::Value = serde_json::from_str(body).ok()?;
+        let field = |key: &str| {
+            v.pointer(&format!("/transaction/{}", key))
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string())
+        };
+
+        Some(CallbackUpdate {
+            reference: field("id"),
+            phone: None,
+            amount: None,
+            status: status(&field("status_code")?, field("airtel_money_id"), field("message")),
+        })
+    }
+}
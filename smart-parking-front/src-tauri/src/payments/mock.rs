@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use super::{CallbackUpdate, MobileMoneyProvider, MobilePayment, ProviderStatus};
+
+/// In-memory wallet for training and tests. Prompts stay pending until
+/// `decide` or `settle` says what the customer did.
+#[derive(Default)]
+pub struct MockProvider {
+    /// Payment id -> the customer's answer, once given.
+    requests: Mutex<HashMap<String, Option<bool>>>,
+}
+
+fn transaction_id(id: &str) -> String {
+    format!("MOCK-{}", id)
+}
+
+impl MockProvider {
+    pub fn decide(&self, id: &str, approve: bool) -> Result<(), String> {
+        let mut requests = self.requests.lock().unwrap();
+        let answer = requests
+            .get_mut(id)
+            .ok_or_else(|| format!("Mock provider has no request {}", id))?;
+        *answer = Some(approve);
+        Ok(())
+    }
+
+    /// Decides the request and returns the callback body the wallet posts.
+    pub fn settle(&self, id: &str, approve: bool) -> Result<String, String> {
+        self.decide(id, approve)?;
+        let body = serde_json::json!({
+            "reference": id,
+            "status": if approve { "confirmed" } else { "failed" },
+            "transaction_id": transaction_id(id),
+        });
+        Ok(body.to_string())
+    }
+}
+
+impl MobileMoneyProvider for MockProvider {
+    fn request_payment(&self, payment: &MobilePayment) -> Result<ProviderStatus, String> {
+        self.requests.lock().unwrap().insert(payment.id.clone(), None);
+        Ok(ProviderStatus::Pending)
+    }
+
+    fn query_status(&self, payment: &MobilePayment) -> Result<ProviderStatus, String> {
+        Ok(match self.requests.lock().unwrap().get(&payment.id) {
+            Some(Some(true)) => ProviderStatus::Confirmed {
+                transaction_id: transaction_id(&payment.id),
+            },
+            Some(Some(false)) => ProviderStatus::Failed {
+                reason: "Customer declined".to_string(),
+            },
+            _ => ProviderStatus::Pending,
+        })
+    }
+
+    fn parse_callback(&self, body: &str) -> Option<CallbackUpdate> {
+        let v: serde_json::Value = serde_json::from_str(body).ok()?;
+        let str_field = |key: &str| v.get(key).and_then(|v| v.as_str()).map(|s| s.to_string());
+
+        let status = match v.get("status")?.as_str()? {
+            "confirmed" => ProviderStatus::Confirmed {
+                transaction_id: str_field("transaction_id").unwrap_or_default(),
+            },
+            "failed" => ProviderStatus::Failed {
+                reason: "Customer declined".to_string(),
+            },
+            _ => ProviderStatus::Pending,
+        };
+        Some(CallbackUpdate {
+            reference: str_field("reference"),
+            phone: str_field("phone"),
+            amount: v.get("amount").and_then(|a| a.as_f64()),
+            status,
+        })
+    }
+}
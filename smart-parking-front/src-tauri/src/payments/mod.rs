@@ -0,0 +1,808 @@
+pub mod airtel;
+pub mod mock;
+pub mod mpesa;
+pub mod tigopesa;
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::audit::AuditLog;
+use crate::callback_server;
+use crate::database::{new_local_id, Database, SessionStatus};
+use crate::serial;
+use crate::sync_queue;
+use crate::util::{now_secs, read_json, write_json};
+use mock::MockProvider;
+
+/// Event emitted with a `MobilePayment` whenever its status changes.
+pub const PAYMENT_UPDATED_EVENT: &str = "mobile-payment-updated";
+
+const WORKER_INTERVAL: Duration = Duration::from_secs(5);
+/// Settled payments kept after they stop changing.
+const KEEP_FINISHED: usize = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderKind {
+    TigoPesa,
+    Mpesa,
+    AirtelMoney,
+}
+
+impl ProviderKind {
+    /// The wallet a Tanzanian number belongs to, from its network prefix.
+    pub fn for_phone(msisdn: &str) -> Option<Self> {
+        match msisdn.get(3..5)? {
+            "65" | "67" | "71" | "77" => Some(ProviderKind::TigoPesa),
+            "74" | "75" | "76" => Some(ProviderKind::Mpesa),
+            "68" | "69" | "78" => Some(ProviderKind::AirtelMoney),
+            _ => None,
+        }
+    }
+
+    /// Callback server path the provider posts to.
+    fn path(self) -> &'static str {
+        match self {
+            ProviderKind::TigoPesa => "/tigopesa",
+            ProviderKind::Mpesa => "/mpesa",
+            ProviderKind::AirtelMoney => "/airtel",
+        }
+    }
+}
+
+/// `0712 345 678`, `+255712345678` and `712345678` all become `255712345678`.
+pub fn normalize_phone(phone: &str) -> Result<String, String> {
+    let digits: String = phone.chars().filter(|c| c.is_ascii_digit()).collect();
+    match digits.len() {
+        12 if digits.starts_with("255") => Ok(digits),
+        10 if digits.starts_with('0') => Ok(format!("255{}", &digits[1..])),
+        9 => Ok(format!("255{}", digits)),
+        _ => Err(format!("'{}' is not a Tanzanian mobile number", phone)),
+    }
+}
+
+/// Whether the callback's `secret` query parameter is `expected`, compared
+/// without bailing out at the first wrong byte.
+fn secret_matches(expected: &str, query: &str) -> bool {
+    let given = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("secret="))
+        .unwrap_or_default();
+    !expected.is_empty()
+        && given.len() == expected.len()
+        && given.bytes().zip(expected.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentStatus {
+    Pending,
+    Confirmed,
+    Failed,
+    /// No answer within `timeout_secs`. A late confirmation is still recorded,
+    /// but no longer opens the gate.
+    TimedOut,
+}
+
+/// What a provider says about a payment.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProviderStatus {
+    Pending,
+    Confirmed { transaction_id: String },
+    Failed { reason: String },
+}
+
+/// A status update a provider posted to the callback server. Payments made
+/// from the Lipa number rather than a push prompt carry no reference of ours,
+/// and are matched on phone and amount.
+#[derive(Debug, Clone)]
+pub struct CallbackUpdate {
+    pub reference: Option<String>,
+    pub phone: Option<String>,
+    pub amount: Option<f64>,
+    pub status: ProviderStatus,
+}
+
+/// One mobile money wallet API.
+pub trait MobileMoneyProvider: Send + Sync {
+    /// Sends the push-USSD prompt to the customer's phone. Providers that
+    /// wait for the PIN answer with the outcome, the rest with `Pending`.
+    fn request_payment(&self, payment: &MobilePayment) -> Result<ProviderStatus, String>;
+
+    /// Asks for the status of an earlier request.
+    fn query_status(&self, payment: &MobilePayment) -> Result<ProviderStatus, String>;
+
+    /// Reads a callback body. `None` when it isn't a payment update.
+    fn parse_callback(&self, body: &str) -> Option<CallbackUpdate>;
+
+    /// Shared secret the provider's callback URL carries as `?secret=`.
+    /// Callbacks are refused while it is empty.
+    fn callback_secret(&self) -> &str {
+        ""
+    }
+
+    /// What to answer the callback with.
+    fn callback_ack(&self, _update: Option<&CallbackUpdate>) -> String {
+        "{\"status\":\"ok\"}".to_string()
+    }
+}
+
+/// Wallet API settings, kept in `payments_config.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PaymentsConfig {
+    pub enabled: bool,
+    /// Route every wallet to the built-in mock provider.
+    pub mock: bool,
+    /// Where providers post status updates, under `/tigopesa`, `/mpesa` and
+    /// `/airtel`, each registered with `?secret=` and that provider's
+    /// `callback_secret`. Only this PC can reach the default; set a LAN
+    /// address when callbacks are forwarded in. Changes take effect when the
+    /// app restarts.
+    pub callback_addr: String,
+    pub poll_interval_secs: u64,
+    pub timeout_secs: u64,
+    pub auto_open_gate: bool,
+    /// Gate controller port. The gate only opens on payment when it is set,
+    /// so a confirmation never lifts a barrier at another lane.
+    pub gate_port: Option<String>,
+    /// What the gate controller firmware listens for.
+    pub gate_command: String,
+    pub tigopesa: tigopesa::TigoPesaConfig,
+    pub mpesa: mpesa::MpesaConfig,
+    pub airtel: airtel::AirtelConfig,
+}
+
+impl Default for PaymentsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            mock: false,
+            callback_addr: "127.0.0.1:8789".to_string(),
+            poll_interval_secs: 10,
+            timeout_secs: 180,
+            auto_open_gate: true,
+            gate_port: None,
+            gate_command: "hell".to_string(),
+            tigopesa: Default::default(),
+            mpesa: Default::default(),
+            airtel: Default::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MobilePayment {
+    /// Our reference, sent to the provider.
+    pub id: String,
+    pub provider: ProviderKind,
+    pub session_id: String,
+    pub plate_number: Option<String>,
+    pub receipt_number: Option<String>,
+    pub phone: String,
+    pub amount: f64,
+    pub status: PaymentStatus,
+    pub transaction_id: Option<String>,
+    pub message: Option<String>,
+    pub gate_opened: bool,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub last_polled_at: u64,
+}
+
+/// What the booth asks the customer to pay.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentRequest {
+    pub session_id: String,
+    pub phone: String,
+    pub amount: f64,
+    /// Worked out from the phone number when not given.
+    pub provider: Option<ProviderKind>,
+    pub plate_number: Option<String>,
+    pub receipt_number: Option<String>,
+}
+
+/// A payment whose status changed. `confirmed` is set on any change to
+/// Confirmed, `confirmed_now` only on the one that should open the gate.
+#[derive(Debug, Clone)]
+pub struct PaymentUpdate {
+    pub payment: MobilePayment,
+    pub confirmed: bool,
+    pub confirmed_now: bool,
+}
+
+pub struct Payments {
+    dir: PathBuf,
+    config: Mutex<PaymentsConfig>,
+    payments: Mutex<Vec<MobilePayment>>,
+    mock: Arc<MockProvider>,
+}
+
+impl Payments {
+    pub fn load(dir: PathBuf) -> Result<Self, String> {
+        let config: PaymentsConfig = read_json(&dir.join("payments_config.json"))?;
+        let payments: Vec<MobilePayment> = read_json(&dir.join("mobile_payments.json"))?;
+
+        let pending = payments.iter().filter(|p| p.status == PaymentStatus::Pending).count();
+        println!("[Rust] Mobile payments loaded: {} pending", pending);
+
+        Ok(Self {
+            dir,
+            config: Mutex::new(config),
+            payments: Mutex::new(payments),
+            mock: Arc::new(MockProvider::default()),
+        })
+    }
+
+    pub fn config(&self) -> PaymentsConfig {
+        self.config.lock().unwrap().clone()
+    }
+
+    pub fn save_config(&self, config: PaymentsConfig) -> Result<(), String> {
+        write_json(&self.dir.join("payments_config.json"), &config)?;
+        *self.config.lock().unwrap() = config;
+        Ok(())
+    }
+
+    fn provider(&self, config: &PaymentsConfig, kind: ProviderKind) -> Arc<dyn MobileMoneyProvider> {
+        if config.mock {
+            return self.mock.clone();
+        }
+        match kind {
+            ProviderKind::TigoPesa => Arc::new(tigopesa::TigoPesa::new(config.tigopesa.clone())),
+            ProviderKind::Mpesa => Arc::new(mpesa::Mpesa::new(config.mpesa.clone())),
+            ProviderKind::AirtelMoney => Arc::new(airtel::Airtel::new(config.airtel.clone())),
+        }
+    }
+
+    fn save_payments(&self, payments: &mut Vec<MobilePayment>) -> Result<(), String> {
+        let finished = |p: &MobilePayment| p.status != PaymentStatus::Pending;
+        let excess = payments.iter().filter(|p| finished(p)).count().saturating_sub(KEEP_FINISHED);
+        let mut dropped = 0;
+        payments.retain(|p| {
+            if dropped < excess && finished(p) {
+                dropped += 1;
+                false
+            } else {
+                true
+            }
+        });
+
+        write_json(&self.dir.join("mobile_payments.json"), &*payments)
+    }
+
+    /// Records a payment for a parking session, and says whether it is new. A
+    /// session that is already paid, or waiting on the same phone, gets that
+    /// payment back instead of a second prompt.
+    pub fn create(&self, request: PaymentRequest) -> Result<(MobilePayment, bool), String> {
+        let config = self.config();
+        if !config.enabled {
+            return Err("Mobile money payments are not enabled".to_string());
+        }
+        if request.amount <= 0.0 {
+            return Err(format!("Session {} has nothing to pay", request.session_id));
+        }
+
+        let phone = normalize_phone(&request.phone)?;
+        let provider = request
+            .provider
+            .or_else(|| ProviderKind::for_phone(&phone))
+            .ok_or_else(|| format!("No mobile money provider for {}", phone))?;
+
+        let mut payments = self.payments.lock().unwrap();
+        if let Some(existing) = payments.iter().rev().find(|p| {
+            p.session_id == request.session_id
+                && (p.status == PaymentStatus::Confirmed || (p.status == PaymentStatus::Pending && p.phone == phone))
+        }) {
+            return Ok((existing.clone(), false));
+        }
+
+        let now = now_secs();
+        let payment = MobilePayment {
+            id: format!("PK{}", new_local_id()),
+            provider,
+            session_id: request.session_id,
+            plate_number: request.plate_number,
+            receipt_number: request.receipt_number,
+            phone,
+            amount: request.amount,
+            status: PaymentStatus::Pending,
+            transaction_id: None,
+            message: None,
+            gate_opened: false,
+            created_at: now,
+            updated_at: now,
+            last_polled_at: now,
+        };
+        payments.push(payment.clone());
+        self.save_payments(&mut payments)?;
+
+        println!(
+            "[Rust] Mobile payment {} of TZS {} started for session {} ({:?})",
+            payment.id, payment.amount, payment.session_id, payment.provider
+        );
+        Ok((payment, true))
+    }
+
+    /// Sends the push prompt for a payment created with `create`.
+    pub fn push(&self, id: &str) -> Option<PaymentUpdate> {
+        let payment = self.payment(id)?;
+        let config = self.config();
+        let status = self
+            .provider(&config, payment.provider)
+            .request_payment(&payment)
+            .unwrap_or_else(|e| ProviderStatus::Failed { reason: e });
+        self.apply(id, status, None)
+    }
+
+    fn apply(&self, id: &str, status: ProviderStatus, message: Option<String>) -> Option<PaymentUpdate> {
+        let mut payments = self.payments.lock().unwrap();
+        let p = payments.iter_mut().find(|p| p.id == id)?;
+        let before = p.status;
+
+        match status {
+            ProviderStatus::Pending => return None,
+            ProviderStatus::Confirmed { transaction_id } => {
+                if before == PaymentStatus::Confirmed {
+                    return None;
+                }
+                println!("[Rust] Mobile payment {} confirmed ({})", p.id, transaction_id);
+                p.status = PaymentStatus::Confirmed;
+                p.transaction_id = Some(transaction_id);
+                p.message = message.or_else(|| {
+                    (before == PaymentStatus::TimedOut).then(|| "Confirmed after the booth stopped waiting".to_string())
+                });
+            }
+            ProviderStatus::Failed { reason } => {
+                if before != PaymentStatus::Pending {
+                    return None;
+                }
+                println!("[Rust] Mobile payment {} failed: {}", p.id, reason);
+                p.status = PaymentStatus::Failed;
+                p.message = Some(reason);
+            }
+        }
+        p.updated_at = now_secs();
+
+        let update = PaymentUpdate {
+            payment: p.clone(),
+            confirmed: before != PaymentStatus::Confirmed && p.status == PaymentStatus::Confirmed,
+            confirmed_now: before == PaymentStatus::Pending && p.status == PaymentStatus::Confirmed,
+        };
+        if let Err(e) = self.save_payments(&mut payments) {
+            println!("[Rust] {}", e);
+        }
+        Some(update)
+    }
+
+    /// Handles a post to the callback server at `path`. Returns the answer for
+    /// the provider and the payment that changed. A callback without the
+    /// provider's secret is refused, and one matched only on phone and amount
+    /// counts once the provider confirms it on a status query.
+    pub fn handle_callback(&self, path: &str, body: &str) -> (String, Option<PaymentUpdate>) {
+        let config = self.config();
+        let (route, query) = path.split_once('?').unwrap_or((path, ""));
+        let provider: Arc<dyn MobileMoneyProvider> = match [ProviderKind::TigoPesa, ProviderKind::Mpesa, ProviderKind::AirtelMoney]
+            .into_iter()
+            .find(|k| route.trim_end_matches('/') == k.path())
+        {
+            Some(kind) => self.provider(&config, kind),
+            None if config.mock => self.mock.clone(),
+            None => {
+                println!("[Rust] Ignoring mobile money callback on {}", route);
+                return ("{\"status\":\"unknown\"}".to_string(), None);
+            }
+        };
+
+        if !config.mock && !secret_matches(provider.callback_secret(), query) {
+            println!("[Rust] Refusing mobile money callback on {} without the provider's secret", route);
+            return ("{\"status\":\"unauthorized\"}".to_string(), None);
+        }
+
+        let Some(update) = provider.parse_callback(body) else {
+            println!("[Rust] Unreadable mobile money callback on {}", route);
+            return (provider.callback_ack(None), None);
+        };
+
+        let (by_reference, by_phone) = {
+            let payments = self.payments.lock().unwrap();
+            let by_reference = update
+                .reference
+                .as_ref()
+                .and_then(|r| payments.iter().find(|p| &p.id == r))
+                .cloned();
+            let by_phone = || {
+                let phone = update.phone.as_deref().and_then(|p| normalize_phone(p).ok())?;
+                let amount = update.amount?;
+                payments
+                    .iter()
+                    .rev()
+                    .find(|p| p.status == PaymentStatus::Pending && p.phone == phone && (p.amount - amount).abs() < 0.005)
+                    .cloned()
+            };
+            let by_phone = if by_reference.is_none() { by_phone() } else { None };
+            (by_reference, by_phone)
+        };
+
+        let changed = match (by_reference, by_phone) {
+            // Part of the fee doesn't pay for the stay
+            (Some(p), _) if update.amount.is_some_and(|a| (a - p.amount).abs() >= 0.005)
+                && matches!(update.status, ProviderStatus::Confirmed { .. }) =>
+            {
+                println!(
+                    "[Rust] Refusing confirmation of {}: TZS {} paid, TZS {} due",
+                    p.id,
+                    update.amount.unwrap_or_default(),
+                    p.amount
+                );
+                None
+            }
+            (Some(p), _) => self.apply(&p.id, update.status.clone(), None),
+            // Anyone can pay the Lipa number, so the wallet has to vouch for it
+            (None, Some(p)) => match provider.query_status(&p) {
+                Ok(status @ ProviderStatus::Confirmed { .. }) => self.apply(&p.id, status, None),
+                Ok(_) => {
+                    println!("[Rust] Callback for {} not confirmed by the provider yet", p.id);
+                    None
+                }
+                Err(e) => {
+                    println!("[Rust] Callback for {} could not be confirmed: {}", p.id, e);
+                    None
+                }
+            },
+            (None, None) => {
+                println!("[Rust] Mobile money callback matches no payment: {:?}", update.reference);
+                None
+            }
+        };
+        (provider.callback_ack(Some(&update)), changed)
+    }
+
+    /// Polls pending payments that are due and times out the ones nobody
+    /// answered.
+    pub fn poll_due(&self) -> Vec<PaymentUpdate> {
+        let config = self.config();
+        let now = now_secs();
+        let due: Vec<MobilePayment> = {
+            let mut payments = self.payments.lock().unwrap();
+            let due: Vec<MobilePayment> = payments
+                .iter_mut()
+                .filter(|p| p.status == PaymentStatus::Pending && p.last_polled_at + config.poll_interval_secs <= now)
+                .map(|p| {
+                    p.last_polled_at = now;
+                    p.clone()
+                })
+                .collect();
+            if due.is_empty() {
+                return vec![];
+            }
+            due
+        };
+
+        let mut updates = Vec::new();
+        for payment in due {
+            match self.provider(&config, payment.provider).query_status(&payment) {
+                Ok(status) => updates.extend(self.apply(&payment.id, status, None)),
+                Err(e) => println!("[Rust] Status of mobile payment {} unknown: {}", payment.id, e),
+            }
+        }
+
+        let mut payments = self.payments.lock().unwrap();
+        for p in payments
+            .iter_mut()
+            .filter(|p| p.status == PaymentStatus::Pending && p.created_at + config.timeout_secs <= now)
+        {
+            println!("[Rust] Mobile payment {} timed out", p.id);
+            p.status = PaymentStatus::TimedOut;
+            p.message = Some("Customer did not confirm in time".to_string());
+            p.updated_at = now;
+            updates.push(PaymentUpdate {
+                payment: p.clone(),
+                confirmed: false,
+                confirmed_now: false,
+            });
+        }
+        if let Err(e) = self.save_payments(&mut payments) {
+            println!("[Rust] {}", e);
+        }
+        updates
+    }
+
+    pub fn payment(&self, id: &str) -> Option<MobilePayment> {
+        self.payments.lock().unwrap().iter().find(|p| p.id == id).cloned()
+    }
+
+    pub fn payments(&self, session_id: Option<&str>) -> Vec<MobilePayment> {
+        self.payments
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|p| session_id.map_or(true, |s| p.session_id == s))
+            .cloned()
+            .collect()
+    }
+
+    fn mark_gate_opened(&self, id: &str) -> Option<MobilePayment> {
+        let mut payments = self.payments.lock().unwrap();
+        let p = payments.iter_mut().find(|p| p.id == id)?;
+        p.gate_opened = true;
+        p.updated_at = now_secs();
+        let updated = p.clone();
+        if let Err(e) = self.save_payments(&mut payments) {
+            println!("[Rust] {}", e);
+        }
+        Some(updated)
+    }
+
+    /// Has the mock provider's customer accept or decline, and delivers the
+    /// callback it would send.
+    pub fn simulate(&self, id: &str, approve: bool) -> Result<Option<PaymentUpdate>, String> {
+        if !self.config().mock {
+            return Err("Payments can only be simulated against the mock provider".to_string());
+        }
+        let body = self.mock.settle(id, approve)?;
+        Ok(self.handle_callback("/mock", &body).1)
+    }
+}
+
+/// Emits the update and opens the gate when a payment is confirmed while the
+/// car is still waiting at it.
+pub fn publish(app: &AppHandle, update: PaymentUpdate) {
+    let mut payment = update.payment;
+    let config = app.state::<Payments>().config();
+
+    // Late money is still money the API has to know about
+    if update.confirmed {
+        sync_queue::record(app, sync_queue::ENTITY_PAYMENT, &payment.id, "confirmed", &payment);
+    }
+
+    if update.confirmed_now && config.auto_open_gate {
+        match &config.gate_port {
+            Some(port) => match serial::open_gate_specific_port(app.clone(), port.clone(), config.gate_command.clone()) {
+                Ok(gate) => {
+                    app.state::<AuditLog>().record(
+                        "system",
+                        "gate_opened_on_payment",
+                        &format!("{} for session {} ({})", payment.id, payment.session_id, gate.message),
+                    );
+                    if let Some(p) = app.state::<Payments>().mark_gate_opened(&payment.id) {
+                        payment = p;
+                    }
+                }
+                Err(e) => println!("[Rust] Payment {} confirmed but the gate did not open: {}", payment.id, e),
+            },
+            None => println!("[Rust] Payment {} confirmed; no gate port is set, so the gate stays closed", payment.id),
+        }
+    }
+
+    if let Err(e) = app.emit(PAYMENT_UPDATED_EVENT, &payment) {
+        println!("[Rust] Failed to emit mobile payment update: {}", e);
+    }
+}
+
+/// Ties the request to a parking session on this desktop, by `local_id` or
+/// row id, and fills in its plate.
+pub fn check_session(db: &Database, request: &mut PaymentRequest) -> Result<(), String> {
+    let session = db
+        .find_session(&request.session_id)?
+        .ok_or_else(|| format!("Session {} not found", request.session_id))?;
+    if session.status == SessionStatus::Cancelled {
+        return Err(format!("Session {} was cancelled", request.session_id));
+    }
+    request.plate_number.get_or_insert(session.plate_number);
+    Ok(())
+}
+
+/// Creates the payment and sends the push prompt in the background, since
+/// some providers hold the request until the customer enters their PIN.
+pub fn start(app: &AppHandle, mut request: PaymentRequest) -> Result<MobilePayment, String> {
+    check_session(&app.state::<Database>(), &mut request)?;
+    let (payment, new) = app.state::<Payments>().create(request)?;
+    if new {
+        let app = app.clone();
+        let id = payment.id.clone();
+        thread::spawn(move || {
+            if let Some(update) = app.state::<Payments>().push(&id) {
+                publish(&app, update);
+            }
+        });
+    }
+    Ok(payment)
+}
+
+/// Background thread that polls providers for payments with no callback yet.
+pub fn start_worker(app: AppHandle) {
+    thread::spawn(move || loop {
+        for update in app.state::<Payments>().poll_due() {
+            publish(&app, update);
+        }
+        thread::sleep(WORKER_INTERVAL);
+    });
+}
+
+pub fn start_callback_server(app: &AppHandle) {
+    let config = app.state::<Payments>().config();
+    if !config.enabled {
+        return;
+    }
+
+    let handle = app.clone();
+    let started = callback_server::serve("Mobile money", &config.callback_addr, "application/json", move |path, body| {
+        let (response, update) = handle.state::<Payments>().handle_callback(path, body);
+        if let Some(update) = update {
+            publish(&handle, update);
+        }
+        response
+    });
+    if let Err(e) = started {
+        println!("[Rust] {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_store;
+
+    fn mock_payments(name: &str) -> Payments {
+        let payments = test_store(&format!("payments-{}", name), Payments::load);
+        payments
+            .save_config(PaymentsConfig {
+                enabled: true,
+                mock: true,
+                poll_interval_secs: 0,
+                ..Default::default()
+            })
+            .unwrap();
+        payments
+    }
+
+    fn request(session: &str, phone: &str) -> PaymentRequest {
+        PaymentRequest {
+            session_id: session.to_string(),
+            phone: phone.to_string(),
+            amount: 2000.0,
+            provider: None,
+            plate_number: Some("T 123 ABC".to_string()),
+            receipt_number: None,
+        }
+    }
+
+    #[test]
+    fn phones_are_normalised_and_matched_to_wallets() {
+        assert_eq!(normalize_phone("0712 345 678").unwrap(), "255712345678");
+        assert_eq!(normalize_phone("+255 754 000 111").unwrap(), "255754000111");
+        assert!(normalize_phone("12345").is_err());
+        assert_eq!(ProviderKind::for_phone("255712345678"), Some(ProviderKind::TigoPesa));
+        assert_eq!(ProviderKind::for_phone("255754000111"), Some(ProviderKind::Mpesa));
+        assert_eq!(ProviderKind::for_phone("255684000111"), Some(ProviderKind::AirtelMoney));
+        assert_eq!(ProviderKind::for_phone("255614000111"), None);
+    }
+
+    #[test]
+    fn confirmation_by_callback_opens_the_gate_once() {
+        let payments = mock_payments("callback");
+        let payment = payments.create(request("S-1", "0712345678")).unwrap().0;
+        assert!(payments.push(&payment.id).is_none());
+        assert_eq!(payments.create(request("S-1", "0712345678")).unwrap().0.id, payment.id);
+
+        let update = payments.simulate(&payment.id, true).unwrap().unwrap();
+        assert!(update.confirmed_now);
+        assert_eq!(update.payment.status, PaymentStatus::Confirmed);
+        assert!(update.payment.transaction_id.is_some());
+
+        // Providers repeat callbacks; the gate must not open again
+        assert!(payments.simulate(&payment.id, true).unwrap().is_none());
+        // A paid session is not charged twice
+        assert_eq!(payments.create(request("S-1", "0754000111")).unwrap().0.id, payment.id);
+    }
+
+    #[test]
+    fn polling_picks_up_declines_and_timeouts() {
+        let payments = mock_payments("poll");
+        let declined = payments.create(request("S-2", "0684000111")).unwrap().0;
+        let silent = payments.create(request("S-3", "0754000111")).unwrap().0;
+        payments.push(&declined.id);
+        payments.push(&silent.id);
+
+        payments.mock.decide(&declined.id, false).unwrap();
+        let updates = payments.poll_due();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].payment.status, PaymentStatus::Failed);
+
+        let mut config = payments.config();
+        config.timeout_secs = 0;
+        payments.save_config(config).unwrap();
+        let updates = payments.poll_due();
+        assert_eq!(updates[0].payment.id, silent.id);
+        assert_eq!(updates[0].payment.status, PaymentStatus::TimedOut);
+
+        // Money that arrives late is recorded, but the car has gone
+        let late = payments.simulate(&silent.id, true).unwrap().unwrap();
+        assert_eq!(late.payment.status, PaymentStatus::Confirmed);
+        assert!(late.confirmed && !late.confirmed_now);
+    }
+
+    #[test]
+    fn part_payments_never_confirm() {
+        let payments = mock_payments("partial");
+        let payment = payments.create(request("S-6", "0754000111")).unwrap().0;
+        payments.push(&payment.id);
+
+        let body = |amount: u32| {
+            format!(r#"{{"reference":"{}","status":"confirmed","transaction_id":"MP9","amount":{}}}"#, payment.id, amount)
+        };
+        assert!(payments.handle_callback("/mock", &body(500)).1.is_none());
+        assert_eq!(payments.payment(&payment.id).unwrap().status, PaymentStatus::Pending);
+        assert!(payments.handle_callback("/mock", &body(2000)).1.unwrap().confirmed_now);
+    }
+
+    #[test]
+    fn payments_belong_to_a_session_on_this_desktop() {
+        let db = Database::open_in_memory().unwrap();
+        let session = db
+            .record_entry(crate::database::NewEntry {
+                plate_number: "T 100 AAA".to_string(),
+                vehicle_type: None,
+                gate_id: None,
+                operator_name: None,
+                ticket_number: None,
+                entry_time: Some(1_000),
+            })
+            .unwrap();
+
+        let mut by_local_id = request(&session.local_id, "0754000111");
+        by_local_id.plate_number = None;
+        check_session(&db, &mut by_local_id).unwrap();
+        assert_eq!(by_local_id.plate_number.as_deref(), Some("T 100 AAA"));
+        check_session(&db, &mut request(&session.id.to_string(), "0754000111")).unwrap();
+        assert!(check_session(&db, &mut request("S-404", "0754000111")).is_err());
+
+        db.cancel_session(session.id).unwrap();
+        assert!(check_session(&db, &mut request(&session.local_id, "0754000111")).is_err());
+    }
+
+    #[test]
+    fn lipa_number_payments_are_matched_on_phone_and_amount() {
+        let payments = mock_payments("lipa");
+        let payment = payments.create(request("S-4", "0712345678")).unwrap().0;
+        payments.push(&payment.id);
+
+        // Phone and amount alone don't confirm anything until the wallet does
+        let body = r#"{"status":"confirmed","transaction_id":"MP123","phone":"0712345678","amount":2000}"#;
+        let (_, update) = payments.handle_callback("/mock", body);
+        assert!(update.is_none());
+        assert_eq!(payments.payment(&payment.id).unwrap().status, PaymentStatus::Pending);
+
+        payments.mock.decide(&payment.id, true).unwrap();
+        let (_, update) = payments.handle_callback("/mock", body);
+        assert_eq!(update.unwrap().payment.id, payment.id);
+    }
+
+    #[test]
+    fn provider_callbacks_need_the_shared_secret() {
+        let payments = mock_payments("secret");
+        let mut config = payments.config();
+        config.mock = false;
+        config.tigopesa.callback_secret = "s3cret".to_string();
+        payments.save_config(config).unwrap();
+        let payment = payments.create(request("S-5", "0712345678")).unwrap().0;
+
+        let body = format!(
+            r#"{{"Status":true,"MFSTransactionID":"TP1","ReferenceID":"{}","CustomerMSISDN":"255712345678","Amount":2000}}"#,
+            payment.id
+        );
+        assert!(payments.handle_callback("/tigopesa", &body).1.is_none());
+        assert!(payments.handle_callback("/tigopesa?secret=s3cre", &body).1.is_none());
+        assert!(payments.handle_callback("/airtel?secret=s3cret", &body).1.is_none());
+        assert_eq!(payments.payment(&payment.id).unwrap().status, PaymentStatus::Pending);
+
+        let (_, update) = payments.handle_callback("/tigopesa?secret=s3cret", &body);
+        assert!(update.unwrap().confirmed_now);
+    }
+}
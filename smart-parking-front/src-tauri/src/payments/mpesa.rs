@@ -0,0 +1,158 @@
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use rsa::pkcs8::DecodePublicKey;
+use rsa::{Pkcs1v15Encrypt, RsaPublicKey};
+use serde::{Deserialize, Serialize};
+
+use super::{CallbackUpdate, MobileMoneyProvider, MobilePayment, ProviderStatus};
+
+/// The single stage C2B call is held open until the customer enters their PIN.
+const HTTP_TIMEOUT: Duration = Duration::from_secs(120);
+const SUCCESS_CODE: &str = "INS-0";
+
+/// Vodacom M-Pesa open API application credentials.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MpesaConfig {
+    /// Base URL including the environment, e.g. `.../openapi` or `.../sandbox`.
+    pub endpoint: String,
+    pub api_key: String,
+    /// Base64 public key from the developer portal.
+    pub public_key: String,
+    pub service_provider_code: String,
+}
+
+impl Default for MpesaConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: "https://openapi.m-pesa.com/openapi".to_string(),
+            api_key: String::new(),
+            public_key: String::new(),
+            service_provider_code: String::new(),
+        }
+    }
+}
+
+/// Vodacom M-Pesa single stage C2B. The push request answers with the
+/// outcome, so there are no callbacks.
+pub struct Mpesa {
+    config: MpesaConfig,
+    agent: ureq::Agent,
+}
+
+impl Mpesa {
+    pub fn new(config: MpesaConfig) -> Self {
+        Self {
+            config,
+            agent: ureq::AgentBuilder::new().timeout(HTTP_TIMEOUT).build(),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/ipg/v2/vodacomTZN{}", self.config.endpoint.trim_end_matches('/'), path)
+    }
+
+    /// The API takes RSA encrypted keys as bearer tokens.
+    fn bearer(&self, secret: &str) -> Result<String, String> {
+        let der = BASE64
+            .decode(self.config.public_key.trim())
+            .map_err(|e| format!("Invalid M-Pesa public key: {}", e))?;
+        let key = RsaPublicKey::from_public_key_der(&der).map_err(|e| format!("Invalid M-Pesa public key: {}", e))?;
+        let encrypted = key
+            .encrypt(&mut rsa::rand_core::OsRng, Pkcs1v15Encrypt, secret.as_bytes())
+            .map_err(|e| format!("Failed to encrypt M-Pesa credentials: {}", e))?;
+        Ok(format!("Bearer {}", BASE64.encode(encrypted)))
+    }
+
+    fn session(&self) -> Result<String, String> {
+        let body = self.read(
+            self.agent
+                .get(&self.url("/getSession/"))
+                .set("Authorization", &self.bearer(&self.config.api_key)?)
+                .set("Origin", "*")
+                .call(),
+        )?;
+        body.get("output_SessionID")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string())
+            .ok_or_else(|| "M-Pesa did not open a session".to_string())
+    }
+
+    /// M-Pesa answers failures with an error status and a JSON body.
+    fn read(&self, response: Result<ureq::Response, ureq::Error>) -> Result<serde_json::Value, String> {
+        match response {
+            Ok(r) | Err(ureq::Error::Status(_, r)) => {
+                r.into_json().map_err(|e| format!("Invalid M-Pesa response: {}", e))
+            }
+            Err(e) => Err(format!("M-Pesa request failed: {}", e)),
+        }
+    }
+}
+
+fn response_status(body: &serde_json::Value) -> ProviderStatus {
+    let field = |key: &str| body.get(key).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+    if field("output_ResponseCode") == SUCCESS_CODE {
+        ProviderStatus::Confirmed {
+            transaction_id: field("output_TransactionID"),
+        }
+    } else {
+        ProviderStatus::Failed {
+            reason: field("output_ResponseDesc"),
+        }
+    }
+}
+
+impl MobileMoneyProvider for Mpesa {
+    fn request_payment(&self, payment: &MobilePayment) -> Result<ProviderStatus, String> {
+        let session = self.session()?;
+        let body = self.read(
+            self.agent
+                .post(&self.url("/c2bPayment/singleStage/"))
+                .set("Authorization", &self.bearer(&session)?)
+                .set("Origin", "*")
+                .send_json(serde_json::json!({
+                    "input_Amount": format!("{:.0}", payment.amount),
+                    "input_Country": "TZN",
+                    "input_Currency": "TZS",
+                    "input_CustomerMSISDN": payment.phone,
+                    "input_ServiceProviderCode": self.config.service_provider_code,
+                    "input_ThirdPartyConversationID": payment.id,
+                    "input_TransactionReference": payment.id,
+                    "input_PurchasedItemsDesc": "Maegesho",
+                })),
+        )?;
+        Ok(response_status(&body))
+    }
+
+    fn query_status(&self, payment: &MobilePayment) -> Result<ProviderStatus, String> {
+        let session = self.session()?;
+        let body = self.read(
+            self.agent
+                .get(&self.url("/queryTransactionStatus/"))
+                .query("input_QueryReference", &payment.id)
+                .query("input_ServiceProviderCode", &self.config.service_provider_code)
+                .query("input_ThirdPartyConversationID", &payment.id)
+                .query("input_Country", "TZN")
+                .set("Authorization", &self.bearer(&session)?)
+                .set("Origin", "*")
+                .call(),
+        )?;
+
+        let field = |key: &str| body.get(key).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+        Ok(match field("output_ResponseTransactionStatus").as_str() {
+            "Completed" => ProviderStatus::Confirmed {
+                transaction_id: field("output_TransactionID"),
+            },
+            "Failed" | "Cancelled" | "Expired" => ProviderStatus::Failed {
+                reason: field("output_ResponseDesc"),
+            },
+            _ => ProviderStatus::Pending,
+        })
+    }
+
+    fn parse_callback(&self, _body: &str) -> Option<CallbackUpdate> {
+        None
+    }
+}
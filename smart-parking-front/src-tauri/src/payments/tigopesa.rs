@@ -0,0 +1,132 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::{CallbackUpdate, MobileMoneyProvider, MobilePayment, ProviderStatus};
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+const SUCCESS_CODE: &str = "BILLER-18-0000-S";
+
+/// Tigo Pesa push billpay credentials, issued with the biller number.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TigoPesaConfig {
+    pub endpoint: String,
+    pub username: String,
+    pub password: String,
+    /// The Lipa number customers pay to.
+    pub biller_msisdn: String,
+    /// Sent back as `?secret=` on the callback URL registered with Tigo.
+    pub callback_secret: String,
+}
+
+/// Tigo Pesa push billpay. Outcomes only arrive on the callback; there is no
+/// status query.
+pub struct TigoPesa {
+    config: TigoPesaConfig,
+    agent: ureq::Agent,
+}
+
+impl TigoPesa {
+    pub fn new(config: TigoPesaConfig) -> Self {
+        Self {
+            config,
+            agent: ureq::AgentBuilder::new().timeout(HTTP_TIMEOUT).build(),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.config.endpoint.trim_end_matches('/'), path)
+    }
+
+    fn token(&self) -> Result<String, String> {
+        let body: serde_json::Value = self
+            .agent
+            .post(&self.url("/token"))
+            .send_form(&[
+                ("username", &self.config.username),
+                ("password", &self.config.password),
+                ("grant_type", "password"),
+            ])
+            .map_err(|e| format!("Tigo Pesa token request failed: {}", e))?
+            .into_json()
+            .map_err(|e| format!("Invalid Tigo Pesa token response: {}", e))?;
+
+        body.get("access_token")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string())
+            .ok_or_else(|| "Tigo Pesa token response has no access_token".to_string())
+    }
+}
+
+impl MobileMoneyProvider for TigoPesa {
+    fn request_payment(&self, payment: &MobilePayment) -> Result<ProviderStatus, String> {
+        let token = self.token()?;
+        let body: serde_json::Value = self
+            .agent
+            .post(&self.url("/API/BillerPayment/BillerPay"))
+            .set("Authorization", &format!("bearer {}", token))
+            .set("Username", &self.config.username)
+            .set("Password", &self.config.password)
+            .send_json(serde_json::json!({
+                "CustomerMSISDN": payment.phone,
+                "BillerMSISDN": self.config.biller_msisdn,
+                "Amount": payment.amount.round() as u64,
+                "Remarks": format!("Maegesho {}", payment.plate_number.as_deref().unwrap_or(&payment.session_id)),
+                "ReferenceID": payment.id,
+            }))
+            .map_err(|e| format!("Tigo Pesa push request failed: {}", e))?
+            .into_json()
+            .map_err(|e| format!("Invalid Tigo Pesa response: {}", e))?;
+
+        if body.get("ResponseStatus").and_then(|v| v.as_bool()) == Some(true) {
+            Ok(ProviderStatus::Pending)
+        } else {
+            Ok(ProviderStatus::Failed {
+                reason: body
+                    .get("ResponseDescription")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("Tigo Pesa refused the request")
+                    .to_string(),
+            })
+        }
+    }
+
+    fn query_status(&self, _payment: &MobilePayment) -> Result<ProviderStatus, String> {
+        Ok(ProviderStatus::Pending)
+    }
+
+    fn parse_callback(&self, body: &str) -> Option<CallbackUpdate> {
+        let v: serde_json::Value = serde_json::from_str(body).ok()?;
+        let str_field = |key: &str| v.get(key).and_then(|v| v.as_str()).map(|s| s.to_string());
+
+        let status = match v.get("Status")?.as_bool()? {
+            true => ProviderStatus::Confirmed {
+                transaction_id: str_field("MFSTransactionID").unwrap_or_default(),
+            },
+            false => ProviderStatus::Failed {
+                reason: str_field("Description").unwrap_or_else(|| "Payment failed".to_string()),
+            },
+        };
+        Some(CallbackUpdate {
+            reference: str_field("ReferenceID"),
+            phone: str_field("CustomerMSISDN"),
+            amount: v.get("Amount").and_then(|a| a.as_f64().or_else(|| a.as_str()?.parse().ok())),
+            status,
+        })
+    }
+
+    fn callback_secret(&self) -> &str {
+        &self.config.callback_secret
+    }
+
+    fn callback_ack(&self, update: Option<&CallbackUpdate>) -> String {
+        serde_json::json!({
+            "ResponseCode": SUCCESS_CODE,
+            "ResponseStatus": update.is_some(),
+            "ResponseDescription": if update.is_some() { "Callback successful" } else { "Unreadable callback" },
+            "ReferenceID": update.and_then(|u| u.reference.clone()).unwrap_or_default(),
+        })
+        .to_string()
+    }
+}
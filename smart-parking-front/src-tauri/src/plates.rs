@@ -0,0 +1,494 @@
+use std::thread;
+use std::time::Duration;
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use rusqlite::{params, Row};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::audit::AuditLog;
+use crate::database::{sql, Database};
+use crate::serial;
+use crate::sync_queue::{SyncConfig, SyncQueue};
+use crate::util::{local_day_start, normalize_plate, now_secs};
+
+/// Event emitted with a `PlateDecision` when a denied or alerted plate is seen.
+pub const PLATE_ALERT_EVENT: &str = "plate-alert";
+
+const CONFIG_KEY: &str = "plates";
+const DAY: u64 = 24 * 60 * 60;
+const REFRESH_INTERVAL: Duration = Duration::from_secs(15 * 60);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlateCategory {
+    #[serde(alias = "permit_holder")]
+    Permit,
+    Staff,
+    /// Exempt government vehicles.
+    #[serde(alias = "exempt")]
+    Government,
+    #[serde(alias = "blacklisted")]
+    Blacklist,
+}
+
+impl PlateCategory {
+    fn as_str(self) -> &'static str {
+        match self {
+            PlateCategory::Permit => "permit",
+            PlateCategory::Staff => "staff",
+            PlateCategory::Government => "government",
+            PlateCategory::Blacklist => "blacklist",
+        }
+    }
+
+    fn parse(s: &str) -> Self {
+        match s {
+            "staff" => PlateCategory::Staff,
+            "government" => PlateCategory::Government,
+            "blacklist" => PlateCategory::Blacklist,
+            _ => PlateCategory::Permit,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlateAction {
+    /// Free passage; the gate opens by itself.
+    Allow,
+    /// Normal tariff.
+    Charge,
+    /// Turned away.
+    Deny,
+    /// Let the operator deal with it, e.g. a vehicle reported stolen.
+    Alert,
+}
+
+/// One plate on one list, as cached from the API.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlateEntry {
+    pub server_id: Option<i64>,
+    pub plate_number: String,
+    pub category: PlateCategory,
+    /// Blacklist only: `Deny` or `Alert`, alert when not given.
+    pub action: Option<PlateAction>,
+    pub holder_name: Option<String>,
+    pub reason: Option<String>,
+    pub valid_from: Option<u64>,
+    pub valid_until: Option<u64>,
+}
+
+impl PlateEntry {
+    fn active(&self, at: u64) -> bool {
+        self.valid_from.map_or(true, |from| from <= at) && self.valid_until.map_or(true, |until| at < until)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlateDecision {
+    pub plate_number: String,
+    pub action: PlateAction,
+    pub category: Option<PlateCategory>,
+    pub holder_name: Option<String>,
+    pub reason: Option<String>,
+    pub valid_until: Option<u64>,
+    #[serde(default)]
+    pub gate_opened: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlateConfig {
+    /// Open the gate for whitelisted plates without the operator, at gates
+    /// with a known serial port.
+    pub auto_open_gate: bool,
+}
+
+impl Default for PlateConfig {
+    fn default() -> Self {
+        Self {
+            auto_open_gate: true,
+        }
+    }
+}
+
+/// A plate as the API sends it. Dates may be unix seconds, `YYYY-MM-DD` or
+/// date-times.
+#[derive(Debug, Deserialize)]
+struct ApiPlate {
+    id: Option<i64>,
+    plate_number: String,
+    #[serde(alias = "list_type", alias = "type")]
+    category: PlateCategory,
+    #[serde(default)]
+    action: Option<PlateAction>,
+    #[serde(default)]
+    holder_name: Option<String>,
+    #[serde(default)]
+    reason: Option<String>,
+    #[serde(default)]
+    valid_from: serde_json::Value,
+    #[serde(default)]
+    valid_until: serde_json::Value,
+}
+
+/// Unix seconds for an API date. A bare `valid_until` date includes the
+/// whole of that day.
+fn parse_time(value: &serde_json::Value, end_of_day: bool) -> Result<Option<u64>, String> {
+    let s = match value {
+        serde_json::Value::Null => return Ok(None),
+        serde_json::Value::Number(n) => return Ok(n.as_u64()),
+        serde_json::Value::String(s) if s.trim().is_empty() => return Ok(None),
+        serde_json::Value::String(s) => s.trim(),
+        _ => return Err(format!("Invalid date {}", value)),
+    };
+
+    if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+        return Ok(Some(t.timestamp().max(0) as u64));
+    }
+    if let Ok(t) = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S") {
+        return Local
+            .from_local_datetime(&t)
+            .earliest()
+            .map(|t| Some(t.timestamp().max(0) as u64))
+            .ok_or_else(|| format!("Invalid local time '{}'", s));
+    }
+    let start = local_day_start(s)?;
+    Ok(Some(if end_of_day { start + DAY } else { start }))
+}
+
+/// Entries the desktop can't read, like a category added on the server
+/// later, are skipped rather than failing the whole refresh.
+fn parse_plate_lists(body: &serde_json::Value) -> Result<Vec<PlateEntry>, String> {
+    let data = body.get("data").unwrap_or(body);
+    let plates = data.as_array().ok_or("Invalid plate lists: expected a list of plates")?;
+
+    Ok(plates
+        .iter()
+        .filter_map(|value| match parse_plate(value) {
+            Ok(entry) => entry,
+            Err(e) => {
+                println!("[Rust] Skipping listed plate {}: {}", value, e);
+                None
+            }
+        })
+        .collect())
+}
+
+/// One list entry, or nothing for a blank plate.
+fn parse_plate(value: &serde_json::Value) -> Result<Option<PlateEntry>, String> {
+    let p: ApiPlate = serde_json::from_value(value.clone()).map_err(|e| e.to_string())?;
+    if normalize_plate(&p.plate_number).is_empty() {
+        return Ok(None);
+    }
+
+    let action = match (p.category, p.action) {
+        (PlateCategory::Blacklist, Some(PlateAction::Deny)) => Some(PlateAction::Deny),
+        (PlateCategory::Blacklist, _) => Some(PlateAction::Alert),
+        _ => None,
+    };
+    Ok(Some(PlateEntry {
+        server_id: p.id,
+        plate_number: p.plate_number.trim().to_string(),
+        category: p.category,
+        action,
+        holder_name: p.holder_name,
+        reason: p.reason,
+        valid_from: parse_time(&p.valid_from, false)?,
+        valid_until: parse_time(&p.valid_until, true)?,
+    }))
+}
+
+/// What to do with a plate, given every list entry for it. The blacklist
+/// wins over any whitelist; a lapsed permit pays like anyone else.
+pub fn decide_from(plate_number: &str, entries: &[PlateEntry], at: u64) -> PlateDecision {
+    let decision = |action, entry: Option<&PlateEntry>, reason: Option<String>| PlateDecision {
+        plate_number: plate_number.trim().to_string(),
+        action,
+        category: entry.map(|e| e.category),
+        holder_name: entry.and_then(|e| e.holder_name.clone()),
+        reason: reason.or_else(|| entry.and_then(|e| e.reason.clone())),
+        valid_until: entry.and_then(|e| e.valid_until),
+        gate_opened: false,
+    };
+    let listed = |e: &&PlateEntry| e.category != PlateCategory::Blacklist;
+
+    if let Some(e) = entries.iter().find(|e| e.category == PlateCategory::Blacklist && e.active(at)) {
+        return decision(e.action.unwrap_or(PlateAction::Alert), Some(e), None);
+    }
+    if let Some(e) = entries.iter().filter(listed).find(|e| e.active(at)) {
+        return decision(PlateAction::Allow, Some(e), None);
+    }
+    if let Some(e) = entries.iter().find(listed) {
+        let reason = match e.valid_from {
+            Some(from) if at < from => format!("{} not valid yet", e.category.as_str()),
+            _ => format!("{} expired", e.category.as_str()),
+        };
+        return decision(PlateAction::Charge, Some(e), Some(reason));
+    }
+    decision(PlateAction::Charge, None, None)
+}
+
+fn entry_from_row(row: &Row) -> rusqlite::Result<PlateEntry> {
+    let action: Option<String> = row.get("action")?;
+    Ok(PlateEntry {
+        server_id: row.get("server_id")?,
+        plate_number: row.get("plate_number")?,
+        category: PlateCategory::parse(&row.get::<_, String>("category")?),
+        action: action.map(|a| if a == "deny" { PlateAction::Deny } else { PlateAction::Alert }),
+        holder_name: row.get("holder_name")?,
+        reason: row.get("reason")?,
+        valid_from: row.get("valid_from")?,
+        valid_until: row.get("valid_until")?,
+    })
+}
+
+/// Replaces the cached lists in one go, so a lookup never sees half of them.
+fn store(db: &Database, entries: &[PlateEntry]) -> Result<(), String> {
+    let mut conn = db.lock();
+    let tx = sql(conn.transaction(), "start plate list update")?;
+    sql(tx.execute("DELETE FROM plate_lists", []), "clear plate lists")?;
+    let now = now_secs();
+    for e in entries {
+        sql(
+            tx.execute(
+                "INSERT INTO plate_lists (server_id, plate_number, plate_key, category, action, holder_name, reason,
+                     valid_from, valid_until, synced_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    e.server_id,
+                    e.plate_number,
+                    normalize_plate(&e.plate_number),
+                    e.category.as_str(),
+                    e.action.map(|a| if a == PlateAction::Deny { "deny" } else { "alert" }),
+                    e.holder_name,
+                    e.reason,
+                    e.valid_from,
+                    e.valid_until,
+                    now,
+                ],
+            ),
+            "cache plate",
+        )?;
+    }
+    sql(tx.commit(), "save plate lists")
+}
+
+pub fn entries(db: &Database, category: Option<PlateCategory>) -> Result<Vec<PlateEntry>, String> {
+    let conn = db.lock();
+    let mut stmt = sql(
+        conn.prepare("SELECT * FROM plate_lists WHERE ?1 IS NULL OR category = ?1 ORDER BY plate_key"),
+        "read plate lists",
+    )?;
+    let rows = sql(stmt.query_map(params![category.map(|c| c.as_str())], entry_from_row), "read plate lists")?;
+    sql(rows.collect(), "read plate lists")
+}
+
+/// Looks the plate up in the cached lists.
+pub fn decide(db: &Database, plate_number: &str, at: u64) -> Result<PlateDecision, String> {
+    let conn = db.lock();
+    let mut stmt = sql(conn.prepare("SELECT * FROM plate_lists WHERE plate_key = ?1"), "look up plate")?;
+    let rows = sql(stmt.query_map(params![normalize_plate(plate_number)], entry_from_row), "look up plate")?;
+    let found: Vec<PlateEntry> = sql(rows.collect(), "look up plate")?;
+    Ok(decide_from(plate_number, &found, at))
+}
+
+pub fn config(db: &Database) -> PlateConfig {
+    db.get_config(CONFIG_KEY)
+        .ok()
+        .flatten()
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_config(db: &Database, config: &PlateConfig) -> Result<(), String> {
+    let value = serde_json::to_value(config).map_err(|e| format!("Failed to encode plate config: {}", e))?;
+    db.set_config(CONFIG_KEY, &value)
+}
+
+/// Fetches the lists and replaces the cache. The cache is left alone if the
+/// API can't be reached.
+pub fn refresh(db: &Database, config: &SyncConfig) -> Result<usize, String> {
+    let url = format!("{}{}", config.api_url.trim_end_matches('/'), config.plate_lists_path);
+    let mut request = ureq::AgentBuilder::new()
+        .timeout(HTTP_TIMEOUT)
+        .build()
+        .get(&url)
+        .set("Accept", "application/json");
+    if !config.api_token.is_empty() {
+        request = request.set("Authorization", &format!("Bearer {}", config.api_token));
+    }
+
+    let body: serde_json::Value = request
+        .call()
+        .map_err(|e| format!("Plate list refresh failed: {}", e))?
+        .into_json()
+        .map_err(|e| format!("Invalid plate list response: {}", e))?;
+
+    let entries = parse_plate_lists(&body)?;
+    store(db, &entries)?;
+    println!("[Rust] Cached {} listed plates", entries.len());
+    Ok(entries.len())
+}
+
+/// Opens the gate the plate was read at. Never guesses: without a gate and
+/// its port, no barrier is lifted.
+fn open_gate(app: &AppHandle, db: &Database, gate_id: Option<i64>) -> Result<String, String> {
+    let id = gate_id.ok_or("the gate it was read at isn't known")?;
+    let gate = db.gate_device(id)?;
+    if !gate.enabled {
+        return Err(format!("Gate {} is disabled", gate.name));
+    }
+    let port = gate.serial_port.ok_or_else(|| format!("Gate {} has no serial port set", gate.name))?;
+    serial::open_gate_specific_port(app.clone(), port, gate.open_command).map(|g| g.message)
+}
+
+/// Decides what to do with a plate read at a gate and acts on it: the gate
+/// opens for whitelisted plates and operators are alerted to blacklisted ones.
+pub fn check(app: &AppHandle, plate_number: &str, gate_id: Option<i64>) -> Result<PlateDecision, String> {
+    let db = app.state::<Database>();
+    let mut decision = decide(&db, plate_number, now_secs())?;
+    let audit = app.state::<AuditLog>();
+
+    match decision.action {
+        PlateAction::Allow => {
+            if config(&db).auto_open_gate {
+                match open_gate(app, &db, gate_id) {
+                    Ok(message) => {
+                        decision.gate_opened = true;
+                        let category = decision.category.map_or("", |c| c.as_str());
+                        audit.record(
+                            "system",
+                            "gate_opened_for_listed_plate",
+                            &format!("{} ({}) {}", decision.plate_number, category, message),
+                        );
+                    }
+                    Err(e) => println!("[Rust] {} is listed but the gate did not open: {}", decision.plate_number, e),
+                }
+            }
+        }
+        PlateAction::Deny | PlateAction::Alert => {
+            println!("[Rust] Blacklisted plate {} seen: {:?}", decision.plate_number, decision.reason);
+            audit.record(
+                "system",
+                "blacklisted_plate_seen",
+                &format!("{} {:?} {}", decision.plate_number, decision.action, decision.reason.as_deref().unwrap_or("")),
+            );
+            if let Err(e) = app.emit(PLATE_ALERT_EVENT, &decision) {
+                println!("[Rust] Failed to emit plate alert: {}", e);
+            }
+        }
+        PlateAction::Charge => {}
+    }
+    Ok(decision)
+}
+
+/// Background thread that keeps the cached lists current while syncing is on.
+pub fn start_worker(app: AppHandle) {
+    thread::spawn(move || loop {
+        let db = app.state::<Database>();
+        let config = app.state::<SyncQueue>().config(&db);
+        if config.enabled {
+            if let Err(e) = refresh(&db, &config) {
+                println!("[Rust] {}", e);
+            }
+        }
+        thread::sleep(REFRESH_INTERVAL);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const NOW: u64 = 1_714_564_800;
+
+    fn lists() -> Vec<PlateEntry> {
+        parse_plate_lists(&json!({
+            "data": [
+                { "id": 1, "plate_number": "T 100 AAA", "list_type": "permit_holder", "holder_name": "Asha",
+                  "valid_from": NOW - DAY, "valid_until": NOW + DAY },
+                { "id": 2, "plate_number": "t200bbb", "category": "staff", "holder_name": "Juma" },
+                { "id": 3, "plate_number": "STK 1234", "category": "exempt" },
+                { "id": 4, "plate_number": "T 400 DDD", "category": "blacklisted", "reason": "Reported stolen" },
+                { "id": 5, "plate_number": "T 500 EEE", "category": "blacklist", "action": "deny",
+                  "reason": "Unpaid fines" },
+                { "id": 6, "plate_number": "T 600 FFF", "category": "permit", "valid_until": "2020-01-31" },
+                { "id": 7, "plate_number": "T 200 BBB", "category": "blacklist", "action": "deny",
+                  "valid_until": "2020-01-31T00:00:00Z" },
+                { "id": 8, "plate_number": " ", "category": "staff" }
+            ]
+        }))
+        .unwrap()
+    }
+
+    fn decide_plate(plate: &str) -> PlateDecision {
+        let entries = lists();
+        let key = normalize_plate(plate);
+        let found: Vec<PlateEntry> = entries
+            .into_iter()
+            .filter(|e| normalize_plate(&e.plate_number) == key)
+            .collect();
+        decide_from(plate, &found, NOW)
+    }
+
+    #[test]
+    fn decisions_cover_every_list() {
+        let permit = decide_plate("T100AAA");
+        assert_eq!((permit.action, permit.category), (PlateAction::Allow, Some(PlateCategory::Permit)));
+        assert_eq!(permit.holder_name.as_deref(), Some("Asha"));
+        assert_eq!(decide_plate("STK 1234").category, Some(PlateCategory::Government));
+
+        let stolen = decide_plate("T 400 DDD");
+        assert_eq!((stolen.action, stolen.reason.as_deref()), (PlateAction::Alert, Some("Reported stolen")));
+        assert_eq!(decide_plate("T 500 EEE").action, PlateAction::Deny);
+
+        let lapsed = decide_plate("T 600 FFF");
+        assert_eq!((lapsed.action, lapsed.reason.as_deref()), (PlateAction::Charge, Some("permit expired")));
+        let stranger = decide_plate("T 999 ZZZ");
+        assert_eq!((stranger.action, stranger.category), (PlateAction::Charge, None));
+    }
+
+    #[test]
+    fn blacklist_wins_only_while_it_is_in_force() {
+        // Juma is staff; his old blacklisting ran out in 2020
+        assert_eq!(decide_plate("T 200 BBB").action, PlateAction::Allow);
+
+        let mut entries = lists();
+        entries.retain(|e| e.server_id == Some(2) || e.server_id == Some(7));
+        entries[1].valid_until = None;
+        assert_eq!(decide_from("T 200 BBB", &entries, NOW).action, PlateAction::Deny);
+
+        let mut early = lists();
+        early.retain(|e| e.server_id == Some(1));
+        let not_yet = decide_from("T 100 AAA", &early, NOW - 2 * DAY);
+        assert_eq!((not_yet.action, not_yet.reason.as_deref()), (PlateAction::Charge, Some("permit not valid yet")));
+    }
+
+    #[test]
+    fn cache_is_replaced_and_looked_up_by_plate() {
+        let db = Database::open_in_memory().unwrap();
+        store(&db, &lists()).unwrap();
+        assert_eq!(entries(&db, None).unwrap().len(), 7);
+        assert_eq!(entries(&db, Some(PlateCategory::Blacklist)).unwrap().len(), 3);
+        assert_eq!(decide(&db, "t 500 eee", NOW).unwrap().action, PlateAction::Deny);
+
+        store(&db, &lists()[..1]).unwrap();
+        assert_eq!(entries(&db, None).unwrap().len(), 1);
+        assert_eq!(decide(&db, "T 500 EEE", NOW).unwrap().action, PlateAction::Charge);
+
+        // A category this desktop doesn't know yet costs only that entry
+        let mixed = json!([
+            { "plate_number": "T 1", "category": "vip" },
+            { "plate_number": "T 2", "category": "staff", "valid_until": "someday" },
+            { "plate_number": "T 3", "category": "staff" }
+        ]);
+        let parsed = parse_plate_lists(&mixed).unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].plate_number, "T 3");
+        assert!(parse_plate_lists(&json!({ "data": "maintenance" })).is_err());
+    }
+}
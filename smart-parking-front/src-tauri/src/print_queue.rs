@@ -0,0 +1,378 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::escpos::PrinterProfile;
+use crate::transport::{self, PrintResult};
+use crate::util::{now_secs, read_json, write_json};
+
+/// Event emitted with the full `PrintJob` whenever a job changes state.
+pub const JOB_UPDATED_EVENT: &str = "print-job-updated";
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const MAX_BACKOFF_SECS: u64 = 60;
+/// Roughly an hour of retries at the capped backoff.
+const MAX_ATTEMPTS: u32 = 60;
+/// Finished jobs kept in the queue file for the operator's job list.
+const KEEP_FINISHED: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Printing,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrintJob {
+    pub id: String,
+    pub description: String,
+    pub printer_name: String,
+    pub profile: PrinterProfile,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub data: Vec<u8>,
+    pub state: JobState,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub next_attempt_at: u64,
+}
+
+/// Print jobs persisted to `print_queue.json`, so receipts survive a printer
+/// being off, and the app being restarted while it is.
+pub struct PrintQueue {
+    path: PathBuf,
+    jobs: Mutex<Vec<PrintJob>>,
+}
+
+static JOB_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+impl PrintQueue {
+    /// Loads the queue file. Jobs that were mid-print when the app stopped
+    /// are queued again, since there's no telling whether they came out. A
+    /// file that won't parse is kept aside for recovery and the queue starts
+    /// empty, so printing still works.
+    pub fn load(path: PathBuf) -> Self {
+        let mut jobs: Vec<PrintJob> = read_json(&path).unwrap_or_else(|e| {
+            println!("[Rust] Print queue not loaded: {}", e);
+            Vec::new()
+        });
+
+        for job in jobs.iter_mut().filter(|j| j.state == JobState::Printing) {
+            job.state = JobState::Queued;
+        }
+
+        let pending = jobs.iter().filter(|j| j.state == JobState::Queued).count();
+        println!("[Rust] Print queue loaded: {} pending job(s)", pending);
+
+        Self {
+            path,
+            jobs: Mutex::new(jobs),
+        }
+    }
+
+    fn save(&self, jobs: &mut Vec<PrintJob>) -> Result<(), String> {
+        let finished = |j: &PrintJob| matches!(j.state, JobState::Done | JobState::Cancelled);
+        let excess = jobs.iter().filter(|j| finished(j)).count().saturating_sub(KEEP_FINISHED);
+        let mut dropped = 0;
+        jobs.retain(|j| {
+            if dropped < excess && finished(j) {
+                dropped += 1;
+                false
+            } else {
+                true
+            }
+        });
+
+        write_json(&self.path, jobs)
+    }
+
+    pub fn enqueue(
+        &self,
+        printer_name: &str,
+        profile: &PrinterProfile,
+        data: Vec<u8>,
+        description: &str,
+    ) -> Result<PrintJob, String> {
+        let now = now_secs();
+        let job = PrintJob {
+            id: format!("job-{}-{}", now, JOB_COUNTER.fetch_add(1, Ordering::Relaxed)),
+            description: description.to_string(),
+            printer_name: printer_name.to_string(),
+            profile: profile.clone(),
+            data,
+            state: JobState::Queued,
+            attempts: 0,
+            last_error: None,
+            created_at: now,
+            updated_at: now,
+            next_attempt_at: now,
+        };
+
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.push(job.clone());
+        self.save(&mut jobs)?;
+        Ok(job)
+    }
+
+    pub fn jobs(&self) -> Vec<PrintJob> {
+        self.jobs.lock().unwrap().clone()
+    }
+
+    /// Moves a queued job to `Printing`, so the worker and a command never
+    /// send the same job twice.
+    fn claim(&self, id: &str) -> Option<PrintJob> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.iter_mut().find(|j| j.id == id && j.state == JobState::Queued)?;
+        job.state = JobState::Printing;
+        job.attempts += 1;
+        job.updated_at = now_secs();
+        let claimed = job.clone();
+
+        if let Err(e) = self.save(&mut jobs) {
+            println!("[Rust] {}", e);
+        }
+        Some(claimed)
+    }
+
+    fn finish(&self, id: &str, outcome: &Result<PrintResult, String>) -> Option<PrintJob> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.iter_mut().find(|j| j.id == id)?;
+        let now = now_secs();
+        job.updated_at = now;
+
+        match outcome {
+            Ok(_) => {
+                job.state = JobState::Done;
+                job.last_error = None;
+                job.data.clear();
+            }
+            Err(e) => {
+                job.last_error = Some(e.clone());
+                if job.attempts >= MAX_ATTEMPTS {
+                    job.state = JobState::Failed;
+                } else {
+                    job.state = JobState::Queued;
+                    let backoff = (POLL_INTERVAL.as_secs() << job.attempts.min(4)).min(MAX_BACKOFF_SECS);
+                    job.next_attempt_at = now + backoff;
+                }
+            }
+        }
+
+        let updated = job.clone();
+        if let Err(e) = self.save(&mut jobs) {
+            println!("[Rust] {}", e);
+        }
+        Some(updated)
+    }
+
+    /// Puts a failed (or waiting) job back at the front of the retry schedule.
+    pub fn retry(&self, id: &str) -> Result<PrintJob, String> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs
+            .iter_mut()
+            .find(|j| j.id == id)
+            .ok_or_else(|| format!("Print job {} not found", id))?;
+
+        if !matches!(job.state, JobState::Queued | JobState::Failed) {
+            return Err(format!("Print job {} can't be retried", id));
+        }
+
+        job.state = JobState::Queued;
+        job.attempts = 0;
+        job.next_attempt_at = now_secs();
+        let updated = job.clone();
+        self.save(&mut jobs)?;
+        Ok(updated)
+    }
+
+    pub fn cancel(&self, id: &str) -> Result<PrintJob, String> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs
+            .iter_mut()
+            .find(|j| j.id == id)
+            .ok_or_else(|| format!("Print job {} not found", id))?;
+
+        if !matches!(job.state, JobState::Queued | JobState::Failed) {
+            return Err(format!("Print job {} can't be cancelled", id));
+        }
+
+        job.state = JobState::Cancelled;
+        job.updated_at = now_secs();
+        job.data.clear();
+        let updated = job.clone();
+        self.save(&mut jobs)?;
+        Ok(updated)
+    }
+
+    fn due_job_ids(&self) -> Vec<String> {
+        let now = now_secs();
+        self.jobs
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|j| j.state == JobState::Queued && j.next_attempt_at <= now)
+            .map(|j| j.id.clone())
+            .collect()
+    }
+}
+
+fn emit_job(app: &AppHandle, job: &PrintJob) {
+    if let Err(e) = app.emit(JOB_UPDATED_EVENT, job) {
+        println!("[Rust] Failed to emit print job update: {}", e);
+    }
+}
+
+/// Sends one queued job to its printer. On failure the job stays queued for
+/// the worker and the error says so.
+pub fn process_job(app: &AppHandle, id: &str) -> Result<PrintResult, String> {
+    let queue = app.state::<PrintQueue>();
+    let job = queue
+        .claim(id)
+        .ok_or_else(|| format!("Print job {} is not waiting to print", id))?;
+    emit_job(app, &job);
+
+    println!("[Rust] Printing job {} ({}) on {}", job.id, job.description, job.printer_name);
+    let message = format!("{} printed successfully", job.description);
+    let outcome = transport::print_bytes(&job.printer_name, &job.data, &job.profile, &message)
+        .map(|r| PrintResult {
+            job_id: Some(job.id.clone()),
+            ..r
+        });
+
+    if let Some(updated) = queue.finish(id, &outcome) {
+        emit_job(app, &updated);
+
+        if let Err(e) = &outcome {
+            return Err(match updated.state {
+                JobState::Failed => format!("{}. Print job {} has failed", e, id),
+                _ => format!(
+                    "{}. Kept in the print queue as job {}; it will print when the printer is back",
+                    e, id
+                ),
+            });
+        }
+    }
+
+    outcome
+}
+
+/// Background thread that retries queued jobs as they become due.
+pub fn start_worker(app: AppHandle) {
+    thread::spawn(move || loop {
+        let due = app.state::<PrintQueue>().due_job_ids();
+        for id in due {
+            if let Err(e) = process_job(&app, &id) {
+                println!("[Rust] {}", e);
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn queue(name: &str) -> PrintQueue {
+        let path = std::env::temp_dir().join(format!("print-queue-{}-{}.json", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        PrintQueue::load(path)
+    }
+
+    fn printed() -> Result<PrintResult, String> {
+        Ok(PrintResult {
+            success: true,
+            message: "printed".to_string(),
+            bytes_written: 3,
+            status: None,
+            job_id: None,
+        })
+    }
+
+    #[test]
+    fn claimed_jobs_print_once() {
+        let queue = queue("claim");
+        let job = queue.enqueue("POS-80", &PrinterProfile::default(), vec![1, 2, 3], "Receipt RL0001").unwrap();
+        assert_eq!(queue.due_job_ids(), vec![job.id.clone()]);
+
+        let claimed = queue.claim(&job.id).unwrap();
+        assert_eq!((claimed.state, claimed.attempts), (JobState::Printing, 1));
+        assert!(queue.claim(&job.id).is_none());
+        assert!(queue.due_job_ids().is_empty());
+
+        let done = queue.finish(&job.id, &printed()).unwrap();
+        assert_eq!(done.state, JobState::Done);
+        assert!(done.data.is_empty());
+        assert!(queue.retry(&job.id).is_err());
+    }
+
+    #[test]
+    fn failures_back_off_then_give_up() {
+        let queue = queue("backoff");
+        let job = queue.enqueue("POS-80", &PrinterProfile::default(), vec![1], "Receipt RL0002").unwrap();
+        let offline = Err("Printer is offline".to_string());
+
+        queue.claim(&job.id).unwrap();
+        let first = queue.finish(&job.id, &offline).unwrap();
+        assert_eq!(first.state, JobState::Queued);
+        assert_eq!(first.next_attempt_at - first.updated_at, POLL_INTERVAL.as_secs() << 1);
+        assert!(queue.due_job_ids().is_empty());
+
+        for _ in 1..5 {
+            queue.jobs.lock().unwrap()[0].state = JobState::Queued;
+            queue.claim(&job.id).unwrap();
+            queue.finish(&job.id, &offline).unwrap();
+        }
+        let capped = queue.jobs()[0].clone();
+        assert_eq!(capped.next_attempt_at - capped.updated_at, MAX_BACKOFF_SECS);
+
+        queue.jobs.lock().unwrap()[0].attempts = MAX_ATTEMPTS - 1;
+        queue.claim(&job.id).unwrap();
+        let failed = queue.finish(&job.id, &offline).unwrap();
+        assert_eq!(failed.state, JobState::Failed);
+        assert_eq!(failed.last_error.as_deref(), Some("Printer is offline"));
+        assert_eq!(failed.data, vec![1]);
+
+        let retried = queue.retry(&job.id).unwrap();
+        assert_eq!((retried.state, retried.attempts), (JobState::Queued, 0));
+    }
+
+    #[test]
+    fn reload_requeues_interrupted_jobs_and_keeps_corrupt_files() {
+        let queue = queue("reload");
+        let job = queue.enqueue("POS-80", &PrinterProfile::default(), vec![7], "Receipt RL0003").unwrap();
+        queue.claim(&job.id).unwrap();
+
+        let reloaded = PrintQueue::load(queue.path.clone());
+        assert_eq!(reloaded.jobs()[0].state, JobState::Queued);
+        assert_eq!(reloaded.jobs()[0].data, vec![7]);
+
+        fs::write(&queue.path, "[{\"id\": ").unwrap();
+        let recovered = PrintQueue::load(queue.path.clone());
+        assert!(recovered.jobs().is_empty());
+        assert!(!queue.path.exists());
+
+        let dir = queue.path.parent().unwrap();
+        let name = format!("{}.corrupt-", queue.path.file_name().unwrap().to_string_lossy());
+        let aside: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().starts_with(&name))
+            .map(|e| e.path())
+            .collect();
+        assert_eq!(aside.len(), 1);
+        assert_eq!(fs::read_to_string(&aside[0]).unwrap(), "[{\"id\": ");
+        let _ = fs::remove_file(&aside[0]);
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::escpos::PrinterProfile;
+use crate::util::{now_secs, read_json, write_json};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreferredPrinter {
+    pub printer_name: String,
+    #[serde(default)]
+    pub profile: PrinterProfile,
+    pub updated_at: u64,
+}
+
+/// The printer each booth prints to, kept in `printer_preferences.json` so a
+/// booth PC shared between gates picks the right one after a restart.
+pub struct PrinterPreferences {
+    path: PathBuf,
+    booths: Mutex<BTreeMap<String, PreferredPrinter>>,
+}
+
+impl PrinterPreferences {
+    pub fn load(path: PathBuf) -> Self {
+        let booths = read_json(&path).unwrap_or_else(|e| {
+            println!("[Rust] Printer preferences not loaded: {}", e);
+            BTreeMap::new()
+        });
+
+        Self {
+            path,
+            booths: Mutex::new(booths),
+        }
+    }
+
+    pub fn get(&self, booth_id: &str) -> Option<PreferredPrinter> {
+        self.booths.lock().unwrap().get(booth_id).cloned()
+    }
+
+    pub fn set(&self, booth_id: &str, printer_name: &str, profile: PrinterProfile) -> Result<PreferredPrinter, String> {
+        let preferred = PreferredPrinter {
+            printer_name: printer_name.to_string(),
+            profile,
+            updated_at: now_secs(),
+        };
+
+        let mut booths = self.booths.lock().unwrap();
+        booths.insert(booth_id.to_string(), preferred.clone());
+        write_json(&self.path, &*booths)?;
+
+        println!("[Rust] Booth {} now prints to {}", booth_id, printer_name);
+        Ok(preferred)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn remembers_a_printer_per_booth_across_restarts() {
+        let path = std::env::temp_dir().join(format!("printer-preferences-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let preferences = PrinterPreferences::load(path.clone());
+        assert!(preferences.get("booth-1").is_none());
+        let profile = PrinterProfile {
+            dot_width: 384,
+            ..Default::default()
+        };
+        preferences.set("booth-1", "POS-58", profile).unwrap();
+        preferences.set("booth-2", "tcp://192.168.1.50", PrinterProfile::default()).unwrap();
+        preferences.set("booth-1", "serial://COM3", PrinterProfile::default()).unwrap();
+
+        let reloaded = PrinterPreferences::load(path.clone());
+        assert_eq!(reloaded.get("booth-1").unwrap().printer_name, "serial://COM3");
+        assert_eq!(reloaded.get("booth-1").unwrap().profile.dot_width, 576);
+        assert_eq!(reloaded.get("booth-2").unwrap().printer_name, "tcp://192.168.1.50");
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,276 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use chrono::{Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::util::{local_day_start, normalize_plate, now_secs, read_json, write_json};
+
+/// Receipts older than this are dropped from the local history.
+const RETENTION_DAYS: u64 = 180;
+const DEFAULT_SEARCH_LIMIT: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reprint {
+    pub operator_name: String,
+    pub printer_name: String,
+    pub reprinted_at: u64,
+}
+
+/// A receipt as it was first printed. The ESC/POS bytes live next to the
+/// index in `<sha256>.bin`, so the index stays small enough to rewrite.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReceiptRecord {
+    pub receipt_number: String,
+    pub plate_number: Option<String>,
+    pub receipt_data: serde_json::Value,
+    pub printer_name: String,
+    pub printed_at: u64,
+    pub sha256: String,
+    pub size: usize,
+    #[serde(default)]
+    pub reprints: Vec<Reprint>,
+}
+
+/// Filters for `search`. Dates are local `YYYY-MM-DD`, both ends inclusive.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReceiptQuery {
+    pub receipt_number: Option<String>,
+    pub plate_number: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// Printed receipts kept on the desktop so disputes can be settled at the
+/// booth without the backend.
+pub struct ReceiptHistory {
+    dir: PathBuf,
+    records: Mutex<Vec<ReceiptRecord>>,
+}
+
+/// Local wall clock time as printed on receipts.
+pub fn format_local(secs: u64) -> String {
+    Local
+        .timestamp_opt(secs as i64, 0)
+        .single()
+        .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl ReceiptHistory {
+    /// Loads the index. One that can't be read stops the app instead of
+    /// starting an empty history that would orphan every stored receipt.
+    pub fn load(dir: PathBuf) -> Result<Self, String> {
+        if let Err(e) = fs::create_dir_all(&dir) {
+            println!("[Rust] Failed to create receipt history folder: {}", e);
+        }
+
+        let records: Vec<ReceiptRecord> = read_json(&dir.join("index.json"))
+            .map_err(|e| format!("Receipt history not loaded: {}", e))?;
+
+        println!("[Rust] Receipt history loaded: {} receipt(s)", records.len());
+
+        Ok(Self {
+            dir,
+            records: Mutex::new(records),
+        })
+    }
+
+    fn bytes_path(&self, sha256: &str) -> PathBuf {
+        self.dir.join(format!("{}.bin", sha256))
+    }
+
+    fn save(&self, records: &mut Vec<ReceiptRecord>) -> Result<(), String> {
+        let cutoff = now_secs().saturating_sub(RETENTION_DAYS * 24 * 60 * 60);
+        let (expired, kept): (Vec<_>, Vec<_>) = records.drain(..).partition(|r| r.printed_at < cutoff);
+        *records = kept;
+
+        for old in expired {
+            if !records.iter().any(|r| r.sha256 == old.sha256) {
+                let _ = fs::remove_file(self.bytes_path(&old.sha256));
+            }
+        }
+
+        write_json(&self.dir.join("index.json"), records)
+    }
+
+    /// Stores a rendered receipt, and says whether its number is new. Keeps
+    /// the first print of each receipt number; printing the same number again
+    /// (a retry from the frontend) doesn't replace the original.
+    pub fn record(
+        &self,
+        receipt_number: &str,
+        receipt_data: &serde_json::Value,
+        printer_name: &str,
+        escpos: &[u8],
+    ) -> Result<bool, String> {
+        let mut records = self.records.lock().unwrap();
+        if records.iter().any(|r| r.receipt_number == receipt_number) {
+            return Ok(false);
+        }
+
+        let sha256 = sha256_hex(escpos);
+        fs::write(self.bytes_path(&sha256), escpos)
+            .map_err(|e| format!("Failed to store receipt {}: {}", receipt_number, e))?;
+
+        records.push(ReceiptRecord {
+            receipt_number: receipt_number.to_string(),
+            plate_number: receipt_data
+                .get("plate_number")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string()),
+            receipt_data: receipt_data.clone(),
+            printer_name: printer_name.to_string(),
+            printed_at: now_secs(),
+            sha256,
+            size: escpos.len(),
+            reprints: Vec::new(),
+        });
+        self.save(&mut records).map(|_| true)
+    }
+
+    /// Matching receipts, newest first.
+    pub fn search(&self, query: &ReceiptQuery) -> Result<Vec<ReceiptRecord>, String> {
+        let from = query.from.as_deref().map(local_day_start).transpose()?;
+        let to = query
+            .to
+            .as_deref()
+            .map(|d| local_day_start(d).map(|start| start + 24 * 60 * 60))
+            .transpose()?;
+        let number = query.receipt_number.as_ref().map(|n| n.trim().to_uppercase());
+        let plate = query.plate_number.as_deref().map(normalize_plate);
+
+        let records = self.records.lock().unwrap();
+        Ok(records
+            .iter()
+            .rev()
+            .filter(|r| number.as_ref().map_or(true, |n| r.receipt_number.to_uppercase().contains(n)))
+            .filter(|r| {
+                plate.as_ref().map_or(true, |p| {
+                    r.plate_number
+                        .as_deref()
+                        .is_some_and(|rp| normalize_plate(rp).contains(p))
+                })
+            })
+            .filter(|r| from.map_or(true, |f| r.printed_at >= f))
+            .filter(|r| to.map_or(true, |t| r.printed_at < t))
+            .take(query.limit.unwrap_or(DEFAULT_SEARCH_LIMIT))
+            .cloned()
+            .collect())
+    }
+
+    pub fn get(&self, receipt_number: &str) -> Result<ReceiptRecord, String> {
+        self.records
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|r| r.receipt_number == receipt_number)
+            .cloned()
+            .ok_or_else(|| format!("Receipt {} is not in the local history", receipt_number))
+    }
+
+    /// The exact bytes sent to the printer, checked against the stored hash.
+    pub fn escpos(&self, record: &ReceiptRecord) -> Result<Vec<u8>, String> {
+        let bytes = fs::read(self.bytes_path(&record.sha256))
+            .map_err(|e| format!("Failed to read receipt {}: {}", record.receipt_number, e))?;
+
+        if sha256_hex(&bytes) != record.sha256 {
+            return Err(format!(
+                "Receipt {} does not match its stored hash",
+                record.receipt_number
+            ));
+        }
+        Ok(bytes)
+    }
+
+    /// Logs a reprint against the operator and returns the record with the
+    /// new reprint last.
+    pub fn log_reprint(&self, receipt_number: &str, operator_name: &str, printer_name: &str) -> Result<ReceiptRecord, String> {
+        let mut records = self.records.lock().unwrap();
+        let record = records
+            .iter_mut()
+            .find(|r| r.receipt_number == receipt_number)
+            .ok_or_else(|| format!("Receipt {} is not in the local history", receipt_number))?;
+
+        record.reprints.push(Reprint {
+            operator_name: operator_name.to_string(),
+            printer_name: printer_name.to_string(),
+            reprinted_at: now_secs(),
+        });
+        let updated = record.clone();
+
+        self.save(&mut records)?;
+        Ok(updated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_store;
+    use serde_json::json;
+
+    fn history(name: &str) -> ReceiptHistory {
+        test_store(&format!("receipt-history-{}", name), ReceiptHistory::load)
+    }
+
+    fn print(history: &ReceiptHistory, number: &str, plate: &str) {
+        let data = json!({ "receipt_number": number, "plate_number": plate });
+        assert!(history.record(number, &data, "POS-80", number.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn search_filters_and_orders_newest_first() {
+        let history = history("search");
+        print(&history, "RL0001", "T 123 ABC");
+        print(&history, "RL0002", "T 456 DEF");
+        print(&history, "RL0003", "t123abc");
+        assert!(!history.record("RL0003", &json!({}), "POS-80", b"retry").unwrap());
+        history.records.lock().unwrap()[0].printed_at = local_day_start("2024-05-01").unwrap() + 60;
+
+        let by_plate = history.search(&ReceiptQuery { plate_number: Some("T123 ABC".into()), ..Default::default() }).unwrap();
+        let numbers: Vec<_> = by_plate.iter().map(|r| r.receipt_number.as_str()).collect();
+        assert_eq!(numbers, ["RL0003", "RL0001"]);
+
+        let by_number = history.search(&ReceiptQuery { receipt_number: Some("rl0002".into()), ..Default::default() }).unwrap();
+        assert_eq!(by_number.len(), 1);
+
+        let on_day = ReceiptQuery { from: Some("2024-05-01".into()), to: Some("2024-05-01".into()), ..Default::default() };
+        assert_eq!(history.search(&on_day).unwrap()[0].receipt_number, "RL0001");
+        assert_eq!(history.search(&ReceiptQuery { limit: Some(2), ..Default::default() }).unwrap().len(), 2);
+        assert!(history.search(&ReceiptQuery { from: Some("01/05/2024".into()), ..Default::default() }).is_err());
+    }
+
+    #[test]
+    fn old_receipts_are_pruned_with_their_bytes() {
+        let history = history("prune");
+        print(&history, "RL0001", "T 123 ABC");
+        let old = history.get("RL0001").unwrap();
+        history.records.lock().unwrap()[0].printed_at = now_secs() - (RETENTION_DAYS + 1) * 24 * 60 * 60;
+
+        print(&history, "RL0002", "T 456 DEF");
+        assert!(history.get("RL0001").is_err());
+        assert!(!history.bytes_path(&old.sha256).exists());
+        let kept = history.get("RL0002").unwrap();
+        assert_eq!(history.escpos(&kept).unwrap(), b"RL0002".to_vec());
+    }
+
+    #[test]
+    fn corrupt_index_is_refused() {
+        let history = history("corrupt");
+        print(&history, "RL0001", "T 123 ABC");
+        fs::write(history.dir.join("index.json"), "[{").unwrap();
+
+        let error = ReceiptHistory::load(history.dir.clone()).err().unwrap();
+        assert!(error.contains("is corrupt"));
+        assert!(!history.dir.join("index.json").exists());
+    }
+}
@@ -2,6 +2,9 @@ use serde::{Deserialize, Serialize};
 use serialport::SerialPortType;
 use std::io::Write;
 use std::time::Duration;
+use tauri::AppHandle;
+
+use crate::sync_queue;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PortInfo {
@@ -48,7 +51,7 @@ pub fn list_serial_ports() -> Result<Vec<PortInfo>, String> {
 }
 
 #[tauri::command]
-pub fn open_gate_all_ports(command: String) -> Result<GateResponse, String> {
+pub fn open_gate_all_ports(app: AppHandle, command: String) -> Result<GateResponse, String> {
     println!("[Rust] Opening gate on ALL ports with command: '{}'", command);
     
     let ports = serialport::available_ports()
@@ -102,6 +105,7 @@ pub fn open_gate_all_ports(command: String) -> Result<GateResponse, String> {
     if any_success {
         let msg = format!("Gate command '{}' sent successfully", command);
         println!("[Rust] SUCCESS: {}", msg);
+        sync_queue::record_gate_opening(&app, successful_port.as_deref(), &command);
         
         Ok(GateResponse {
             success: true,
@@ -120,7 +124,7 @@ pub fn open_gate_all_ports(command: String) -> Result<GateResponse, String> {
 }
 
 #[tauri::command]
-pub fn open_gate_specific_port(port_name: String, command: String) -> Result<GateResponse, String> {
+pub fn open_gate_specific_port(app: AppHandle, port_name: String, command: String) -> Result<GateResponse, String> {
     println!("[Rust] Opening gate on SPECIFIC port '{}' with command: '{}'", port_name, command);
     
     match serialport::new(&port_name, 9600)
@@ -138,6 +142,7 @@ pub fn open_gate_specific_port(port_name: String, command: String) -> Result<Gat
                 .map_err(|e| format!("Failed to flush {}: {}", port_name, e))?;
 
             println!("[Rust] ✓ Gate command sent to {}", port_name);
+            sync_queue::record_gate_opening(&app, Some(&port_name), &command);
 
             Ok(GateResponse {
                 success: true,
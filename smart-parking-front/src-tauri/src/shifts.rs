@@ -0,0 +1,381 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::fiscal::vfd::parse_amount;
+use crate::util::{now_secs, read_json, write_json};
+
+/// Closed shifts kept on the desktop.
+const KEEP_CLOSED: usize = 500;
+
+static SHIFT_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// A receipt taken during a shift.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShiftSale {
+    pub receipt_number: String,
+    pub amount: f64,
+    pub payment_method: String,
+    pub vehicle_type: String,
+    pub at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Shift {
+    pub id: String,
+    pub booth_id: String,
+    pub operator_name: String,
+    pub opening_float: f64,
+    pub opened_at: u64,
+    pub closed_at: Option<u64>,
+    /// Cash counted in the drawer at close.
+    pub counted_cash: Option<f64>,
+    /// Running number of the Z report that closed the shift.
+    pub z_number: Option<u64>,
+    pub x_reports: u32,
+    pub notes: Option<String>,
+    pub sales: Vec<ShiftSale>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Tally {
+    pub count: usize,
+    pub amount: f64,
+}
+
+/// Shift figures as printed on the X and Z reports.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShiftSummary {
+    pub shift: Shift,
+    pub receipts: usize,
+    pub total: f64,
+    pub by_payment_method: BTreeMap<String, Tally>,
+    pub by_vehicle_type: BTreeMap<String, Tally>,
+    /// Opening float plus cash takings.
+    pub expected_cash: f64,
+    /// Counted minus expected; negative when the drawer is short.
+    pub variance: Option<f64>,
+}
+
+impl ShiftSummary {
+    fn new(shift: &Shift) -> Self {
+        let mut by_payment_method: BTreeMap<String, Tally> = BTreeMap::new();
+        let mut by_vehicle_type: BTreeMap<String, Tally> = BTreeMap::new();
+        for sale in &shift.sales {
+            for (map, key) in [
+                (&mut by_payment_method, &sale.payment_method),
+                (&mut by_vehicle_type, &sale.vehicle_type),
+            ] {
+                let tally = map.entry(key.clone()).or_default();
+                tally.count += 1;
+                tally.amount += sale.amount;
+            }
+        }
+
+        let cash = by_payment_method.get("cash").map_or(0.0, |t| t.amount);
+        let expected_cash = shift.opening_float + cash;
+
+        Self {
+            shift: shift.clone(),
+            receipts: shift.sales.len(),
+            total: shift.sales.iter().map(|s| s.amount).sum(),
+            by_payment_method,
+            by_vehicle_type,
+            expected_cash,
+            variance: shift.counted_cash.map(|counted| counted - expected_cash),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ShiftLedger {
+    /// Last Z report number issued on this desktop.
+    z_counter: u64,
+    shifts: Vec<Shift>,
+}
+
+/// Whole shillings with thousands separators, as amounts are printed.
+pub fn format_tzs(amount: f64) -> String {
+    let rounded = amount.round() as i64;
+    let digits = rounded.unsigned_abs().to_string();
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    if rounded < 0 {
+        out.insert(0, '-');
+    }
+    out
+}
+
+/// Operator shifts and their takings, kept in `shifts.json`. Each booth has
+/// at most one open shift.
+pub struct Shifts {
+    path: PathBuf,
+    ledger: Mutex<ShiftLedger>,
+}
+
+impl Shifts {
+    pub fn load(path: PathBuf) -> Result<Self, String> {
+        let ledger: ShiftLedger = read_json(&path)?;
+        let open = ledger.shifts.iter().filter(|s| s.closed_at.is_none()).count();
+        println!("[Rust] Shifts loaded: {} open", open);
+
+        Ok(Self {
+            path,
+            ledger: Mutex::new(ledger),
+        })
+    }
+
+    fn save(&self, ledger: &mut ShiftLedger) -> Result<(), String> {
+        let closed = ledger.shifts.iter().filter(|s| s.closed_at.is_some()).count();
+        let mut excess = closed.saturating_sub(KEEP_CLOSED);
+        ledger.shifts.retain(|s| {
+            if excess > 0 && s.closed_at.is_some() {
+                excess -= 1;
+                false
+            } else {
+                true
+            }
+        });
+
+        write_json(&self.path, &*ledger)
+    }
+
+    pub fn open(&self, booth_id: &str, operator_name: &str, opening_float: f64) -> Result<Shift, String> {
+        if opening_float < 0.0 {
+            return Err("Opening float can't be negative".to_string());
+        }
+
+        let mut ledger = self.ledger.lock().unwrap();
+        if let Some(open) = ledger.shifts.iter().find(|s| s.booth_id == booth_id && s.closed_at.is_none()) {
+            return Err(format!(
+                "Booth {} already has a shift open by {}",
+                booth_id, open.operator_name
+            ));
+        }
+
+        let now = now_secs();
+        let shift = Shift {
+            id: format!("SHIFT-{}-{}-{}", booth_id, now, SHIFT_COUNTER.fetch_add(1, Ordering::Relaxed)),
+            booth_id: booth_id.to_string(),
+            operator_name: operator_name.to_string(),
+            opening_float,
+            opened_at: now,
+            closed_at: None,
+            counted_cash: None,
+            z_number: None,
+            x_reports: 0,
+            notes: None,
+            sales: Vec::new(),
+        };
+        ledger.shifts.push(shift.clone());
+        self.save(&mut ledger)?;
+
+        println!("[Rust] Shift {} opened by {} with float {}", shift.id, operator_name, opening_float);
+        Ok(shift)
+    }
+
+    pub fn current(&self, booth_id: &str) -> Option<ShiftSummary> {
+        let ledger = self.ledger.lock().unwrap();
+        ledger
+            .shifts
+            .iter()
+            .find(|s| s.booth_id == booth_id && s.closed_at.is_none())
+            .map(ShiftSummary::new)
+    }
+
+    pub fn summary(&self, shift_id: &str) -> Result<ShiftSummary, String> {
+        let ledger = self.ledger.lock().unwrap();
+        ledger
+            .shifts
+            .iter()
+            .find(|s| s.id == shift_id)
+            .map(ShiftSummary::new)
+            .ok_or_else(|| format!("Shift {} not found", shift_id))
+    }
+
+    /// Most recent first.
+    pub fn list(&self, booth_id: Option<&str>, limit: usize) -> Vec<Shift> {
+        let ledger = self.ledger.lock().unwrap();
+        ledger
+            .shifts
+            .iter()
+            .rev()
+            .filter(|s| booth_id.map_or(true, |b| s.booth_id == b))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// Adds a printed receipt to a shift: the one given, or else the open
+    /// shift of the booth that printed it. A receipt is only counted once, so
+    /// a retried print doesn't inflate the takings.
+    pub fn record_sale(
+        &self,
+        shift_id: Option<&str>,
+        booth_id: Option<&str>,
+        receipt_number: &str,
+        data: &serde_json::Value,
+    ) -> Result<(), String> {
+        let mut ledger = self.ledger.lock().unwrap();
+        let shift = match (shift_id, booth_id) {
+            (Some(id), _) => ledger.shifts.iter_mut().find(|s| s.id == id),
+            (None, Some(booth)) => ledger.shifts.iter_mut().find(|s| s.booth_id == booth && s.closed_at.is_none()),
+            // Another booth's shift must never collect these takings
+            (None, None) => None,
+        }
+        .ok_or_else(|| format!("Receipt {} taken with no shift open", receipt_number))?;
+
+        if shift.closed_at.is_some() {
+            return Err(format!("Shift {} is closed", shift.id));
+        }
+        if shift.sales.iter().any(|s| s.receipt_number == receipt_number) {
+            return Ok(());
+        }
+
+        let str_field = |key: &str| data.get(key).and_then(|v| v.as_str());
+        shift.sales.push(ShiftSale {
+            receipt_number: receipt_number.to_string(),
+            amount: str_field("item_amount")
+                .or_else(|| str_field("total_amount"))
+                .and_then(parse_amount)
+                .unwrap_or(0.0),
+            // Receipts without a method are cash, as in fiscalisation
+            payment_method: str_field("payment_method")
+                .map(|m| m.trim().to_lowercase())
+                .filter(|m| !m.is_empty())
+                .unwrap_or_else(|| "cash".to_string()),
+            vehicle_type: str_field("vehicle_type").unwrap_or("-").to_string(),
+            at: now_secs(),
+        });
+        self.save(&mut ledger)
+    }
+
+    /// Counts an interim X report against the shift and returns its figures.
+    pub fn x_report(&self, shift_id: &str) -> Result<ShiftSummary, String> {
+        let mut ledger = self.ledger.lock().unwrap();
+        let shift = ledger
+            .shifts
+            .iter_mut()
+            .find(|s| s.id == shift_id)
+            .ok_or_else(|| format!("Shift {} not found", shift_id))?;
+        if shift.closed_at.is_some() {
+            return Err(format!("Shift {} is closed; reprint its Z report instead", shift_id));
+        }
+        shift.x_reports += 1;
+        let summary = ShiftSummary::new(shift);
+        self.save(&mut ledger)?;
+        Ok(summary)
+    }
+
+    /// Closes the shift with the cash counted in the drawer and gives it the
+    /// next Z number.
+    pub fn close(&self, shift_id: &str, counted_cash: f64, notes: Option<String>) -> Result<ShiftSummary, String> {
+        if counted_cash < 0.0 {
+            return Err("Counted cash can't be negative".to_string());
+        }
+
+        let mut ledger = self.ledger.lock().unwrap();
+        let z_number = ledger.z_counter + 1;
+        let shift = ledger
+            .shifts
+            .iter_mut()
+            .find(|s| s.id == shift_id)
+            .ok_or_else(|| format!("Shift {} not found", shift_id))?;
+        if shift.closed_at.is_some() {
+            return Err(format!("Shift {} is already closed", shift_id));
+        }
+
+        shift.closed_at = Some(now_secs());
+        shift.counted_cash = Some(counted_cash);
+        shift.z_number = Some(z_number);
+        shift.notes = notes;
+        let summary = ShiftSummary::new(shift);
+        ledger.z_counter = z_number;
+        self.save(&mut ledger)?;
+
+        println!(
+            "[Rust] Shift {} closed with Z {}, variance {}",
+            shift_id,
+            z_number,
+            summary.variance.unwrap_or_default()
+        );
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn shifts(name: &str) -> Shifts {
+        let path = std::env::temp_dir().join(format!("shifts-{}-{}.json", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        Shifts::load(path).unwrap()
+    }
+
+    fn sale(amount: &str, method: Option<&str>, vehicle: &str) -> serde_json::Value {
+        serde_json::json!({ "total_amount": amount, "payment_method": method, "vehicle_type": vehicle })
+    }
+
+    #[test]
+    fn totals_and_variance_by_method_and_vehicle() {
+        let shifts = shifts("totals");
+        let shift = shifts.open("B1", "Asha", 10_000.0).unwrap();
+        assert!(shifts.open("B1", "Juma", 0.0).is_err());
+
+        shifts.record_sale(None, Some("B1"), "R1", &sale("2,000", None, "Bus")).unwrap();
+        shifts.record_sale(None, Some("B1"), "R1", &sale("2,000", None, "Bus")).unwrap();
+        shifts.record_sale(None, Some("B1"), "R2", &sale("1,000", Some("Cash"), "Car")).unwrap();
+        shifts.record_sale(Some(&shift.id), None, "R3", &sale("3,000", Some("tigopesa"), "Bus")).unwrap();
+
+        let x = shifts.x_report(&shift.id).unwrap();
+        assert_eq!(x.shift.x_reports, 1);
+        assert_eq!((x.receipts, x.total), (3, 6_000.0));
+        assert_eq!(x.by_payment_method["cash"], Tally { count: 2, amount: 3_000.0 });
+        assert_eq!(x.by_vehicle_type["Bus"], Tally { count: 2, amount: 5_000.0 });
+        assert_eq!(x.expected_cash, 13_000.0);
+        assert_eq!(x.variance, None);
+
+        assert!(shifts.close(&shift.id, -1.0, None).is_err());
+        let z = shifts.close(&shift.id, 12_500.0, None).unwrap();
+        assert_eq!(z.shift.z_number, Some(1));
+        assert_eq!(z.variance, Some(-500.0));
+        assert!(shifts.record_sale(Some(&shift.id), None, "R4", &sale("500", None, "Car")).is_err());
+        assert!(shifts.x_report(&shift.id).is_err());
+        assert!(shifts.current("B1").is_none());
+
+        let next = shifts.open("B1", "Juma", 0.0).unwrap();
+        assert_eq!(shifts.close(&next.id, 0.0, None).unwrap().shift.z_number, Some(2));
+    }
+
+    #[test]
+    fn sales_without_a_shift_stay_at_their_booth() {
+        let shifts = shifts("booths");
+        let b1 = shifts.open("B1", "Asha", 0.0).unwrap();
+        let b2 = shifts.open("B2", "Juma", 0.0).unwrap();
+
+        shifts.record_sale(None, Some("B1"), "R1", &sale("1,000", None, "Car")).unwrap();
+        assert!(shifts.record_sale(None, None, "R2", &sale("1,000", None, "Car")).is_err());
+        assert!(shifts.record_sale(None, Some("B3"), "R3", &sale("1,000", None, "Car")).is_err());
+
+        assert_eq!(shifts.summary(&b1.id).unwrap().receipts, 1);
+        assert_eq!(shifts.summary(&b2.id).unwrap().receipts, 0);
+    }
+
+    #[test]
+    fn amounts_print_with_thousands_separators() {
+        assert_eq!(format_tzs(0.0), "0");
+        assert_eq!(format_tzs(999.0), "999");
+        assert_eq!(format_tzs(1_234_567.4), "1,234,567");
+        assert_eq!(format_tzs(-500.0), "-500");
+    }
+}
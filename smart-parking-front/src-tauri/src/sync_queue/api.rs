@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+use serde_json::json;
+
+use super::{SyncConfig, SyncEvent};
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// What the API made of one event.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    /// Stored, with the API's id for the record when it sends one.
+    Synced(Option<i64>),
+    /// The API holds a different version of the record and refused this one.
+    Conflict(String),
+    /// Try again later. `answered` is false when the API was not reached.
+    Retry { error: String, answered: bool },
+}
+
+/// Posts queued events to the Laravel API.
+pub struct SyncClient {
+    url: String,
+    token: String,
+    booth_id: String,
+    agent: ureq::Agent,
+}
+
+impl SyncClient {
+    pub fn new(config: &SyncConfig) -> Self {
+        Self {
+            url: format!("{}{}", config.api_url.trim_end_matches('/'), config.sync_path),
+            token: config.api_token.clone(),
+            booth_id: config.booth_id.clone(),
+            agent: ureq::AgentBuilder::new().timeout(HTTP_TIMEOUT).build(),
+        }
+    }
+
+    pub fn send(&self, event: &SyncEvent) -> Outcome {
+        let body = json!({
+            "idempotency_key": event.idempotency_key,
+            "booth_id": self.booth_id,
+            "entity_type": event.entity_type,
+            "entity_id": event.entity_id,
+            "action": event.action,
+            "data": event.data,
+            "occurred_at": event.created_at,
+        });
+
+        let mut request = self
+            .agent
+            .post(&self.url)
+            .set("Accept", "application/json")
+            // The API answers a repeated key with the original result
+            .set("Idempotency-Key", &event.idempotency_key);
+        if !self.token.is_empty() {
+            request = request.set("Authorization", &format!("Bearer {}", self.token));
+        }
+
+        match request.send_json(body) {
+            Ok(r) => classify(r.status(), &r.into_string().unwrap_or_default()),
+            Err(ureq::Error::Status(code, r)) => classify(code, &r.into_string().unwrap_or_default()),
+            Err(e) => Outcome::Retry {
+                error: format!("API unreachable: {}", e),
+                answered: false,
+            },
+        }
+    }
+}
+
+/// Reads a Laravel response. 409 and 422 mean the API will never take this
+/// event as it is; anything else unsuccessful may clear up on its own.
+pub fn classify(status: u16, body: &str) -> Outcome {
+    let json: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+    let message = json
+        .get("message")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| body.chars().take(200).collect());
+
+    match status {
+        200..=299 => {
+            let data = json.get("data").unwrap_or(&json);
+            Outcome::Synced(data.get("id").and_then(|v| v.as_i64()))
+        }
+        409 | 422 => Outcome::Conflict(format!("HTTP {}: {}", status, message)),
+        _ => Outcome::Retry {
+            error: format!("HTTP {}: {}", status, message),
+            answered: true,
+        },
+    }
+}
@@ -0,0 +1,746 @@
+pub mod api;
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::database::{install_id, new_local_id, sql, Database};
+use crate::util::now_secs;
+use api::{Outcome, SyncClient};
+
+/// Event emitted with a `SyncStatus` when the queue grows or a replay runs.
+pub const SYNC_STATUS_EVENT: &str = "sync-status-updated";
+/// Event emitted with a `SyncEvent` the API refused or kept failing on.
+pub const SYNC_ISSUE_EVENT: &str = "sync-issue";
+
+/// Entries, exits and cancellations, keyed by the session's `local_id`.
+pub const ENTITY_PASSAGE: &str = "vehicle_passage";
+pub const ENTITY_PAYMENT: &str = "payment";
+pub const ENTITY_RECEIPT: &str = "receipt";
+pub const ENTITY_GATE_OPENING: &str = "gate_opening";
+
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+const MAX_BACKOFF_SECS: u64 = 15 * 60;
+/// Tries before an event the API keeps failing on is set aside, so the
+/// events behind it can go.
+const MAX_ATTEMPTS: u32 = 10;
+const CONFIG_KEY: &str = "sync";
+const LAST_SYNCED_KEY: &str = "sync_last_synced_at";
+
+/// Where the queue is replayed to, kept under `sync` in the config table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncConfig {
+    pub enabled: bool,
+    /// Base URL of smart-parking-api.
+    pub api_url: String,
+    pub sync_path: String,
+    /// Where the tariff rules for offline fees are fetched from.
+    pub tariffs_path: String,
+    /// Where the permit, staff, exempt and blacklisted plates are fetched from.
+    pub plate_lists_path: String,
+    /// Sanctum token of the booth's API user.
+    pub api_token: String,
+    pub booth_id: String,
+    /// Events read from the queue at a time.
+    pub batch_size: usize,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            api_url: "http://127.0.0.1:8000".to_string(),
+            sync_path: "/api/toll-v1/desktop-sync".to_string(),
+            tariffs_path: "/api/toll-v1/desktop-sync/tariffs".to_string(),
+            plate_lists_path: "/api/toll-v1/desktop-sync/plate-lists".to_string(),
+            api_token: String::new(),
+            booth_id: String::new(),
+            batch_size: 50,
+        }
+    }
+}
+
+/// Blanks the API token in a raw config value read for the webview.
+pub(crate) fn hide_token(key: &str, value: &mut serde_json::Value) {
+    if let (CONFIG_KEY, Some(token)) = (key, value.get_mut("api_token")) {
+        *token = String::new().into();
+    }
+}
+
+/// Keeps the saved API token when a raw config value comes back without one.
+pub(crate) fn keep_token(db: &Database, key: &str, value: &mut serde_json::Value) -> Result<(), String> {
+    if key != CONFIG_KEY || value.get("api_token").and_then(|t| t.as_str()).is_some_and(|t| !t.is_empty()) {
+        return Ok(());
+    }
+    let saved = db.get_config(CONFIG_KEY)?.and_then(|v| v.get("api_token").cloned());
+    if let (Some(fields), Some(saved)) = (value.as_object_mut(), saved) {
+        fields.insert("api_token".into(), saved);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventStatus {
+    Pending,
+    /// Refused by the API; needs someone to look at it.
+    Conflict,
+    /// Gave up after `MAX_ATTEMPTS`.
+    Failed,
+}
+
+impl EventStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            EventStatus::Pending => "pending",
+            EventStatus::Conflict => "conflict",
+            EventStatus::Failed => "failed",
+        }
+    }
+
+    fn parse(s: &str) -> Self {
+        match s {
+            "conflict" => EventStatus::Conflict,
+            "failed" => EventStatus::Failed,
+            _ => EventStatus::Pending,
+        }
+    }
+}
+
+/// One local change, replayed to the API in the order it happened. Events
+/// are deleted once the API has them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncEvent {
+    pub id: i64,
+    pub entity_type: String,
+    pub entity_id: String,
+    pub action: String,
+    pub data: serde_json::Value,
+    pub idempotency_key: String,
+    pub status: EventStatus,
+    pub sync_attempts: u32,
+    pub last_sync_attempt: Option<u64>,
+    pub next_attempt_at: u64,
+    pub sync_error: Option<String>,
+    pub created_at: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncStatus {
+    pub enabled: bool,
+    /// Whether the API answered the last attempt; unknown until one is made.
+    pub online: Option<bool>,
+    pub pending: usize,
+    pub conflicts: usize,
+    pub failed: usize,
+    pub oldest_pending_at: Option<u64>,
+    pub last_synced_at: Option<u64>,
+    pub last_attempt_at: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct Progress {
+    online: Option<bool>,
+    last_attempt_at: Option<u64>,
+    last_error: Option<String>,
+}
+
+/// What one replay did.
+#[derive(Debug, Default)]
+pub struct Replay {
+    pub attempted: bool,
+    pub synced: usize,
+    /// Events set aside as conflicts or failures.
+    pub issues: Vec<SyncEvent>,
+    online: Option<bool>,
+    error: Option<String>,
+}
+
+fn event_from_row(row: &Row) -> rusqlite::Result<SyncEvent> {
+    let data: String = row.get("data")?;
+    Ok(SyncEvent {
+        id: row.get("id")?,
+        entity_type: row.get("entity_type")?,
+        entity_id: row.get("entity_id")?,
+        action: row.get("action")?,
+        data: serde_json::from_str(&data).unwrap_or(serde_json::Value::String(data)),
+        idempotency_key: row.get("idempotency_key")?,
+        status: EventStatus::parse(&row.get::<_, String>("status")?),
+        sync_attempts: row.get("sync_attempts")?,
+        last_sync_attempt: row.get("last_sync_attempt")?,
+        next_attempt_at: row.get("next_attempt_at")?,
+        sync_error: row.get("sync_error")?,
+        created_at: row.get("created_at")?,
+    })
+}
+
+/// Adds a change to the queue. Takes the connection so callers can queue in
+/// the same transaction as the change itself. Queuing the same action on the
+/// same entity again while the first is still waiting sends the newer data
+/// in its place. The key
+/// starts with this installation's id, since entity ids like receipt numbers
+/// repeat across booths.
+pub(crate) fn enqueue<T: Serialize>(
+    conn: &Connection,
+    entity_type: &str,
+    entity_id: &str,
+    action: &str,
+    data: &T,
+) -> Result<(), String> {
+    let data = serde_json::to_string(data).map_err(|e| format!("Failed to encode {} {}: {}", entity_type, entity_id, e))?;
+    let install_id = install_id(conn)?;
+    sql(
+        conn.execute(
+            "INSERT INTO desktop_sync_queue (entity_type, entity_id, action, data, idempotency_key,
+                 next_attempt_at, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)
+             ON CONFLICT(idempotency_key) DO UPDATE SET data = excluded.data WHERE status = 'pending'",
+            params![
+                entity_type,
+                entity_id,
+                action,
+                data,
+                format!("{}:{}:{}:{}", install_id, entity_type, entity_id, action),
+                now_secs(),
+            ],
+        ),
+        "queue sync event",
+    )
+    .map(|_| ())
+}
+
+fn event_if_exists(conn: &Connection, id: i64) -> rusqlite::Result<Option<SyncEvent>> {
+    conn.query_row("SELECT * FROM desktop_sync_queue WHERE id = ?1", params![id], event_from_row)
+        .optional()
+}
+
+fn event(conn: &Connection, id: i64) -> Result<SyncEvent, String> {
+    sql(event_if_exists(conn, id), "read sync event")?.ok_or_else(|| format!("Sync event {} not found", id))
+}
+
+/// Pending events, oldest first, leaving out those behind a conflict or
+/// failure on the same entity: an exit waits until its entry is sorted out.
+fn next_batch(conn: &Connection, limit: usize) -> Result<Vec<SyncEvent>, String> {
+    let mut stmt = sql(
+        conn.prepare(
+            "SELECT * FROM desktop_sync_queue q WHERE status = 'pending' AND NOT EXISTS (
+                 SELECT 1 FROM desktop_sync_queue b
+                 WHERE b.entity_type = q.entity_type AND b.entity_id = q.entity_id
+                     AND b.status != 'pending' AND b.id < q.id)
+             ORDER BY id LIMIT ?1",
+        ),
+        "read sync queue",
+    )?;
+    let rows = sql(stmt.query_map(params![limit.max(1) as i64], event_from_row), "read sync queue")?;
+    sql(rows.collect(), "read sync queue")
+}
+
+/// Data queued again while the event was being sent stays for the next replay.
+fn mark_synced(conn: &Connection, event: &SyncEvent, server_id: Option<i64>) -> Result<(), String> {
+    let requeued = sql(event_if_exists(conn, event.id), "read sync event")?.is_some_and(|e| e.data != event.data);
+    if !requeued {
+        sql(conn.execute("DELETE FROM desktop_sync_queue WHERE id = ?1", params![event.id]), "clear sync event")?;
+    }
+    if let (ENTITY_PASSAGE, Some(server_id)) = (event.entity_type.as_str(), server_id) {
+        sql(
+            conn.execute(
+                "UPDATE parking_sessions SET server_id = ?2 WHERE local_id = ?1",
+                params![event.entity_id, server_id],
+            ),
+            "store server id",
+        )?;
+    }
+    Ok(())
+}
+
+fn mark_attempt(conn: &Connection, event: &SyncEvent, status: EventStatus, error: &str) -> Result<SyncEvent, String> {
+    let attempts = event.sync_attempts + 1;
+    let backoff = (POLL_INTERVAL.as_secs() << attempts.min(6)).min(MAX_BACKOFF_SECS);
+    let now = now_secs();
+    sql(
+        conn.execute(
+            "UPDATE desktop_sync_queue SET status = ?2, sync_attempts = ?3, last_sync_attempt = ?4,
+                 next_attempt_at = ?5, sync_error = ?6
+             WHERE id = ?1",
+            params![event.id, status.as_str(), attempts, now, now + backoff, error],
+        ),
+        "update sync event",
+    )?;
+    self::event(conn, event.id)
+}
+
+/// Replays the queue to the API, oldest event first.
+#[derive(Default)]
+pub struct SyncQueue {
+    replaying: Mutex<()>,
+    progress: Mutex<Progress>,
+}
+
+impl SyncQueue {
+    pub fn config(&self, db: &Database) -> SyncConfig {
+        match db.get_config(CONFIG_KEY) {
+            Ok(Some(value)) => serde_json::from_value(value).unwrap_or_else(|e| {
+                println!("[Rust] Sync config unreadable, using defaults: {}", e);
+                SyncConfig::default()
+            }),
+            Ok(None) => SyncConfig::default(),
+            Err(e) => {
+                println!("[Rust] {}", e);
+                SyncConfig::default()
+            }
+        }
+    }
+
+    pub fn save_config(&self, db: &Database, config: &SyncConfig) -> Result<(), String> {
+        let value = serde_json::to_value(config).map_err(|e| format!("Failed to encode sync config: {}", e))?;
+        db.set_config(CONFIG_KEY, &value)
+    }
+
+    pub fn status(&self, db: &Database) -> Result<SyncStatus, String> {
+        let mut status = SyncStatus {
+            enabled: self.config(db).enabled,
+            last_synced_at: db.get_config(LAST_SYNCED_KEY)?.and_then(|v| v.as_u64()),
+            ..Default::default()
+        };
+        {
+            let progress = self.progress.lock().unwrap();
+            status.online = progress.online;
+            status.last_attempt_at = progress.last_attempt_at;
+            status.last_error = progress.last_error.clone();
+        }
+
+        let conn = db.lock();
+        let mut stmt = sql(
+            conn.prepare("SELECT status, COUNT(*), MIN(created_at) FROM desktop_sync_queue GROUP BY status"),
+            "count sync queue",
+        )?;
+        let rows = sql(
+            stmt.query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, usize>(1)?, row.get::<_, u64>(2)?))
+            }),
+            "count sync queue",
+        )?;
+        for row in rows {
+            let (state, count, oldest) = sql(row, "count sync queue")?;
+            match EventStatus::parse(&state) {
+                EventStatus::Pending => {
+                    status.pending = count;
+                    status.oldest_pending_at = Some(oldest);
+                }
+                EventStatus::Conflict => status.conflicts = count,
+                EventStatus::Failed => status.failed = count,
+            }
+        }
+        Ok(status)
+    }
+
+    /// Conflicts and failures, oldest first.
+    pub fn issues(&self, db: &Database) -> Result<Vec<SyncEvent>, String> {
+        let conn = db.lock();
+        let mut stmt = sql(
+            conn.prepare("SELECT * FROM desktop_sync_queue WHERE status != 'pending' ORDER BY id"),
+            "read sync issues",
+        )?;
+        let rows = sql(stmt.query_map([], event_from_row), "read sync issues")?;
+        sql(rows.collect(), "read sync issues")
+    }
+
+    /// Puts a conflict or failure back in line, e.g. once the record was fixed
+    /// on the server.
+    pub fn retry(&self, db: &Database, id: i64) -> Result<SyncEvent, String> {
+        let conn = db.lock();
+        let changed = sql(
+            conn.execute(
+                "UPDATE desktop_sync_queue SET status = 'pending', sync_attempts = 0, next_attempt_at = ?2
+                 WHERE id = ?1 AND status != 'pending'",
+                params![id, now_secs()],
+            ),
+            "retry sync event",
+        )?;
+        if changed == 0 {
+            return Err(format!("Sync event {} is not a conflict or failure", id));
+        }
+        event(&conn, id)
+    }
+
+    /// Drops a conflict or failure the server should never get, letting the
+    /// events queued after it for the same entity go.
+    pub fn discard(&self, db: &Database, id: i64) -> Result<SyncEvent, String> {
+        let conn = db.lock();
+        let discarded = event(&conn, id)?;
+        if discarded.status == EventStatus::Pending {
+            return Err(format!("Sync event {} is still waiting to be sent", id));
+        }
+        sql(conn.execute("DELETE FROM desktop_sync_queue WHERE id = ?1", params![id]), "discard sync event")?;
+        println!("[Rust] Sync event {} ({} {}) discarded", id, discarded.entity_type, discarded.action);
+        Ok(discarded)
+    }
+
+    pub fn replay(&self, db: &Database) -> Replay {
+        let Ok(_guard) = self.replaying.try_lock() else {
+            return Replay::default();
+        };
+        let config = self.config(db);
+        if !config.enabled {
+            return Replay::default();
+        }
+        let client = SyncClient::new(&config);
+        self.replay_with(db, config.batch_size, |event| client.send(event))
+    }
+
+    /// Sends events in order until the queue is empty or one has to wait.
+    /// A waiting event holds back everything behind it, and a refused one
+    /// everything behind it for the same entity, so the API never sees an
+    /// exit before its entry.
+    fn replay_with(&self, db: &Database, batch_size: usize, send: impl Fn(&SyncEvent) -> Outcome) -> Replay {
+        let mut replay = Replay::default();
+        if let Err(e) = replay_batches(db, batch_size, &send, &mut replay) {
+            println!("[Rust] Sync stopped: {}", e);
+            replay.error = Some(e);
+        }
+
+        if replay.attempted {
+            let mut progress = self.progress.lock().unwrap();
+            progress.last_attempt_at = Some(now_secs());
+            progress.online = replay.online;
+            progress.last_error = replay.error.clone();
+        }
+        if replay.synced > 0 {
+            println!("[Rust] Synced {} events to the API", replay.synced);
+            if let Err(e) = db.set_config(LAST_SYNCED_KEY, &now_secs().into()) {
+                println!("[Rust] {}", e);
+            }
+        }
+        replay
+    }
+}
+
+fn replay_batches(
+    db: &Database,
+    batch_size: usize,
+    send: &impl Fn(&SyncEvent) -> Outcome,
+    replay: &mut Replay,
+) -> Result<(), String> {
+    loop {
+        let batch = next_batch(&db.lock(), batch_size)?;
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        // Entities refused earlier in this batch; later events for them wait
+        let mut held: Vec<(String, String)> = Vec::new();
+        for event in batch {
+            if held.contains(&(event.entity_type.clone(), event.entity_id.clone())) {
+                continue;
+            }
+            if event.next_attempt_at > now_secs() {
+                return Ok(());
+            }
+            replay.attempted = true;
+
+            // The lock is not held while the API is busy with the event
+            let outcome = send(&event);
+            let conn = db.lock();
+            match outcome {
+                Outcome::Synced(server_id) => {
+                    replay.online = Some(true);
+                    mark_synced(&conn, &event, server_id)?;
+                    replay.synced += 1;
+                }
+                Outcome::Conflict(error) => {
+                    replay.online = Some(true);
+                    println!("[Rust] API refused {} {} {}: {}", event.entity_type, event.action, event.entity_id, error);
+                    replay.issues.push(mark_attempt(&conn, &event, EventStatus::Conflict, &error)?);
+                    held.push((event.entity_type.clone(), event.entity_id.clone()));
+                }
+                Outcome::Retry { error, answered } => {
+                    replay.online = Some(answered);
+                    if answered && event.sync_attempts + 1 >= MAX_ATTEMPTS {
+                        println!("[Rust] Giving up on sync event {}: {}", event.id, error);
+                        replay.issues.push(mark_attempt(&conn, &event, EventStatus::Failed, &error)?);
+                        held.push((event.entity_type.clone(), event.entity_id.clone()));
+                        continue;
+                    }
+                    mark_attempt(&conn, &event, EventStatus::Pending, &error)?;
+                    replay.error = Some(error);
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+pub fn emit_status(app: &AppHandle) {
+    let status = app.state::<SyncQueue>().status(&app.state::<Database>());
+    match status {
+        Ok(status) => {
+            if let Err(e) = app.emit(SYNC_STATUS_EVENT, &status) {
+                println!("[Rust] Failed to emit sync status: {}", e);
+            }
+        }
+        Err(e) => println!("[Rust] {}", e),
+    }
+}
+
+pub fn replay_and_emit(app: &AppHandle) {
+    let replay = app.state::<SyncQueue>().replay(&app.state::<Database>());
+    for event in &replay.issues {
+        if let Err(e) = app.emit(SYNC_ISSUE_EVENT, event) {
+            println!("[Rust] Failed to emit sync issue: {}", e);
+        }
+    }
+    if replay.attempted {
+        emit_status(app);
+    }
+}
+
+/// Replays in the background straight away.
+pub fn sync_soon(app: &AppHandle) {
+    let app = app.clone();
+    thread::spawn(move || replay_and_emit(&app));
+}
+
+/// Call after queuing: updates the queue depth and starts sending.
+pub fn queued(app: &AppHandle) {
+    emit_status(app);
+    sync_soon(app);
+}
+
+/// Queues a change made outside the database module. Never fails the caller:
+/// the change itself already happened.
+pub fn record<T: Serialize>(app: &AppHandle, entity_type: &str, entity_id: &str, action: &str, data: &T) {
+    match record_in(&app.state::<Database>(), entity_type, entity_id, action, data) {
+        Ok(()) => queued(app),
+        Err(e) => println!("[Rust] {} {} not queued for sync: {}", entity_type, entity_id, e),
+    }
+}
+
+/// Queues in a connection of its own. The lock is released before this
+/// returns, since `queued` reads the database again.
+fn record_in<T: Serialize>(db: &Database, entity_type: &str, entity_id: &str, action: &str, data: &T) -> Result<(), String> {
+    let conn = db.lock();
+    enqueue(&conn, entity_type, entity_id, action, data)
+}
+
+/// Queues a gate that was opened, by hand or on payment.
+pub fn record_gate_opening(app: &AppHandle, port: Option<&str>, command: &str) {
+    let data = serde_json::json!({ "port": port, "command": command, "opened_at": now_secs() });
+    record(app, ENTITY_GATE_OPENING, &new_local_id(), "opened", &data);
+}
+
+/// Background thread that replays the queue whenever the API is reachable.
+pub fn start_worker(app: AppHandle) {
+    thread::spawn(move || loop {
+        replay_and_emit(&app);
+        thread::sleep(POLL_INTERVAL);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{ExitRecord, NewEntry};
+    use std::cell::RefCell;
+
+    fn entry(db: &Database, plate: &str) -> crate::database::ParkingSession {
+        db.record_entry(NewEntry {
+            plate_number: plate.to_string(),
+            vehicle_type: None,
+            gate_id: None,
+            operator_name: None,
+            ticket_number: None,
+            entry_time: Some(1_000),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn recorded_changes_leave_the_database_unlocked() {
+        let db = std::sync::Arc::new(Database::open_in_memory().unwrap());
+        let (tx, rx) = std::sync::mpsc::channel();
+        let shared = db.clone();
+        thread::spawn(move || {
+            record_in(&shared, ENTITY_RECEIPT, "R1", "create", &serde_json::json!({})).unwrap();
+            let _ = tx.send(SyncQueue::default().status(&shared).map(|s| s.pending));
+        });
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)).expect("database still locked"), Ok(1));
+    }
+
+    #[test]
+    fn the_api_token_never_leaves_the_desktop() {
+        let db = Database::open_in_memory().unwrap();
+        let queue = SyncQueue::default();
+        let config = SyncConfig {
+            api_token: "secret".to_string(),
+            ..Default::default()
+        };
+        queue.save_config(&db, &config).unwrap();
+
+        let mut raw = db.get_config(CONFIG_KEY).unwrap().unwrap();
+        hide_token(CONFIG_KEY, &mut raw);
+        assert_eq!(raw["api_token"], "");
+
+        // Saving what the webview was shown keeps the token
+        keep_token(&db, CONFIG_KEY, &mut raw).unwrap();
+        db.set_config(CONFIG_KEY, &raw).unwrap();
+        assert_eq!(queue.config(&db).api_token, "secret");
+    }
+
+    #[test]
+    fn laravel_responses_are_classified() {
+        assert_eq!(api::classify(201, r#"{"data":{"id":42}}"#), Outcome::Synced(Some(42)));
+        assert_eq!(api::classify(204, ""), Outcome::Synced(None));
+        assert_eq!(
+            api::classify(409, r#"{"message":"Vehicle already exited"}"#),
+            Outcome::Conflict("HTTP 409: Vehicle already exited".to_string())
+        );
+        assert!(matches!(api::classify(503, "down"), Outcome::Retry { answered: true, .. }));
+    }
+
+    #[test]
+    fn replays_in_order_and_reports_conflicts() {
+        let db = Database::open_in_memory().unwrap();
+        let queue = SyncQueue::default();
+        let parked = entry(&db, "T 100 AAA");
+        db.record_exit(ExitRecord {
+            session_id: parked.id,
+            gate_id: None,
+            operator_name: None,
+            fee: Some(1_000.0),
+            payment_method: Some("cash".to_string()),
+            payment_reference: None,
+            receipt_number: Some("R1".to_string()),
+            exit_time: Some(2_000),
+        })
+        .unwrap();
+        enqueue(&db.lock(), ENTITY_RECEIPT, "R1", "create", &serde_json::json!({ "total_amount": 1000 })).unwrap();
+        enqueue(&db.lock(), ENTITY_RECEIPT, "R1", "create", &serde_json::json!({})).unwrap();
+        assert_eq!(queue.status(&db).unwrap().pending, 3);
+
+        let sent = RefCell::new(Vec::new());
+        let replay = queue.replay_with(&db, 2, |event| {
+            sent.borrow_mut().push(event.idempotency_key.clone());
+            match event.entity_type.as_str() {
+                ENTITY_RECEIPT => Outcome::Conflict("HTTP 409: duplicate receipt".to_string()),
+                _ => Outcome::Synced(Some(77)),
+            }
+        });
+
+        let local_id = &parked.local_id;
+        let install_id = install_id(&db.lock()).unwrap();
+        assert_eq!(install_id.len(), 32);
+        assert_eq!(
+            *sent.borrow(),
+            vec![
+                format!("{}:vehicle_passage:{}:entry", install_id, local_id),
+                format!("{}:vehicle_passage:{}:exit", install_id, local_id),
+                format!("{}:receipt:R1:create", install_id),
+            ]
+        );
+        assert_eq!((replay.synced, replay.issues.len()), (2, 1));
+        assert_eq!(db.get_session(parked.id).unwrap().server_id, Some(77));
+
+        let status = queue.status(&db).unwrap();
+        assert_eq!((status.pending, status.conflicts, status.online), (0, 1, Some(true)));
+        assert!(status.last_synced_at.is_some());
+
+        let conflict = &queue.issues(&db).unwrap()[0];
+        assert_eq!(conflict.sync_error.as_deref(), Some("HTTP 409: duplicate receipt"));
+        queue.retry(&db, conflict.id).unwrap();
+        assert_eq!(queue.status(&db).unwrap().pending, 1);
+    }
+
+    #[test]
+    fn a_refused_event_holds_back_its_entity_only() {
+        let db = Database::open_in_memory().unwrap();
+        let queue = SyncQueue::default();
+        let refused = entry(&db, "T 100 AAA");
+        db.record_exit(ExitRecord {
+            session_id: refused.id,
+            gate_id: None,
+            operator_name: None,
+            fee: Some(1_000.0),
+            payment_method: None,
+            payment_reference: None,
+            receipt_number: None,
+            exit_time: Some(2_000),
+        })
+        .unwrap();
+        let other = entry(&db, "T 200 BBB");
+
+        let sent = RefCell::new(Vec::new());
+        let send = |event: &SyncEvent| {
+            sent.borrow_mut().push((event.entity_id.clone(), event.action.clone()));
+            if event.entity_id == refused.local_id && event.action == "entry" {
+                Outcome::Conflict("HTTP 422: invalid plate".to_string())
+            } else {
+                Outcome::Synced(None)
+            }
+        };
+        let replay = queue.replay_with(&db, 50, send);
+        assert_eq!(
+            *sent.borrow(),
+            vec![(refused.local_id.clone(), "entry".to_string()), (other.local_id.clone(), "entry".to_string())]
+        );
+        assert_eq!((replay.synced, queue.status(&db).unwrap().pending), (1, 1));
+
+        // Once the entry is dealt with, the exit follows
+        queue.discard(&db, replay.issues[0].id).unwrap();
+        queue.replay_with(&db, 50, send);
+        assert_eq!(sent.borrow().last().unwrap(), &(refused.local_id.clone(), "exit".to_string()));
+        assert_eq!(queue.status(&db).unwrap().pending, 0);
+    }
+
+    #[test]
+    fn queuing_again_replaces_the_waiting_data() {
+        let db = Database::open_in_memory().unwrap();
+        let queue = SyncQueue::default();
+        enqueue(&db.lock(), ENTITY_PAYMENT, "PK1", "confirmed", &serde_json::json!({ "amount": 1000 })).unwrap();
+        enqueue(&db.lock(), ENTITY_PAYMENT, "PK1", "confirmed", &serde_json::json!({ "amount": 2000 })).unwrap();
+
+        // A change queued while the API has the event is sent next time round
+        let replay = queue.replay_with(&db, 50, |event| {
+            if event.data["amount"] == 2000 {
+                enqueue(&db.lock(), ENTITY_PAYMENT, "PK1", "confirmed", &serde_json::json!({ "amount": 3000 })).unwrap();
+            }
+            Outcome::Synced(None)
+        });
+        assert_eq!(replay.synced, 2);
+        assert_eq!(queue.status(&db).unwrap().pending, 0);
+    }
+
+    #[test]
+    fn offline_backs_off_and_holds_the_queue() {
+        let db = Database::open_in_memory().unwrap();
+        let queue = SyncQueue::default();
+        entry(&db, "T 200 BBB");
+        entry(&db, "T 300 CCC");
+
+        let calls = RefCell::new(0);
+        let offline = |_: &SyncEvent| {
+            *calls.borrow_mut() += 1;
+            Outcome::Retry {
+                error: "API unreachable".to_string(),
+                answered: false,
+            }
+        };
+        queue.replay_with(&db, 50, offline);
+        // Waiting out the backoff, nothing else is tried
+        queue.replay_with(&db, 50, offline);
+        assert_eq!(*calls.borrow(), 1);
+
+        let status = queue.status(&db).unwrap();
+        assert_eq!((status.pending, status.online), (2, Some(false)));
+        assert_eq!(status.last_error.as_deref(), Some("API unreachable"));
+
+        let head = &next_batch(&db.lock(), 1).unwrap()[0];
+        assert_eq!(head.sync_attempts, 1);
+        assert!(head.next_attempt_at > now_secs());
+    }
+}
@@ -0,0 +1,539 @@
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Deserializer, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::database::{Database, ParkingSession};
+use crate::fiscal::vfd::parse_amount;
+use crate::sync_queue::{SyncConfig, SyncQueue};
+use crate::util::now_secs;
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+/// Longest stay charged here. Anything longer is a wrong clock or a case for
+/// the office, and would only keep the booth busy counting hours.
+const MAX_STAY: u64 = 400 * DAY;
+/// 9999-12-31, past any real exit time.
+const LATEST_TIME: u64 = 253_402_300_799;
+const TOO_LARGE: &str = "Parking fee is too large to charge";
+const CACHE_KEY: &str = "tariffs";
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(20);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Charging {
+    /// First hour, then every started hour after it.
+    #[default]
+    Hourly,
+    /// A flat rate per day, printed as SIKU on receipts.
+    Daily,
+}
+
+/// Where one day of parking ends, for daily rates and daily caps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DayBoundary {
+    /// Every started 24 hours from entry.
+    #[default]
+    Rolling,
+    /// Every calendar date the vehicle was inside on.
+    Midnight,
+}
+
+/// Hours that start inside the window are charged at `hourly_rate`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NightRate {
+    /// Local time, "HH:MM" or "HH:MM:SS". The window may run past midnight.
+    pub start: String,
+    pub end: String,
+    #[serde(deserialize_with = "amount")]
+    pub hourly_rate: i64,
+}
+
+/// One vehicle type's tariff, as served by the API. Amounts are whole TZS.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TariffRule {
+    pub id: Option<i64>,
+    /// Matched ignoring case; "*" or empty applies to any other type.
+    pub vehicle_type: String,
+    pub charging: Charging,
+    #[serde(deserialize_with = "amount")]
+    pub first_hour: i64,
+    #[serde(deserialize_with = "amount")]
+    pub subsequent_hour: i64,
+    /// Most an hourly stay pays per day.
+    #[serde(deserialize_with = "optional_amount")]
+    pub daily_cap: Option<i64>,
+    #[serde(deserialize_with = "amount")]
+    pub day_rate: i64,
+    pub day_boundary: DayBoundary,
+    /// Stays this short are free.
+    pub grace_minutes: u32,
+    pub night: Option<NightRate>,
+    /// The fee is rounded to the nearest multiple, halves up. 0 or 1 leaves
+    /// it as it is.
+    #[serde(deserialize_with = "amount")]
+    pub round_to: i64,
+}
+
+/// The rules last synced from the API, kept under `tariffs` in the config
+/// table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TariffSet {
+    pub version: Option<String>,
+    /// Offset of local time from UTC. Tanzania is UTC+3 all year.
+    pub utc_offset_minutes: i32,
+    pub rules: Vec<TariffRule>,
+    pub synced_at: Option<u64>,
+}
+
+impl Default for TariffSet {
+    fn default() -> Self {
+        Self {
+            version: None,
+            utc_offset_minutes: 180,
+            rules: Vec::new(),
+            synced_at: None,
+        }
+    }
+}
+
+/// What a stay costs and how it was worked out.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Fee {
+    pub amount: i64,
+    pub rule_id: Option<i64>,
+    /// The rule's vehicle type, "*" when the catch-all rule was used.
+    pub vehicle_type: String,
+    pub charging: Charging,
+    pub duration_minutes: u64,
+    pub within_grace: bool,
+    /// Days charged (SIKU), or days the hourly charge was split into.
+    pub days: u64,
+    /// Hours charged, hourly only.
+    pub hours: u64,
+    pub night_hours: u64,
+    /// Days where the daily cap applied.
+    pub capped_days: u64,
+}
+
+fn amount<'de, D: Deserializer<'de>>(d: D) -> Result<i64, D::Error> {
+    optional_amount(d).map(Option::unwrap_or_default)
+}
+
+/// Laravel sends decimals as strings, e.g. "1500.00".
+fn optional_amount<'de, D: Deserializer<'de>>(d: D) -> Result<Option<i64>, D::Error> {
+    let value = serde_json::Value::deserialize(d)?;
+    let parsed = match &value {
+        serde_json::Value::Null => return Ok(None),
+        serde_json::Value::Number(n) => n.as_f64(),
+        serde_json::Value::String(s) => parse_amount(s),
+        _ => None,
+    };
+    parsed
+        .map(|a| Some(a.round() as i64))
+        .ok_or_else(|| serde::de::Error::custom(format!("invalid amount {}", value)))
+}
+
+/// Minutes after midnight for "HH:MM" or "HH:MM:SS".
+fn clock_minutes(s: &str) -> Result<u64, String> {
+    let mut parts = s.trim().split(':').map(|p| p.parse::<u64>().ok());
+    match (parts.next().flatten(), parts.next().flatten()) {
+        (Some(h), Some(m)) if h < 24 && m < 60 => Ok(h * 60 + m),
+        _ => Err(format!("Invalid time '{}' in night rate", s)),
+    }
+}
+
+fn local_day(t: u64, offset: i64) -> i64 {
+    (t as i64 + offset).div_euclid(DAY as i64)
+}
+
+fn local_minute(t: u64, offset: i64) -> u64 {
+    ((t as i64 + offset).rem_euclid(DAY as i64) / 60) as u64
+}
+
+fn in_window(minute: u64, start: u64, end: u64) -> bool {
+    if start <= end {
+        minute >= start && minute < end
+    } else {
+        minute >= start || minute < end
+    }
+}
+
+fn round(amount: i64, to: i64) -> Option<i64> {
+    if to <= 1 {
+        return Some(amount);
+    }
+    amount.checked_add(to / 2)?.checked_div(to)?.checked_mul(to)
+}
+
+fn type_key(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+impl TariffSet {
+    /// The rule for a vehicle type, or the catch-all rule.
+    pub fn rule_for(&self, vehicle_type: &str) -> Result<&TariffRule, String> {
+        let key = type_key(vehicle_type);
+        let catch_all = |r: &&TariffRule| matches!(r.vehicle_type.trim(), "" | "*");
+        self.rules
+            .iter()
+            .find(|r| !key.is_empty() && type_key(&r.vehicle_type) == key)
+            .or_else(|| self.rules.iter().find(catch_all))
+            .ok_or_else(|| format!("No tariff for vehicle type '{}'", vehicle_type))
+    }
+
+    /// The fee for a stay. Times are unix seconds.
+    pub fn calculate(&self, vehicle_type: &str, entry: u64, exit: u64) -> Result<Fee, String> {
+        if exit < entry {
+            return Err("Exit time is before entry".to_string());
+        }
+        if exit > LATEST_TIME {
+            return Err(format!("Exit time {} is out of range", exit));
+        }
+        let duration = exit - entry;
+        if duration > MAX_STAY {
+            return Err(format!("A stay of {} days is too long to charge at the booth", duration / DAY));
+        }
+        let rule = self.rule_for(vehicle_type)?;
+        let mut fee = Fee {
+            rule_id: rule.id,
+            vehicle_type: rule.vehicle_type.clone(),
+            charging: rule.charging,
+            duration_minutes: duration / 60,
+            ..Default::default()
+        };
+
+        if duration <= rule.grace_minutes as u64 * 60 {
+            fee.within_grace = true;
+            return Ok(fee);
+        }
+
+        let offset = self.utc_offset_minutes as i64 * 60;
+        match rule.charging {
+            Charging::Daily => {
+                fee.days = match rule.day_boundary {
+                    DayBoundary::Rolling => duration.div_ceil(DAY).max(1),
+                    DayBoundary::Midnight => (local_day(exit, offset) - local_day(entry, offset) + 1) as u64,
+                };
+                fee.amount = rule.day_rate.checked_mul(fee.days as i64).ok_or(TOO_LARGE)?;
+            }
+            Charging::Hourly => hourly(rule, entry, duration, offset, &mut fee)?,
+        }
+
+        fee.amount = round(fee.amount, rule.round_to).ok_or(TOO_LARGE)?;
+        Ok(fee)
+    }
+}
+
+/// Charges hour by hour, each hour at the rate for when it starts, and caps
+/// each day's total.
+fn hourly(rule: &TariffRule, entry: u64, duration: u64, offset: i64, fee: &mut Fee) -> Result<(), String> {
+    let night = match &rule.night {
+        Some(n) => Some((clock_minutes(&n.start)?, clock_minutes(&n.end)?, n.hourly_rate)),
+        None => None,
+    };
+    // Bounded by MAX_STAY and LATEST_TIME, but checked all the same
+    let start_of = |hour: u64| hour.checked_mul(HOUR).and_then(|s| entry.checked_add(s)).ok_or("Stay is out of range");
+    let day_of = |hour: u64| -> Result<i64, String> {
+        Ok(match rule.day_boundary {
+            DayBoundary::Rolling => hour as i64 / 24,
+            DayBoundary::Midnight => local_day(start_of(hour)?, offset),
+        })
+    };
+
+    fee.hours = duration.div_ceil(HOUR);
+    let mut day_total: i64 = 0;
+    for hour in 0..fee.hours {
+        let starts = start_of(hour)?;
+        let rate = match night {
+            _ if hour == 0 => rule.first_hour,
+            Some((start, end, rate)) if in_window(local_minute(starts, offset), start, end) => {
+                fee.night_hours += 1;
+                rate
+            }
+            _ => rule.subsequent_hour,
+        };
+        day_total = day_total.checked_add(rate).ok_or(TOO_LARGE)?;
+
+        let last = hour + 1 == fee.hours;
+        if last || day_of(hour + 1)? != day_of(hour)? {
+            fee.days += 1;
+            let charged = match rule.daily_cap {
+                Some(cap) if day_total > cap => {
+                    fee.capped_days += 1;
+                    cap
+                }
+                _ => day_total,
+            };
+            fee.amount = fee.amount.checked_add(charged).ok_or(TOO_LARGE)?;
+            day_total = 0;
+        }
+    }
+    Ok(())
+}
+
+/* ───────────── CACHE ───────────── */
+
+/// The rules last synced, for working out fees offline.
+pub fn cached(db: &Database) -> Result<TariffSet, String> {
+    let value = db
+        .get_config(CACHE_KEY)?
+        .ok_or("No tariffs synced yet; connect to the server once")?;
+    serde_json::from_value(value).map_err(|e| format!("Cached tariffs are unreadable: {}", e))
+}
+
+fn store(db: &Database, set: &TariffSet) -> Result<(), String> {
+    let value = serde_json::to_value(set).map_err(|e| format!("Failed to encode tariffs: {}", e))?;
+    db.set_config(CACHE_KEY, &value)
+}
+
+/// The fee for a parked session leaving at `exit_time`.
+pub fn quote(db: &Database, session: &ParkingSession, exit_time: u64) -> Result<Fee, String> {
+    let vehicle_type = session.vehicle_type.as_deref().unwrap_or_default();
+    cached(db)?.calculate(vehicle_type, session.entry_time, exit_time)
+}
+
+/// Reads the API's answer: the rules on their own, or a set with a version.
+fn parse_tariffs(body: &serde_json::Value) -> Result<TariffSet, String> {
+    let data = body.get("data").unwrap_or(body);
+    let set = match data {
+        serde_json::Value::Array(_) => TariffSet {
+            rules: serde_json::from_value(data.clone()).map_err(|e| format!("Invalid tariff rules: {}", e))?,
+            ..Default::default()
+        },
+        _ => serde_json::from_value(data.clone()).map_err(|e| format!("Invalid tariffs: {}", e))?,
+    };
+    if set.rules.is_empty() {
+        return Err("The server sent no tariff rules".to_string());
+    }
+    for rule in set.rules.iter().filter_map(|r| r.night.as_ref()) {
+        clock_minutes(&rule.start)?;
+        clock_minutes(&rule.end)?;
+    }
+    Ok(set)
+}
+
+/// Fetches the current rules and caches them. The cache is left alone if the
+/// API can't be reached.
+pub fn refresh(db: &Database, config: &SyncConfig) -> Result<TariffSet, String> {
+    let url = format!("{}{}", config.api_url.trim_end_matches('/'), config.tariffs_path);
+    let mut request = ureq::AgentBuilder::new()
+        .timeout(HTTP_TIMEOUT)
+        .build()
+        .get(&url)
+        .set("Accept", "application/json");
+    if !config.api_token.is_empty() {
+        request = request.set("Authorization", &format!("Bearer {}", config.api_token));
+    }
+
+    let body: serde_json::Value = request
+        .call()
+        .map_err(|e| format!("Tariff refresh failed: {}", e))?
+        .into_json()
+        .map_err(|e| format!("Invalid tariff response: {}", e))?;
+
+    let mut set = parse_tariffs(&body)?;
+    set.synced_at = Some(now_secs());
+    store(db, &set)?;
+    println!("[Rust] Cached {} tariff rules ({})", set.rules.len(), set.version.as_deref().unwrap_or("no version"));
+    Ok(set)
+}
+
+/// Background thread that keeps the cached rules current while syncing is on.
+pub fn start_worker(app: AppHandle) {
+    thread::spawn(move || loop {
+        let db = app.state::<Database>();
+        let config = app.state::<SyncQueue>().config(&db);
+        if config.enabled {
+            if let Err(e) = refresh(&db, &config) {
+                println!("[Rust] {}", e);
+            }
+        }
+        thread::sleep(REFRESH_INTERVAL);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const MINUTE: u64 = 60;
+    /// 2024-05-01 00:00 in Dar es Salaam (21:00 UTC the day before).
+    const MIDNIGHT: u64 = 1_714_510_800;
+
+    fn at(hour: u64, minute: u64) -> u64 {
+        MIDNIGHT + hour * HOUR + minute * MINUTE
+    }
+
+    fn tariffs() -> TariffSet {
+        parse_tariffs(&json!({
+            "data": {
+                "version": "2024-05",
+                "rules": [
+                    {
+                        "id": 1, "vehicle_type": "Car", "charging": "hourly",
+                        "first_hour": "1,000.00", "subsequent_hour": 500, "daily_cap": "5000",
+                        "grace_minutes": 15
+                    },
+                    {
+                        "id": 2, "vehicle_type": "Bus", "charging": "daily",
+                        "day_rate": 2000, "day_boundary": "midnight", "grace_minutes": 10
+                    },
+                    {
+                        "id": 3, "vehicle_type": "Lorry", "charging": "daily", "day_rate": 3000
+                    },
+                    {
+                        "id": 4, "vehicle_type": "Taxi", "charging": "hourly",
+                        "first_hour": 700, "subsequent_hour": 300, "daily_cap": 4000,
+                        "day_boundary": "midnight", "round_to": 500,
+                        "night": { "start": "18:00", "end": "06:00:00", "hourly_rate": 200 }
+                    },
+                    {
+                        "id": 5, "vehicle_type": "*", "charging": "hourly",
+                        "first_hour": 300, "subsequent_hour": 300
+                    }
+                ]
+            }
+        }))
+        .unwrap()
+    }
+
+    fn fee(vehicle_type: &str, entry: u64, exit: u64) -> Fee {
+        tariffs().calculate(vehicle_type, entry, exit).unwrap()
+    }
+
+    #[test]
+    fn grace_period_is_free_up_to_and_including_its_end() {
+        assert!(fee("Car", at(8, 0), at(8, 0)).within_grace);
+        assert_eq!(fee("Car", at(8, 0), at(8, 15)).amount, 0);
+        let just_over = fee("Car", at(8, 0), at(8, 15) + 1);
+        assert!(!just_over.within_grace);
+        assert_eq!((just_over.amount, just_over.hours), (1_000, 1));
+        // No grace at all: a second is an hour
+        assert_eq!(fee("Motorcycle", at(8, 0), at(8, 0) + 1).amount, 300);
+    }
+
+    #[test]
+    fn wild_times_are_refused_not_counted() {
+        let tariffs = tariffs();
+        assert!(tariffs.calculate("Car", at(8, 0), at(8, 0) + 10 * 365 * DAY).is_err());
+        assert!(tariffs.calculate("Car", 0, u64::MAX).is_err());
+        assert!(tariffs.calculate("Car", u64::MAX - 10, u64::MAX).is_err());
+        assert!(tariffs.calculate("Car", at(8, 0), at(8, 0) + 300 * DAY).is_ok());
+    }
+
+    #[test]
+    fn started_hours_are_charged_after_the_first() {
+        assert_eq!(fee("Car", at(8, 0), at(9, 0)).amount, 1_000);
+        assert_eq!(fee("Car", at(8, 0), at(9, 0) + 1).amount, 1_500);
+        let three = fee("Car", at(8, 0), at(10, 30));
+        assert_eq!((three.amount, three.hours, three.days), (2_000, 3, 1));
+    }
+
+    #[test]
+    fn daily_cap_applies_per_rolling_day() {
+        // 1,000 + 23 x 500 = 12,500 capped to 5,000
+        let day = fee("Car", at(8, 0), at(8, 0) + DAY);
+        assert_eq!((day.amount, day.capped_days, day.days), (5_000, 1, 1));
+        // Next day restarts at the subsequent rate, not the first hour
+        let day_and_two = fee("Car", at(8, 0), at(10, 0) + DAY);
+        assert_eq!((day_and_two.amount, day_and_two.days), (6_000, 2));
+        let three_days = fee("Car", at(8, 0), at(8, 0) + 3 * DAY);
+        assert_eq!((three_days.amount, three_days.capped_days), (15_000, 3));
+    }
+
+    #[test]
+    fn night_hours_use_the_night_rate_and_midnight_caps() {
+        // 16:00-20:00: first hour, one day hour, two night hours; 1,400 rounds to 1,500
+        let evening = fee("Taxi", at(16, 0), at(20, 0));
+        assert_eq!((evening.night_hours, evening.amount), (2, 1_500));
+        // 22:00-02:00 crosses midnight: two calendar days, night rate throughout
+        let overnight = fee("Taxi", at(22, 0), at(26, 0));
+        assert_eq!((overnight.days, overnight.night_hours), (2, 3));
+        // (700 + 200) + (200 + 200) = 1,300, rounds to 1,500
+        assert_eq!(overnight.amount, 1_500);
+        // A full calendar day comes to 6,500 before the cap
+        let long_day = fee("Taxi", at(0, 0), at(24, 0));
+        assert_eq!((long_day.amount, long_day.capped_days), (4_000, 1));
+    }
+
+    #[test]
+    fn siku_counts_calendar_days_or_started_days() {
+        // Bus: per calendar date, like the SIKU column on receipts
+        assert_eq!(fee("Bus", at(8, 15), at(17, 40)).days, 1);
+        assert_eq!(fee("Bus", at(23, 50), at(24, 10)).days, 2);
+        let week = fee("bus", at(8, 0), at(8, 0) + 6 * DAY);
+        assert_eq!((week.days, week.amount, week.charging), (7, 14_000, Charging::Daily));
+        assert_eq!(fee("Bus", at(23, 50), at(23, 55)).amount, 0);
+        // Lorry: per started 24 hours from entry
+        assert_eq!(fee("Lorry", at(23, 50), at(24, 10)).days, 1);
+        assert_eq!(fee("Lorry", at(8, 0), at(8, 0) + DAY).amount, 3_000);
+        assert_eq!(fee("Lorry", at(8, 0), at(8, 0) + DAY + 1).amount, 6_000);
+    }
+
+    #[test]
+    fn rounding_is_to_the_nearest_with_halves_up() {
+        assert_eq!(round(1_249, 500), Some(1_000));
+        assert_eq!(round(1_250, 500), Some(1_500));
+        assert_eq!(round(1_251, 100), Some(1_300));
+        assert_eq!(round(1_234, 1), Some(1_234));
+        assert_eq!(round(1_234, 0), Some(1_234));
+    }
+
+    #[test]
+    fn vehicle_types_match_loosely_and_fall_back() {
+        let set = tariffs();
+        assert_eq!(set.rule_for("  BUS ").unwrap().id, Some(2));
+        assert_eq!(set.rule_for("Pick Up").unwrap().id, Some(5));
+        assert_eq!(set.rule_for("").unwrap().id, Some(5));
+
+        let mut no_default = set.clone();
+        no_default.rules.retain(|r| r.vehicle_type != "*");
+        assert!(no_default.rule_for("Pick Up").is_err());
+        assert!(set.calculate("Car", at(9, 0), at(8, 0)).is_err());
+    }
+
+    #[test]
+    fn timezone_is_fixed_by_the_tariff_not_the_machine() {
+        let mut utc = tariffs();
+        utc.utc_offset_minutes = 0;
+        // 16:00-20:00 in Dar is 13:00-17:00 UTC: no night hours there
+        assert_eq!(utc.calculate("Taxi", at(16, 0), at(20, 0)).unwrap().night_hours, 0);
+        assert_eq!(fee("Taxi", at(16, 0), at(20, 0)).night_hours, 2);
+    }
+
+    #[test]
+    fn api_responses_are_validated() {
+        let bare = parse_tariffs(&json!([{ "vehicle_type": "Car", "first_hour": 1000 }])).unwrap();
+        assert_eq!((bare.rules.len(), bare.utc_offset_minutes), (1, 180));
+        assert!(parse_tariffs(&json!({ "data": [] })).is_err());
+        assert!(parse_tariffs(&json!([{ "first_hour": "free" }])).is_err());
+        let bad_night = json!([{ "night": { "start": "25:00", "end": "06:00", "hourly_rate": 1 } }]);
+        assert!(parse_tariffs(&bad_night).is_err());
+    }
+
+    #[test]
+    fn cached_rules_quote_a_parked_session() {
+        let db = Database::open_in_memory().unwrap();
+        assert!(cached(&db).is_err());
+        store(&db, &tariffs()).unwrap();
+
+        let session = db
+            .record_entry(crate::database::NewEntry {
+                plate_number: "T 1 AAA".to_string(),
+                vehicle_type: Some("Car".to_string()),
+                gate_id: None,
+                operator_name: None,
+                ticket_number: None,
+                entry_time: Some(at(8, 0)),
+            })
+            .unwrap();
+        assert_eq!(quote(&db, &session, at(10, 30)).unwrap().amount, 2_000);
+    }
+}
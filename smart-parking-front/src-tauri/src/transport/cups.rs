@@ -0,0 +1,157 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use super::{looks_thermal, DiscoveredPrinter, PrinterTransport};
+use crate::escpos::status::{PrinterStatus, StatusQuery};
+
+/// Raw job submitted to a CUPS queue with `lp -o raw`.
+///
+/// CUPS doesn't relay printer replies, so status comes from `lpstat`.
+pub struct CupsTransport {
+    queue: String,
+}
+
+impl CupsTransport {
+    pub fn new(queue: &str) -> Self {
+        Self {
+            queue: queue.to_string(),
+        }
+    }
+}
+
+impl PrinterTransport for CupsTransport {
+    fn write_all(&mut self, data: &[u8]) -> Result<(), String> {
+        let mut child = Command::new("lp")
+            .args(["-d", &self.queue, "-o", "raw", "-t", "Parking Receipt", "-s"])
+            .env("LC_ALL", "C")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to run lp: {}", e))?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(data)
+                .map_err(|e| format!("Failed to send job to {}: {}", self.queue, e))?;
+        }
+
+        let output = child
+            .wait_with_output()
+            .map_err(|e| format!("Failed to run lp: {}", e))?;
+
+        if output.status.success() {
+            Ok(())
+        } else {
+            let error = String::from_utf8_lossy(&output.stderr).to_string();
+            Err(format!("CUPS rejected job for {}: {}", self.queue, error.trim()))
+        }
+    }
+
+    fn status(&mut self, _query: StatusQuery) -> Result<Option<PrinterStatus>, String> {
+        let output = Command::new("lpstat")
+            .args(["-p", &self.queue])
+            .env("LC_ALL", "C")
+            .output()
+            .map_err(|e| format!("Failed to run lpstat: {}", e))?;
+
+        if !output.status.success() {
+            return Err(format!("CUPS queue {} not found", self.queue));
+        }
+
+        // "printer X is idle.  enabled since ..." or "printer X disabled since ..."
+        // followed by indented reason lines
+        let text = String::from_utf8_lossy(&output.stdout);
+        let mut lines = text.lines();
+        let online = lines.next().is_some_and(|l| !l.contains("disabled"));
+        let details = lines
+            .map(|l| l.trim().to_string())
+            .filter(|l| !l.is_empty())
+            .collect();
+
+        Ok(Some(PrinterStatus {
+            online,
+            details,
+            ..Default::default()
+        }))
+    }
+}
+
+fn lpstat(args: &[&str]) -> String {
+    Command::new("lpstat")
+        .args(args)
+        .env("LC_ALL", "C")
+        .output()
+        .ok()
+        .map(|o| String::from_utf8_lossy(&o.stdout).to_string())
+        .unwrap_or_default()
+}
+
+/// `printer-make-and-model` from `lpoptions -p queue`.
+fn make_and_model(queue: &str) -> String {
+    let output = Command::new("lpoptions")
+        .args(["-p", queue])
+        .env("LC_ALL", "C")
+        .output()
+        .map(|o| String::from_utf8_lossy(&o.stdout).to_string())
+        .unwrap_or_default();
+    parse_option(&output, "printer-make-and-model").unwrap_or_default()
+}
+
+/// Value of `key=value` or `key='quoted value'` in lpoptions output.
+fn parse_option(output: &str, key: &str) -> Option<String> {
+    let start = output.find(&format!("{}=", key))? + key.len() + 1;
+    let rest = &output[start..];
+
+    match rest.strip_prefix('\'') {
+        Some(quoted) => quoted.split('\'').next().map(|v| v.to_string()),
+        None => rest.split_whitespace().next().map(|v| v.to_string()),
+    }
+}
+
+/// CUPS queues with their device URI, default flag and enabled state.
+pub fn list_queues() -> Vec<DiscoveredPrinter> {
+    let default = lpstat(&["-d"])
+        .split_once(": ")
+        .map(|(_, name)| name.trim().to_string());
+    let states = lpstat(&["-p"]);
+
+    // "device for NAME: usb://EPSON/TM-T20II?serial=..."
+    lpstat(&["-v"])
+        .lines()
+        .filter_map(|l| l.strip_prefix("device for "))
+        .filter_map(|l| l.split_once(": "))
+        .map(|(name, uri)| {
+            let driver = make_and_model(name);
+            let online = states
+                .lines()
+                .find(|l| l.starts_with(&format!("printer {} ", name)))
+                .map(|l| !l.contains("disabled"));
+
+            DiscoveredPrinter {
+                is_default: default.as_deref() == Some(name),
+                online,
+                thermal: looks_thermal(&[name, &driver, uri]),
+                name: name.to_string(),
+                port: uri.trim().to_string(),
+                driver,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_quoted_and_bare_options() {
+        let output = "copies=1 device-uri=usb://EPSON/TM-T20II printer-make-and-model='EPSON TM-T20II Receipt' printer-type=2";
+        assert_eq!(
+            parse_option(output, "printer-make-and-model").as_deref(),
+            Some("EPSON TM-T20II Receipt")
+        );
+        assert_eq!(parse_option(output, "device-uri").as_deref(), Some("usb://EPSON/TM-T20II"));
+        assert_eq!(parse_option(output, "printer-info"), None);
+    }
+}
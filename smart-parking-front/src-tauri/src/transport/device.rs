@@ -0,0 +1,158 @@
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{looks_thermal, DiscoveredPrinter, PrinterTransport};
+
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Printer character device such as `/dev/usb/lp0` or `/dev/ttyUSB0`.
+///
+/// The device is opened non-blocking so a printer that stops accepting data
+/// (or never answers a status request) can't hang the command.
+pub struct DeviceTransport {
+    path: String,
+    file: File,
+}
+
+impl DeviceTransport {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK | libc::O_NOCTTY)
+            .open(path)
+            .or_else(|_| {
+                // Some usblp devices are write-only for the kiosk user
+                OpenOptions::new()
+                    .write(true)
+                    .custom_flags(libc::O_NONBLOCK | libc::O_NOCTTY)
+                    .open(path)
+            })
+            .map_err(|e| format!("Failed to open printer device {}: {}", path, e))?;
+
+        Ok(Self {
+            path: path.to_string(),
+            file,
+        })
+    }
+}
+
+impl PrinterTransport for DeviceTransport {
+    fn write_all(&mut self, data: &[u8]) -> Result<(), String> {
+        let deadline = Instant::now() + WRITE_TIMEOUT;
+        let mut remaining = data;
+
+        while !remaining.is_empty() {
+            match self.file.write(remaining) {
+                Ok(0) => return Err(format!("Printer device {} stopped accepting data", self.path)),
+                Ok(n) => remaining = &remaining[n..],
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    if Instant::now() >= deadline {
+                        return Err(format!(
+                            "Timed out writing to {} ({} of {} bytes sent)",
+                            self.path,
+                            data.len() - remaining.len(),
+                            data.len()
+                        ));
+                    }
+                    thread::sleep(POLL_INTERVAL);
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(format!("Failed to write to {}: {}", self.path, e)),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read(&mut self, buf: &mut [u8], timeout: Duration) -> Result<usize, String> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            match self.file.read(buf) {
+                Ok(n) if n > 0 => return Ok(n),
+                Ok(_) => {}
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted) => {}
+                // Write-only handle or a driver without read support
+                Err(_) => return Ok(0),
+            }
+
+            if Instant::now() >= deadline {
+                return Ok(0);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+/// MFG, MDL and CMD fields of an IEEE 1284 device ID, e.g.
+/// `MFG:EPSON;CMD:ESC/POS;MDL:TM-T20II;CLS:PRINTER;`.
+fn parse_device_id(id: &str) -> (String, String, String) {
+    let mut fields = (String::new(), String::new(), String::new());
+
+    for field in id.split(';') {
+        let Some((key, value)) = field.split_once(':') else {
+            continue;
+        };
+        let value = value.trim().to_string();
+        match key.trim() {
+            "MFG" | "MANUFACTURER" => fields.0 = value,
+            "MDL" | "MODEL" => fields.1 = value,
+            "CMD" | "COMMAND SET" => fields.2 = value,
+            _ => {}
+        }
+    }
+
+    fields
+}
+
+/// USB line printer devices exposed by the usblp driver, described by the
+/// device ID the printer reported when it was plugged in.
+pub fn list_devices() -> Vec<DiscoveredPrinter> {
+    let mut devices: Vec<String> = std::fs::read_dir("/dev/usb")
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .filter_map(|e| e.file_name().into_string().ok())
+                .filter(|n| n.starts_with("lp"))
+                .collect()
+        })
+        .unwrap_or_default();
+    devices.sort();
+
+    devices
+        .into_iter()
+        .map(|dev| {
+            let id = std::fs::read_to_string(format!("/sys/class/usbmisc/{}/device/ieee1284_id", dev))
+                .unwrap_or_default();
+            let (manufacturer, model, commands) = parse_device_id(&id);
+            let driver = format!("{} {}", manufacturer, model).trim().to_string();
+
+            DiscoveredPrinter {
+                name: format!("/dev/usb/{}", dev),
+                port: "usb".to_string(),
+                thermal: looks_thermal(&[&driver, &commands]),
+                driver,
+                is_default: false,
+                // The node only exists while the printer is connected and on
+                online: Some(true),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_ieee1284_device_id() {
+        let (mfg, mdl, cmd) = parse_device_id("MFG:EPSON;CMD:ESC/POS;MDL:TM-T20II;CLS:PRINTER;");
+        assert_eq!((mfg.as_str(), mdl.as_str(), cmd.as_str()), ("EPSON", "TM-T20II", "ESC/POS"));
+        assert!(looks_thermal(&[&cmd]));
+    }
+}
@@ -0,0 +1,202 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::escpos::status::{self, PrinterStatus, StatusQuery};
+use crate::escpos::PrinterProfile;
+
+#[cfg(unix)]
+mod cups;
+#[cfg(unix)]
+mod device;
+mod serial;
+#[cfg(windows)]
+mod spooler;
+mod tcp;
+
+/// How long to wait for each real-time status reply.
+const STATUS_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// A connection to one printer.
+pub trait PrinterTransport {
+    fn write_all(&mut self, data: &[u8]) -> Result<(), String>;
+
+    /// Reads up to `buf.len()` bytes sent back by the printer. Write-only
+    /// transports report nothing read.
+    fn read(&mut self, _buf: &mut [u8], _timeout: Duration) -> Result<usize, String> {
+        Ok(0)
+    }
+
+    /// `None` when the printer can't be asked or didn't answer.
+    fn status(&mut self, query: StatusQuery) -> Result<Option<PrinterStatus>, String> {
+        query_realtime(self, query)
+    }
+}
+
+/// Sends the DLE EOT / GS r requests one at a time and decodes the replies.
+pub fn query_realtime<T: PrinterTransport + ?Sized>(
+    transport: &mut T,
+    query: StatusQuery,
+) -> Result<Option<PrinterStatus>, String> {
+    let requests = query.requests();
+    if requests.is_empty() {
+        return Ok(None);
+    }
+
+    let mut replies = Vec::with_capacity(requests.len());
+    for request in requests {
+        transport.write_all(&request)?;
+
+        let mut reply = [0u8; 1];
+        if transport.read(&mut reply, STATUS_TIMEOUT)? == 0 {
+            return Ok(None);
+        }
+        replies.push(reply[0]);
+    }
+
+    Ok(Some(status::parse_status(query, &replies)))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PrintResult {
+    pub success: bool,
+    pub message: String,
+    pub bytes_written: usize,
+    /// Printer state after the job was handed over, when the transport can tell.
+    pub status: Option<PrinterStatus>,
+    /// Print queue job the bytes were sent from.
+    pub job_id: Option<String>,
+}
+
+/// Sends a rendered job. The printer is asked for its status first, and a
+/// printer that can't print (no paper, cover open, offline) gets nothing, so
+/// the caller sees a failure instead of a receipt that never came out.
+pub fn print_bytes(
+    printer_name: &str,
+    data: &[u8],
+    profile: &PrinterProfile,
+    message: &str,
+) -> Result<PrintResult, String> {
+    let mut printer = open(printer_name)?;
+
+    if let Some(status) = printer.status(profile.status_query)? {
+        if let Some(reason) = status.blocking_reason() {
+            return Err(reason);
+        }
+    }
+
+    printer.write_all(data)?;
+
+    let status = printer.status(profile.status_query).unwrap_or_else(|e| {
+        println!("[Rust] Status after printing unavailable: {}", e);
+        None
+    });
+
+    let message = match status.as_ref() {
+        Some(s) if s.paper_near_end => format!("{} (paper is running low)", message),
+        _ => message.to_string(),
+    };
+
+    Ok(PrintResult {
+        success: true,
+        message,
+        bytes_written: data.len(),
+        status,
+        job_id: None,
+    })
+}
+
+/// Opens the printer a request names: `tcp://host[:port]` for network
+/// printers, `serial://PORT?baud=..&flow=..` for serial ports, a `/dev/...`
+/// path for device files, otherwise a printer installed in the OS (Windows
+/// spooler or CUPS queue).
+pub fn open(printer_name: &str) -> Result<Box<dyn PrinterTransport>, String> {
+    if let Some(target) = printer_name.strip_prefix("tcp://") {
+        return Ok(Box::new(tcp::TcpTransport::connect(target)?));
+    }
+    if let Some(target) = printer_name.strip_prefix("serial://") {
+        return Ok(Box::new(serial::SerialTransport::open(target)?));
+    }
+
+    #[cfg(windows)]
+    {
+        Ok(Box::new(spooler::SpoolerTransport::open(printer_name)?))
+    }
+
+    #[cfg(unix)]
+    {
+        if printer_name.starts_with("/dev/") {
+            return Ok(Box::new(device::DeviceTransport::open(printer_name)?));
+        }
+
+        let queue = printer_name.strip_prefix("cups://").unwrap_or(printer_name);
+        Ok(Box::new(cups::CupsTransport::new(queue)))
+    }
+
+    #[cfg(not(any(windows, unix)))]
+    {
+        Err("Printing is not supported on this platform".to_string())
+    }
+}
+
+/// A printer found on this machine, in the form the printer picker shows it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscoveredPrinter {
+    /// Value to pass as `printer_name` when printing.
+    pub name: String,
+    pub port: String,
+    pub driver: String,
+    pub is_default: bool,
+    /// `None` when there's no way to tell without opening the port.
+    pub online: Option<bool>,
+    /// Name, driver or device ID suggest an ESC/POS receipt printer.
+    pub thermal: bool,
+}
+
+/// Guesses from names the receipt printers we've seen at booths go by.
+pub fn looks_thermal(texts: &[&str]) -> bool {
+    const HINTS: [&str; 16] = [
+        "esc/pos", "escpos", "thermal", "receipt", "pos-", "pos58", "pos80", "tm-t",
+        "tm-m", "xprinter", "xp-", "rongta", "gprinter", "80mm", "58mm", "text only",
+    ];
+
+    texts.iter().any(|t| {
+        let t = t.to_lowercase();
+        HINTS.iter().any(|h| t.contains(h))
+    })
+}
+
+/// Every printer this machine can reach: installed printers (Windows spooler,
+/// or printer devices and CUPS queues), then serial ports.
+pub fn discover_printers() -> Vec<DiscoveredPrinter> {
+    let mut printers = Vec::new();
+
+    #[cfg(windows)]
+    match spooler::list_printers() {
+        Ok(found) => printers.extend(found),
+        Err(e) => println!("[Rust] {}", e),
+    }
+
+    #[cfg(unix)]
+    {
+        printers.extend(device::list_devices());
+        printers.extend(cups::list_queues());
+    }
+
+    printers.extend(serial::list_ports());
+    printers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognises_receipt_printers_by_name_or_driver() {
+        assert!(looks_thermal(&["POS-80C", ""]));
+        assert!(looks_thermal(&["Front desk", "EPSON TM-T20II Receipt"]));
+        assert!(looks_thermal(&["XP-58", "Generic / Text Only"]));
+        assert!(!looks_thermal(&["HP LaserJet Pro M404", "HP Universal Printing PCL 6"]));
+        assert!(!looks_thermal(&["Microsoft Print to PDF", ""]));
+    }
+}
@@ -0,0 +1,118 @@
+use std::io::{ErrorKind, Read, Write};
+use std::time::Duration;
+
+use serialport::{FlowControl, SerialPort, SerialPortType};
+
+use super::{looks_thermal, DiscoveredPrinter, PrinterTransport};
+
+const DEFAULT_BAUD: u32 = 9600;
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Receipt printer on an RS-232 or USB-CDC port, addressed as
+/// `serial://COM3?baud=19200&flow=hardware`.
+///
+/// `flow` is `none` (default), `software` (XON/XOFF) or `hardware` (RTS/CTS).
+pub struct SerialTransport {
+    port_name: String,
+    port: Box<dyn SerialPort>,
+}
+
+impl SerialTransport {
+    pub fn open(target: &str) -> Result<Self, String> {
+        let (port_name, query) = target.split_once('?').unwrap_or((target, ""));
+
+        let mut baud = DEFAULT_BAUD;
+        let mut flow = FlowControl::None;
+
+        for param in query.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            match key {
+                "baud" => {
+                    baud = value
+                        .parse()
+                        .map_err(|_| format!("Invalid baud rate '{}'", value))?;
+                }
+                "flow" => {
+                    flow = match value {
+                        "none" => FlowControl::None,
+                        "software" | "xonxoff" => FlowControl::Software,
+                        "hardware" | "rtscts" => FlowControl::Hardware,
+                        _ => return Err(format!("Invalid flow control '{}'", value)),
+                    };
+                }
+                _ => return Err(format!("Unknown serial printer option '{}'", key)),
+            }
+        }
+
+        println!("[Rust] Opening serial printer {} at {} baud ({:?} flow control)", port_name, baud, flow);
+
+        let port = serialport::new(port_name, baud)
+            .flow_control(flow)
+            .timeout(WRITE_TIMEOUT)
+            .open()
+            .map_err(|e| format!("Failed to open {}: {}", port_name, e))?;
+
+        Ok(Self {
+            port_name: port_name.to_string(),
+            port,
+        })
+    }
+}
+
+impl PrinterTransport for SerialTransport {
+    fn write_all(&mut self, data: &[u8]) -> Result<(), String> {
+        self.port
+            .set_timeout(WRITE_TIMEOUT)
+            .map_err(|e| format!("Failed to configure {}: {}", self.port_name, e))?;
+
+        self.port
+            .write_all(data)
+            .and_then(|_| self.port.flush())
+            .map_err(|e| format!("Failed to write to {}: {}", self.port_name, e))
+    }
+
+    fn read(&mut self, buf: &mut [u8], timeout: Duration) -> Result<usize, String> {
+        self.port
+            .set_timeout(timeout)
+            .map_err(|e| format!("Failed to configure {}: {}", self.port_name, e))?;
+
+        match self.port.read(buf) {
+            Ok(n) => Ok(n),
+            Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => Ok(0),
+            Err(e) => Err(format!("Failed to read from {}: {}", self.port_name, e)),
+        }
+    }
+}
+
+/// Serial ports that could hold a printer. Whether anything is attached
+/// can't be known without talking to it, so `online` is left unknown.
+pub fn list_ports() -> Vec<DiscoveredPrinter> {
+    serialport::available_ports()
+        .map(|ports| {
+            ports
+                .into_iter()
+                .map(|p| {
+                    let driver = match &p.port_type {
+                        SerialPortType::UsbPort(usb) => [usb.manufacturer.as_deref(), usb.product.as_deref()]
+                            .iter()
+                            .flatten()
+                            .copied()
+                            .collect::<Vec<_>>()
+                            .join(" "),
+                        SerialPortType::BluetoothPort => "Bluetooth".to_string(),
+                        _ => "Serial".to_string(),
+                    };
+
+                    DiscoveredPrinter {
+                        name: format!("serial://{}", p.port_name),
+                        thermal: looks_thermal(&[&driver]),
+                        port: p.port_name,
+                        driver,
+                        is_default: false,
+                        online: None,
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
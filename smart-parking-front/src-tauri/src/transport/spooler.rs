@@ -0,0 +1,294 @@
+use std::ffi::{CStr, CString};
+use std::ptr;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use winapi::shared::ntdef::HANDLE;
+use winapi::um::winspool::*;
+
+use super::{looks_thermal, query_realtime, DiscoveredPrinter, PrinterTransport};
+use crate::escpos::status::{PrinterStatus, StatusQuery};
+
+/// RAW job on a printer installed in the Windows spooler.
+///
+/// The document is started on the first write and finished when the
+/// transport is dropped, so everything written goes out as one job.
+pub struct SpoolerTransport {
+    name: String,
+    handle: HANDLE,
+    doc_open: bool,
+}
+
+impl SpoolerTransport {
+    pub fn open(printer_name: &str) -> Result<Self, String> {
+        let name = CString::new(printer_name).map_err(|_| "Invalid printer name")?;
+        let mut handle: HANDLE = ptr::null_mut();
+
+        let mut defaults = PRINTER_DEFAULTSA {
+            pDataType: ptr::null_mut(),
+            pDevMode: ptr::null_mut(),
+            DesiredAccess: PRINTER_ACCESS_USE,
+        };
+
+        unsafe {
+            if OpenPrinterA(name.as_ptr() as *mut i8, &mut handle, &mut defaults) == 0 {
+                return Err(format!("Failed to open printer: {}", printer_name));
+            }
+        }
+
+        Ok(Self {
+            name: printer_name.to_string(),
+            handle,
+            doc_open: false,
+        })
+    }
+
+    fn start_doc(&mut self) -> Result<(), String> {
+        if self.doc_open {
+            return Ok(());
+        }
+
+        let doc_name = CString::new("Parking Receipt").unwrap();
+        let doc_type = CString::new("RAW").unwrap();
+
+        let mut info = DOC_INFO_1A {
+            pDocName: doc_name.as_ptr() as *mut i8,
+            pOutputFile: ptr::null_mut(),
+            pDatatype: doc_type.as_ptr() as *mut i8,
+        };
+
+        unsafe {
+            if StartDocPrinterA(self.handle, 1, &mut info as *mut _ as *mut u8) == 0 {
+                return Err("Failed to start print job".into());
+            }
+
+            if StartPagePrinter(self.handle) == 0 {
+                EndDocPrinter(self.handle);
+                return Err("Failed to start page".into());
+            }
+        }
+
+        self.doc_open = true;
+        Ok(())
+    }
+
+    /// What the spooler knows about the printer (PRINTER_INFO_2 status and
+    /// attributes), and whether its port is bidirectional. Available for
+    /// every driver.
+    fn spooler_status(&self) -> Result<(PrinterStatus, bool), String> {
+        unsafe {
+            let mut needed = 0;
+            GetPrinterA(self.handle, 2, ptr::null_mut(), 0, &mut needed);
+            if needed == 0 {
+                return Err(format!("Failed to read status of {}", self.name));
+            }
+
+            // u64 backing keeps the PRINTER_INFO_2A at the start aligned
+            let mut buffer = vec![0u64; (needed as usize).div_ceil(8)];
+            if GetPrinterA(self.handle, 2, buffer.as_mut_ptr() as *mut u8, needed, &mut needed) == 0 {
+                return Err(format!("Failed to read status of {}", self.name));
+            }
+
+            let info = &*(buffer.as_ptr() as *const PRINTER_INFO_2A);
+            let bidi = info.Attributes & PRINTER_ATTRIBUTE_ENABLE_BIDI != 0;
+            Ok((status_from_spooler(info.Status, info.Attributes), bidi))
+        }
+    }
+}
+
+fn status_from_spooler(flags: u32, attributes: u32) -> PrinterStatus {
+    let mut s = PrinterStatus {
+        online: flags & (PRINTER_STATUS_OFFLINE | PRINTER_STATUS_NOT_AVAILABLE) == 0
+            && attributes & PRINTER_ATTRIBUTE_WORK_OFFLINE == 0,
+        paper_out: flags & PRINTER_STATUS_PAPER_OUT != 0,
+        cover_open: flags & PRINTER_STATUS_DOOR_OPEN != 0,
+        error: flags & PRINTER_STATUS_ERROR != 0,
+        ..Default::default()
+    };
+
+    let causes = [
+        (PRINTER_STATUS_PAPER_JAM, "paper jam"),
+        (PRINTER_STATUS_PAPER_PROBLEM, "paper problem"),
+        (PRINTER_STATUS_USER_INTERVENTION, "needs operator attention"),
+    ];
+    for (flag, cause) in causes {
+        if flags & flag != 0 {
+            s.error = true;
+            s.details.push(cause.to_string());
+        }
+    }
+
+    s
+}
+
+/// Combines the spooler's and the printer's view; a problem in either wins.
+fn merge(status: &mut PrinterStatus, realtime: &PrinterStatus) {
+    status.online &= realtime.online;
+    status.paper_near_end |= realtime.paper_near_end;
+    status.paper_out |= realtime.paper_out;
+    status.cover_open |= realtime.cover_open;
+    status.error |= realtime.error;
+    for d in &realtime.details {
+        if !status.details.contains(d) {
+            status.details.push(d.clone());
+        }
+    }
+}
+
+impl PrinterTransport for SpoolerTransport {
+    fn write_all(&mut self, data: &[u8]) -> Result<(), String> {
+        self.start_doc()?;
+
+        let mut written = 0;
+        let ok = unsafe {
+            WritePrinter(
+                self.handle,
+                data.as_ptr() as *mut _,
+                data.len() as u32,
+                &mut written,
+            )
+        };
+
+        if ok == 0 {
+            return Err(format!("Failed to write to printer: {}", self.name));
+        }
+        if written as usize != data.len() {
+            return Err(format!(
+                "Printer {} accepted only {} of {} bytes",
+                self.name,
+                written,
+                data.len()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// ReadPrinter only returns data on ports with bidirectional support
+    /// enabled; everywhere else it fails and we report nothing read. It can
+    /// block until the port answers, so it runs on its own thread and a late
+    /// reply counts as nothing read.
+    fn read(&mut self, buf: &mut [u8], timeout: Duration) -> Result<usize, String> {
+        let handle = self.handle as usize;
+        let len = buf.len();
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mut data = vec![0u8; len];
+            let mut read = 0;
+            let ok = unsafe { ReadPrinter(handle as HANDLE, data.as_mut_ptr() as *mut _, len as u32, &mut read) };
+            data.truncate(if ok == 0 { 0 } else { read as usize });
+            let _ = tx.send(data);
+        });
+
+        match rx.recv_timeout(timeout) {
+            Ok(data) => {
+                buf[..data.len()].copy_from_slice(&data);
+                Ok(data.len())
+            }
+            Err(_) => Ok(0),
+        }
+    }
+
+    /// The spooler's status, plus the printer's own answer when a job is
+    /// already open on a bidirectional port. Status requests are only ever
+    /// sent inside a job we are printing: each write starts a RAW document,
+    /// and a status check must never put an empty job in the queue.
+    fn status(&mut self, query: StatusQuery) -> Result<Option<PrinterStatus>, String> {
+        let (mut status, bidi) = self.spooler_status()?;
+        if bidi && self.doc_open {
+            if let Some(realtime) = query_realtime(self, query)? {
+                merge(&mut status, &realtime);
+            }
+        }
+        Ok(Some(status))
+    }
+}
+
+impl Drop for SpoolerTransport {
+    fn drop(&mut self) {
+        unsafe {
+            if self.doc_open {
+                EndPagePrinter(self.handle);
+                EndDocPrinter(self.handle);
+            }
+            ClosePrinter(self.handle);
+        }
+    }
+}
+
+/// Copies a string out of a spooler buffer. The spooler owns that memory, so
+/// it's only ever borrowed, never freed or taken over.
+unsafe fn copy_str(p: *const i8) -> String {
+    if p.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(p).to_string_lossy().into_owned()
+    }
+}
+
+fn default_printer() -> Option<String> {
+    unsafe {
+        let mut len = 0;
+        GetDefaultPrinterA(ptr::null_mut(), &mut len);
+        if len == 0 {
+            return None;
+        }
+
+        let mut buffer = vec![0u8; len as usize];
+        if GetDefaultPrinterA(buffer.as_mut_ptr() as *mut i8, &mut len) == 0 {
+            return None;
+        }
+        Some(copy_str(buffer.as_ptr() as *const i8))
+    }
+}
+
+/// Installed printers from EnumPrinters level 2.
+pub fn list_printers() -> Result<Vec<DiscoveredPrinter>, String> {
+    let flags = PRINTER_ENUM_LOCAL | PRINTER_ENUM_CONNECTIONS;
+    let default = default_printer();
+
+    unsafe {
+        let mut needed = 0;
+        let mut returned = 0;
+        EnumPrintersA(flags, ptr::null_mut(), 2, ptr::null_mut(), 0, &mut needed, &mut returned);
+        if needed == 0 {
+            return Ok(vec![]);
+        }
+
+        // u64 backing keeps the PRINTER_INFO_2A array aligned
+        let mut buffer = vec![0u64; (needed as usize).div_ceil(8)];
+        if EnumPrintersA(
+            flags,
+            ptr::null_mut(),
+            2,
+            buffer.as_mut_ptr() as *mut u8,
+            needed,
+            &mut needed,
+            &mut returned,
+        ) == 0
+        {
+            return Err("Failed to enumerate printers".to_string());
+        }
+
+        let infos = std::slice::from_raw_parts(buffer.as_ptr() as *const PRINTER_INFO_2A, returned as usize);
+        Ok(infos
+            .iter()
+            .map(|info| {
+                let name = copy_str(info.pPrinterName);
+                let port = copy_str(info.pPortName);
+                let driver = copy_str(info.pDriverName);
+
+                DiscoveredPrinter {
+                    is_default: default.as_deref() == Some(name.as_str()),
+                    online: Some(status_from_spooler(info.Status, info.Attributes).online),
+                    thermal: looks_thermal(&[&name, &driver]),
+                    name,
+                    port,
+                    driver,
+                }
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,125 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use super::PrinterTransport;
+
+pub const DEFAULT_PORT: u16 = 9100;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Raw socket printer (JetDirect / port 9100), addressed as `tcp://host[:port]`.
+pub struct TcpTransport {
+    addr: String,
+    stream: TcpStream,
+}
+
+impl TcpTransport {
+    pub fn connect(target: &str) -> Result<Self, String> {
+        let addr = if target.contains(':') {
+            target.to_string()
+        } else {
+            format!("{}:{}", target, DEFAULT_PORT)
+        };
+
+        let socket_addr = addr
+            .to_socket_addrs()
+            .map_err(|e| format!("Invalid printer address {}: {}", addr, e))?
+            .next()
+            .ok_or_else(|| format!("Printer address {} did not resolve", addr))?;
+
+        let stream = TcpStream::connect_timeout(&socket_addr, CONNECT_TIMEOUT)
+            .map_err(|e| format!("Failed to connect to printer {}: {}", addr, e))?;
+        stream
+            .set_write_timeout(Some(WRITE_TIMEOUT))
+            .map_err(|e| format!("Failed to configure {}: {}", addr, e))?;
+        let _ = stream.set_nodelay(true);
+
+        Ok(Self { addr, stream })
+    }
+}
+
+impl PrinterTransport for TcpTransport {
+    fn write_all(&mut self, data: &[u8]) -> Result<(), String> {
+        self.stream
+            .write_all(data)
+            .and_then(|_| self.stream.flush())
+            .map_err(|e| format!("Failed to write to printer {}: {}", self.addr, e))
+    }
+
+    fn read(&mut self, buf: &mut [u8], timeout: Duration) -> Result<usize, String> {
+        self.stream
+            .set_read_timeout(Some(timeout))
+            .map_err(|e| format!("Failed to configure {}: {}", self.addr, e))?;
+
+        match self.stream.read(buf) {
+            Ok(n) => Ok(n),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Ok(0),
+            Err(e) => Err(format!("Failed to read from printer {}: {}", self.addr, e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::escpos::status::StatusQuery;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn sends_job_bytes_to_listener() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let server = thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut received = Vec::new();
+            conn.read_to_end(&mut received).unwrap();
+            received
+        });
+
+        let mut printer = TcpTransport::connect(&addr).unwrap();
+        printer.write_all(b"\x1b@hello\n").unwrap();
+        drop(printer);
+
+        assert_eq!(server.join().unwrap(), b"\x1b@hello\n");
+    }
+
+    #[test]
+    fn reads_back_realtime_status() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        // Answers DLE EOT 1-4 like a printer with the cover open and paper low
+        thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let replies = [0x12, 0x16, 0x12, 0x1E];
+            for reply in replies {
+                let mut request = [0u8; 3];
+                conn.read_exact(&mut request).unwrap();
+                conn.write_all(&[reply]).unwrap();
+            }
+        });
+
+        let mut printer = TcpTransport::connect(&addr).unwrap();
+        let status = printer.status(StatusQuery::DleEot).unwrap().unwrap();
+
+        assert!(status.online);
+        assert!(status.cover_open);
+        assert!(status.paper_near_end);
+        assert!(!status.paper_out);
+    }
+
+    #[test]
+    fn silent_printer_has_no_status() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || listener.accept().unwrap());
+
+        let mut printer = TcpTransport::connect(&addr).unwrap();
+        assert_eq!(printer.status(StatusQuery::DleEot).unwrap(), None);
+        drop(server.join());
+    }
+}
@@ -0,0 +1,90 @@
+//! Small helpers shared across the desktop's modules.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::{Local, NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+
+/// Reads a JSON file, or the default before it has ever been saved. A file
+/// that won't parse is an error, never an empty start: it is moved aside so
+/// the next save can't overwrite what was in it.
+pub(crate) fn read_json<T: for<'de> Deserialize<'de> + Default>(path: &Path) -> Result<T, String> {
+    let json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(T::default()),
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
+
+    serde_json::from_str(&json).map_err(|e| {
+        format!("{} is corrupt ({}); {}", path.display(), e, set_aside(path))
+    })
+}
+
+/// Renames an unreadable file to `<name>.corrupt-<secs>`, and says where it went.
+pub(crate) fn set_aside(path: &Path) -> String {
+    let mut aside = path.as_os_str().to_owned();
+    aside.push(format!(".corrupt-{}", now_secs()));
+    match fs::rename(path, &aside) {
+        Ok(()) => format!("kept as {}", Path::new(&aside).display()),
+        Err(e) => format!("could not move it aside: {}", e),
+    }
+}
+
+pub(crate) fn write_json<T: Serialize>(path: &PathBuf, value: &T) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(value).map_err(|e| format!("Failed to serialize {}: {}", path.display(), e))?;
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, json)
+        .and_then(|_| fs::rename(&tmp, path))
+        .map_err(|e| format!("Failed to save {}: {}", path.display(), e))
+}
+
+/// Loads a store from a fresh, empty data directory named after the test.
+#[cfg(test)]
+pub(crate) fn test_store<S>(name: &str, load: impl FnOnce(PathBuf) -> Result<S, String>) -> S {
+    let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    load(dir).unwrap()
+}
+
+/// A copy of `name` that `set_aside` moved out of the way, if there is one.
+pub(crate) fn corrupt_copy(dir: &Path, name: &str) -> Option<PathBuf> {
+    let prefix = format!("{}.corrupt-", name);
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .find(|e| e.file_name().to_string_lossy().starts_with(&prefix))
+        .map(|e| e.path())
+}
+
+pub(crate) fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Start of a local day as unix seconds.
+pub(crate) fn local_day_start(date: &str) -> Result<u64, String> {
+    let day = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| format!("Invalid date '{}', expected YYYY-MM-DD", date))?;
+    let start = day.and_hms_opt(0, 0, 0).unwrap();
+
+    Local
+        .from_local_datetime(&start)
+        .earliest()
+        .map(|t| t.timestamp().max(0) as u64)
+        .ok_or_else(|| format!("Invalid local date '{}'", date))
+}
+
+/// Plates are typed with and without spaces ("T 123 ABC", "t123abc").
+pub(crate) fn normalize_plate(plate: &str) -> String {
+    plate
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_uppercase())
+        .collect()
+}